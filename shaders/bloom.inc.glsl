@@ -0,0 +1,49 @@
+#extension GL_EXT_nonuniform_qualifier : require
+
+// The draw image, from the resource table's storage images
+layout (rgba16f, set = 0, binding = 0) uniform image2D images[];
+// The whole bloom chain, sampled one mip level at a time
+layout (set = 0, binding = 1) uniform sampler2D textures[];
+// Single mip views of the bloom chain
+layout (rgba16f, set = 0, binding = 2) uniform image2D mipViews[];
+
+// Matches BloomPushConstants in src/bloom.rs
+layout (push_constant) uniform constants {
+    uint drawImage;
+    uint chainTexture;
+    // Storage view of the mip level written
+    uint targetView;
+    // Mip level of the chain read from
+    float sourceLevel;
+    uint levelCount;
+    float threshold;
+    float knee;
+    float radius;
+    float intensity;
+} pushConstants;
+
+vec3 sampleChain(vec2 uv)
+{
+    return textureLod(textures[nonuniformEXT(pushConstants.chainTexture)], uv,
+        pushConstants.sourceLevel).rgb;
+}
+
+// Texel size of the mip level read from
+vec2 sourceTexelSize()
+{
+    ivec2 size = textureSize(textures[nonuniformEXT(pushConstants.chainTexture)],
+        int(pushConstants.sourceLevel));
+    return 1.0f / vec2(size);
+}
+
+// 3x3 tent filter around uv, `radius` source texels wide
+vec3 sampleTent(vec2 uv)
+{
+    vec2 offset = sourceTexelSize() * pushConstants.radius;
+    vec3 sum = sampleChain(uv) * 4.0f;
+    sum += (sampleChain(uv + vec2(-offset.x, 0.0f)) + sampleChain(uv + vec2(offset.x, 0.0f))
+        + sampleChain(uv + vec2(0.0f, -offset.y)) + sampleChain(uv + vec2(0.0f, offset.y))) * 2.0f;
+    sum += sampleChain(uv - offset) + sampleChain(uv + offset)
+        + sampleChain(uv + vec2(-offset.x, offset.y)) + sampleChain(uv + vec2(offset.x, -offset.y));
+    return sum / 16.0f;
+}
@@ -0,0 +1,13 @@
+#extension GL_EXT_buffer_reference : require
+
+// Matches CameraUniforms in src/camera.rs
+layout (buffer_reference, std430) readonly buffer CameraBuffer {
+    mat4 view;
+    mat4 projection;
+    mat4 viewProjection;
+    mat4 inverseProjection;
+    // viewProjection without the TAA jitter
+    mat4 unjitteredViewProjection;
+    mat4 previousViewProjection;
+    vec4 position;
+};
@@ -0,0 +1,19 @@
+#extension GL_EXT_buffer_reference : require
+
+// Matches HISTOGRAM_BINS in src/tonemap.rs. Bin 0 holds black pixels, the others split the log2
+// luminance range evenly
+#define HISTOGRAM_BINS 256
+
+layout (buffer_reference, std430) buffer HistogramBuffer {
+    uint bins[HISTOGRAM_BINS];
+};
+
+// Scene luminance the exposure has adapted to, persists across frames
+layout (buffer_reference, std430) buffer ExposureBuffer {
+    float adaptedLuminance;
+};
+
+float luminance(vec3 color)
+{
+    return dot(color, vec3(0.2126f, 0.7152f, 0.0722f));
+}
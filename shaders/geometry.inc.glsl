@@ -0,0 +1,19 @@
+#extension GL_EXT_buffer_reference : require
+
+#include "camera.inc.glsl"
+#include "mesh.inc.glsl"
+#include "material.inc.glsl"
+#include "lighting.inc.glsl"
+#include "shadow.inc.glsl"
+
+// Matches GeometryPushConstants in src/main.rs
+layout (push_constant) uniform constants {
+    CameraBuffer camera;
+    VertexBuffer vertexBuffer;
+    InstanceBuffer instanceBuffer;
+    MaterialBuffer materialBuffer;
+    LightBuffer lightBuffer;
+    ClusterBuffer clusterBuffer;
+    ShadowBuffer shadowBuffer;
+    uint materialIndex;
+} pushConstants;
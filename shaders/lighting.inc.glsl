@@ -0,0 +1,74 @@
+#extension GL_EXT_buffer_reference : require
+
+// Matches the LIGHT_* constants in src/light.rs
+const uint LIGHT_DIRECTIONAL = 0;
+const uint LIGHT_POINT = 1;
+const uint LIGHT_SPOT = 2;
+
+// Matches LightData in src/light.rs
+struct Light {
+    vec3 position;
+    float range;
+    vec3 direction;
+    uint kind;
+    vec3 color;
+    float intensity;
+    float spotScale;
+    float spotOffset;
+    vec2 padding;
+};
+
+// Matches LightingHeader in src/light.rs, followed by the lights. Directional lights come first
+layout (buffer_reference, std430) readonly buffer LightBuffer {
+    uvec4 clusterGrid; // xyz: cluster counts, w: cluster stride
+    vec2 screenSize;
+    float near;
+    float far;
+    uint lightCount;
+    uint directionalCount;
+    uvec2 padding;
+    Light lights[];
+};
+
+// For every cluster its light count followed by the indices of its lights, clusterGrid.w uints each
+layout (buffer_reference, std430) buffer ClusterBuffer {
+    uint data[];
+};
+
+// Depth slice of a view space depth, slices are spaced exponentially between near and far
+uint clusterSlice(LightBuffer lightBuffer, float viewDepth)
+{
+    float slices = float(lightBuffer.clusterGrid.z);
+    float depthRatio = lightBuffer.far / lightBuffer.near;
+    float slice = log(viewDepth / lightBuffer.near) * slices / log(depthRatio);
+    return uint(clamp(slice, 0.0f, slices - 1.0f));
+}
+
+uint clusterIndex(uvec3 cluster, uvec3 grid)
+{
+    return cluster.x + cluster.y * grid.x + cluster.z * grid.x * grid.y;
+}
+
+// Direction towards the light and the radiance arriving from it at `position`
+void lightIncidence(Light light, vec3 position, out vec3 lightDirection, out vec3 radiance)
+{
+    if (light.kind == LIGHT_DIRECTIONAL) {
+        lightDirection = -light.direction;
+        radiance = light.color * light.intensity;
+        return;
+    }
+    vec3 toLight = light.position - position;
+    float distanceSquared = max(dot(toLight, toLight), 1e-4f);
+    lightDirection = toLight * inversesqrt(distanceSquared);
+    // Inverse square falloff windowed to reach zero at the range, as recommended by
+    // KHR_lights_punctual
+    float ratio = distanceSquared / (light.range * light.range);
+    float window = clamp(1.0f - ratio * ratio, 0.0f, 1.0f);
+    float attenuation = window * window / distanceSquared;
+    if (light.kind == LIGHT_SPOT) {
+        float cd = dot(light.direction, -lightDirection);
+        float angular = clamp(cd * light.spotScale + light.spotOffset, 0.0f, 1.0f);
+        attenuation *= angular * angular;
+    }
+    radiance = light.color * light.intensity * attenuation;
+}
@@ -0,0 +1,120 @@
+#version 460
+
+#include "geometry.inc.glsl"
+#include "pbr.inc.glsl"
+
+layout (location = 0) in vec3 inWorldPosition;
+layout (location = 1) in vec3 inNormal;
+layout (location = 2) in vec2 inUV;
+layout (location = 3) in vec4 inColor;
+layout (location = 4) in vec4 inCurrentPosition;
+layout (location = 5) in vec4 inPreviousPosition;
+
+layout (location = 0) out vec4 outFragColor;
+// Screen-space motion since the previous frame in UV units, read by shaders/taa.comp
+layout (location = 1) out vec2 outVelocity;
+
+// Set when the pipeline turns alpha into sample coverage, see create_lit_pipeline in src/main.rs
+layout (constant_id = 0) const bool ALPHA_TO_COVERAGE = false;
+
+// Stand-in for indirect lighting
+const vec3 AMBIENT_RADIANCE = vec3(0.03f);
+
+// Perturb `normal` with a tangent space normal map sample, building the tangent frame from
+// screen-space derivatives so meshes need no tangents
+vec3 applyNormalMap(vec3 normal, vec3 mapNormal, vec3 position, vec2 uv)
+{
+    vec3 dp1 = dFdx(position);
+    vec3 dp2 = dFdy(position);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+    vec3 dp2perp = cross(dp2, normal);
+    vec3 dp1perp = cross(normal, dp1);
+    vec3 T = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 B = dp2perp * duv1.y + dp1perp * duv2.y;
+    float invMax = inversesqrt(max(dot(T, T), dot(B, B)));
+    mat3 TBN = mat3(T * invMax, B * invMax, normal);
+    return normalize(TBN * mapNormal);
+}
+
+void main()
+{
+    vec2 currentUV = inCurrentPosition.xy / inCurrentPosition.w * 0.5f;
+    vec2 previousUV = inPreviousPosition.xy / inPreviousPosition.w * 0.5f;
+    outVelocity = currentUV - previousUV;
+
+    Material material = pushConstants.materialBuffer.materials[pushConstants.materialIndex];
+
+    vec4 baseColor = material.baseColorFactor * inColor
+        * sampleMaterialTexture(material.baseColorTexture, inUV, vec4(1.0f));
+    float alpha = 1.0f;
+    if (ALPHA_TO_COVERAGE && material.alphaCutoff > 0.0f) {
+        // Sharpen alpha to a ramp about a pixel wide around the cutoff, so edges are antialiased
+        // without turning translucent
+        alpha = (baseColor.a - material.alphaCutoff) / max(fwidth(baseColor.a), 1e-4f) + 0.5f;
+        alpha = clamp(alpha, 0.0f, 1.0f);
+    } else if (baseColor.a < material.alphaCutoff) {
+        discard;
+    }
+    if (material.unlit != 0u) {
+        outFragColor = vec4(baseColor.rgb, alpha);
+        return;
+    }
+    vec4 metallicRoughness =
+        sampleMaterialTexture(material.metallicRoughnessTexture, inUV, vec4(1.0f));
+
+    vec3 normal = normalize(inNormal);
+    if (!gl_FrontFacing) {
+        normal = -normal;
+    }
+    if (material.normalTexture != NO_TEXTURE) {
+        vec3 mapNormal = texture(textures[nonuniformEXT(material.normalTexture)], inUV).xyz;
+        mapNormal = mapNormal * 2.0f - 1.0f;
+        mapNormal.xy *= material.normalScale;
+        normal = applyNormalMap(normal, normalize(mapNormal), inWorldPosition, inUV);
+    }
+
+    SurfaceData surface;
+    surface.baseColor = baseColor.rgb;
+    surface.metallic = clamp(material.metallicFactor * metallicRoughness.b, 0.0f, 1.0f);
+    // Very low roughness makes highlights alias, so clamp it
+    surface.roughness = clamp(material.roughnessFactor * metallicRoughness.g, 0.045f, 1.0f);
+    surface.normal = normal;
+    surface.viewDirection = normalize(pushConstants.camera.position.xyz - inWorldPosition);
+
+    LightBuffer lightBuffer = pushConstants.lightBuffer;
+    float viewDepth = -(pushConstants.camera.view * vec4(inWorldPosition, 1.0f)).z;
+    vec3 color = vec3(0.0f);
+    vec3 lightDirection;
+    vec3 radiance;
+    for (uint i = 0u; i < lightBuffer.directionalCount; i++) {
+        lightIncidence(lightBuffer.lights[i], inWorldPosition, lightDirection, radiance);
+        // Only the first directional light casts shadows
+        if (i == 0u) {
+            ShadowBuffer shadow = pushConstants.shadowBuffer;
+            radiance *= sampleShadow(shadow, inWorldPosition, normal, viewDepth);
+        }
+        color += evaluateBRDF(surface, lightDirection, radiance);
+    }
+
+    // Local lights come from the cluster this fragment falls in
+    uvec3 grid = lightBuffer.clusterGrid.xyz;
+    uvec2 tile = uvec2(gl_FragCoord.xy / lightBuffer.screenSize * vec2(grid.xy));
+    uvec3 cluster = uvec3(min(tile, grid.xy - 1u), clusterSlice(lightBuffer, viewDepth));
+    uint base = clusterIndex(cluster, grid) * lightBuffer.clusterGrid.w;
+    uint clusterLightCount = pushConstants.clusterBuffer.data[base];
+    for (uint i = 0u; i < clusterLightCount; i++) {
+        uint lightIndex = pushConstants.clusterBuffer.data[base + 1u + i];
+        lightIncidence(lightBuffer.lights[lightIndex], inWorldPosition, lightDirection, radiance);
+        color += evaluateBRDF(surface, lightDirection, radiance);
+    }
+
+    float occlusion = sampleMaterialTexture(material.occlusionTexture, inUV, vec4(1.0f)).r;
+    occlusion = mix(1.0f, occlusion, material.occlusionStrength);
+    color += AMBIENT_RADIANCE * baseColor.rgb * occlusion;
+
+    vec3 emissive = sampleMaterialTexture(material.emissiveTexture, inUV, vec4(1.0f)).rgb;
+    color += material.emissiveFactor * emissive;
+
+    outFragColor = vec4(color, alpha);
+}
@@ -0,0 +1,27 @@
+#version 460
+
+#include "geometry.inc.glsl"
+
+layout (location = 0) out vec3 outWorldPosition;
+layout (location = 1) out vec3 outNormal;
+layout (location = 2) out vec2 outUV;
+layout (location = 3) out vec4 outColor;
+// Unjittered clip positions of this and the previous frame, for the velocity output
+layout (location = 4) out vec4 outCurrentPosition;
+layout (location = 5) out vec4 outPreviousPosition;
+
+void main()
+{
+    Vertex v = pushConstants.vertexBuffer.vertices[gl_VertexIndex];
+    Instance instance = pushConstants.instanceBuffer.instances[gl_InstanceIndex];
+
+    vec4 worldPosition = instance.model * vec4(v.position, 1.0f);
+    gl_Position = pushConstants.camera.viewProjection * worldPosition;
+    outWorldPosition = worldPosition.xyz;
+    outNormal = mat3(instance.normal) * v.normal;
+    outUV = vec2(v.uvX, v.uvY);
+    outColor = v.color;
+    outCurrentPosition = pushConstants.camera.unjitteredViewProjection * worldPosition;
+    vec4 previousWorldPosition = instance.previousModel * vec4(v.position, 1.0f);
+    outPreviousPosition = pushConstants.camera.previousViewProjection * previousWorldPosition;
+}
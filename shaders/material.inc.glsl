@@ -0,0 +1,39 @@
+#extension GL_EXT_buffer_reference : require
+#extension GL_EXT_nonuniform_qualifier : require
+
+// Matches NO_TEXTURE in src/material.rs
+const uint NO_TEXTURE = 0xFFFFFFFFu;
+
+// Matches MaterialData in src/material.rs
+struct Material {
+    vec4 baseColorFactor;
+    vec3 emissiveFactor;
+    float metallicFactor;
+    float roughnessFactor;
+    float normalScale;
+    float occlusionStrength;
+    float alphaCutoff;
+    uint baseColorTexture;
+    uint metallicRoughnessTexture;
+    uint normalTexture;
+    uint occlusionTexture;
+    uint emissiveTexture;
+    // Non-zero to output the base color without lighting
+    uint unlit;
+};
+
+layout (buffer_reference, std430) readonly buffer MaterialBuffer {
+    Material materials[];
+};
+
+// Resource table textures, see TEXTURE_BINDING in src/lv/descriptors/resource_table.rs
+layout (set = 0, binding = 1) uniform sampler2D textures[];
+
+// Sample `texture` or return `fallback` if the material has none in that slot
+vec4 sampleMaterialTexture(uint textureIndex, vec2 uv, vec4 fallback)
+{
+    if (textureIndex == NO_TEXTURE) {
+        return fallback;
+    }
+    return texture(textures[nonuniformEXT(textureIndex)], uv);
+}
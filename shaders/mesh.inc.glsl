@@ -0,0 +1,25 @@
+#extension GL_EXT_buffer_reference : require
+
+struct Vertex {
+    vec3 position;
+    float uvX;
+    vec3 normal;
+    float uvY;
+    vec4 color;
+};
+
+// Matches Vertex in src/mesh.rs
+layout (buffer_reference, std430) readonly buffer VertexBuffer {
+    Vertex vertices[];
+};
+
+struct Instance {
+    mat4 model;
+    mat4 normal;
+    mat4 previousModel;
+};
+
+// Matches InstanceData in src/scene.rs
+layout (buffer_reference, std430) readonly buffer InstanceBuffer {
+    Instance instances[];
+};
@@ -0,0 +1,18 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+layout (rgba16f, set = 0, binding = 0) uniform readonly image2D images[];
+
+// Matches MsaaLoadPushConstants in src/msaa.rs
+layout (push_constant) uniform constants {
+    uint image;
+} pushConstants;
+
+layout (location = 0) out vec4 outFragColor;
+
+// Copy the single-sample draw image into every sample of the multisampled color image, so the
+// geometry is drawn over the background
+void main()
+{
+    outFragColor = imageLoad(images[nonuniformEXT(pushConstants.image)], ivec2(gl_FragCoord.xy));
+}
@@ -0,0 +1,61 @@
+// Matches OutputEncoding::get_index in src/display.rs
+const uint OUTPUT_LINEAR = 0;
+const uint OUTPUT_SRGB = 1;
+const uint OUTPUT_PQ = 2;
+const uint OUTPUT_SCRGB = 3;
+
+// Luminance of an scRGB value of 1, in nits
+const float SCRGB_WHITE_NITS = 80.0f;
+// Luminance of a PQ value of 1, in nits
+const float PQ_MAX_NITS = 10000.0f;
+
+vec3 encodeSrgb(vec3 linear)
+{
+    vec3 low = linear * 12.92f;
+    vec3 high = 1.055f * pow(linear, vec3(1.0f / 2.4f)) - 0.055f;
+    return mix(high, low, lessThanEqual(linear, vec3(0.0031308f)));
+}
+
+vec3 decodeSrgb(vec3 encoded)
+{
+    vec3 low = encoded / 12.92f;
+    vec3 high = pow((encoded + 0.055f) / 1.055f, vec3(2.4f));
+    return mix(high, low, lessThanEqual(encoded, vec3(0.04045f)));
+}
+
+// SMPTE ST 2084 perceptual quantizer, from luminance relative to PQ_MAX_NITS
+vec3 encodePq(vec3 luminance)
+{
+    const float m1 = 0.1593017578125f;
+    const float m2 = 78.84375f;
+    const float c1 = 0.8359375f;
+    const float c2 = 18.8515625f;
+    const float c3 = 18.6875f;
+    vec3 y = pow(clamp(luminance, 0.0f, 1.0f), vec3(m1));
+    return pow((c1 + c2 * y) / (1.0f + c3 * y), vec3(m2));
+}
+
+vec3 rec709ToRec2020(vec3 color)
+{
+    const mat3 conversion = mat3(
+        0.627404f, 0.069097f, 0.016391f,
+        0.329283f, 0.919540f, 0.088013f,
+        0.043313f, 0.011362f, 0.895595f
+    );
+    return conversion * color;
+}
+
+// Encode linear Rec. 709 display values, where 1 is SDR white, for the swapchain. HDR displays
+// show SDR white at `paperWhite` nits
+vec3 encodeOutput(vec3 color, uint encoding, float paperWhite)
+{
+    if (encoding == OUTPUT_SRGB) {
+        return encodeSrgb(color);
+    } else if (encoding == OUTPUT_PQ) {
+        return encodePq(rec709ToRec2020(color) * paperWhite / PQ_MAX_NITS);
+    } else if (encoding == OUTPUT_SCRGB) {
+        return color * paperWhite / SCRGB_WHITE_NITS;
+    }
+    // An sRGB target encodes on write instead
+    return color;
+}
@@ -0,0 +1,35 @@
+#version 460
+#extension GL_EXT_nonuniform_qualifier : require
+
+#include "output.inc.glsl"
+
+layout (set = 0, binding = 1) uniform sampler2D textures[];
+
+// Matches OverlayPushConstants in src/overlay.rs
+layout (push_constant) uniform constants {
+    // Buffer addresses only the vertex shader reads
+    uvec2 vertices;
+    uvec2 indices;
+    vec2 screenSize;
+    uint texture;
+    uint outputEncoding;
+    float paperWhite;
+} pushConstants;
+
+layout (location = 0) in vec2 inUV;
+layout (location = 1) in vec4 inColor;
+
+layout (location = 0) out vec4 outFragColor;
+
+// Colors and textures are premultiplied sRGB, like egui's, and blended in that space on SDR
+// targets
+void main()
+{
+    vec4 color = inColor * texture(textures[nonuniformEXT(pushConstants.texture)], inUV);
+    if (pushConstants.outputEncoding != OUTPUT_SRGB) {
+        // Decoding premultiplied values is close enough for a UI, and keeps its alpha
+        color.rgb = encodeOutput(decodeSrgb(color.rgb), pushConstants.outputEncoding,
+            pushConstants.paperWhite);
+    }
+    outFragColor = color;
+}
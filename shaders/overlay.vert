@@ -0,0 +1,39 @@
+#version 460
+#extension GL_EXT_buffer_reference : require
+
+// Vertices are 5 words each, position, uv and a packed color, matching OverlayVertex in
+// src/overlay.rs. A struct would be padded to 24 bytes under std430
+layout (buffer_reference, std430) readonly buffer VertexBuffer {
+    float words[];
+};
+
+layout (buffer_reference, std430) readonly buffer IndexBuffer {
+    uint indices[];
+};
+
+// Matches OverlayPushConstants in src/overlay.rs
+layout (push_constant) uniform constants {
+    VertexBuffer vertices;
+    IndexBuffer indices;
+    // Size of the target in pixels
+    vec2 screenSize;
+    uint texture;
+    uint outputEncoding;
+    float paperWhite;
+} pushConstants;
+
+layout (location = 0) out vec2 outUV;
+layout (location = 1) out vec4 outColor;
+
+// Indices are pulled by the draw's first index, so meshes share one pair of buffers
+void main()
+{
+    uint index = pushConstants.indices.indices[gl_VertexIndex];
+    uint base = index * 5;
+    VertexBuffer vertices = pushConstants.vertices;
+    vec2 position = vec2(vertices.words[base], vertices.words[base + 1]);
+    outUV = vec2(vertices.words[base + 2], vertices.words[base + 3]);
+    outColor = unpackUnorm4x8(floatBitsToUint(vertices.words[base + 4]));
+    // Pixels from the top left map onto clip space, whose y also points down
+    gl_Position = vec4(position / pushConstants.screenSize * 2.0f - 1.0f, 0.0f, 1.0f);
+}
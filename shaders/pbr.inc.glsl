@@ -0,0 +1,60 @@
+// Cook-Torrance BRDF for glTF metallic-roughness materials
+
+const float PI = 3.14159265359f;
+
+// Trowbridge-Reitz GGX normal distribution
+float distributionGGX(float NdotH, float roughness)
+{
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float denominator = NdotH * NdotH * (a2 - 1.0f) + 1.0f;
+    return a2 / (PI * denominator * denominator);
+}
+
+// Height-correlated Smith visibility term, which already includes the 1 / (4 NdotL NdotV)
+float visibilitySmithGGX(float NdotL, float NdotV, float roughness)
+{
+    float a = roughness * roughness;
+    float a2 = a * a;
+    float ggxV = NdotL * sqrt(NdotV * NdotV * (1.0f - a2) + a2);
+    float ggxL = NdotV * sqrt(NdotL * NdotL * (1.0f - a2) + a2);
+    return 0.5f / max(ggxV + ggxL, 1e-5f);
+}
+
+vec3 fresnelSchlick(float VdotH, vec3 f0)
+{
+    return f0 + (1.0f - f0) * pow(1.0f - VdotH, 5.0f);
+}
+
+struct SurfaceData {
+    vec3 baseColor;
+    float metallic;
+    float roughness;
+    vec3 normal;
+    vec3 viewDirection;
+};
+
+// Outgoing radiance towards the viewer from a light arriving along `lightDirection`
+vec3 evaluateBRDF(SurfaceData surface, vec3 lightDirection, vec3 radiance)
+{
+    vec3 N = surface.normal;
+    vec3 V = surface.viewDirection;
+    vec3 L = lightDirection;
+    vec3 H = normalize(V + L);
+    float NdotL = max(dot(N, L), 0.0f);
+    if (NdotL <= 0.0f) {
+        return vec3(0.0f);
+    }
+    float NdotV = max(dot(N, V), 1e-4f);
+    float NdotH = max(dot(N, H), 0.0f);
+    float VdotH = max(dot(V, H), 0.0f);
+
+    vec3 f0 = mix(vec3(0.04f), surface.baseColor, surface.metallic);
+    vec3 F = fresnelSchlick(VdotH, f0);
+    float D = distributionGGX(NdotH, surface.roughness);
+    float Vis = visibilitySmithGGX(NdotL, NdotV, surface.roughness);
+
+    vec3 diffuse = (1.0f - F) * (1.0f - surface.metallic) * surface.baseColor / PI;
+    vec3 specular = F * D * Vis;
+    return (diffuse + specular) * radiance * NdotL;
+}
@@ -0,0 +1,52 @@
+#extension GL_EXT_buffer_reference : require
+#extension GL_EXT_nonuniform_qualifier : require
+
+// Matches MAX_CASCADES in src/shadow.rs
+#define MAX_CASCADES 4
+
+// Matches ShadowData in src/shadow.rs
+layout (buffer_reference, std430) readonly buffer ShadowBuffer {
+    mat4 viewProjection[MAX_CASCADES];
+    vec4 splitDepths;
+    vec4 texelSizes;
+    uint cascadeCount;
+    uint shadowMap;
+    float normalOffset;
+    uint filterRadius;
+};
+
+// Shadow maps are depth array textures in the resource table, with a comparison sampler
+layout (set = 0, binding = 1) uniform sampler2DArrayShadow shadowMaps[];
+
+// Fraction of the shadow casting light reaching `position`, which is `viewDepth` in front of the
+// camera. Filters the cascade's shadow map with a (2 * filterRadius + 1)^2 texel PCF kernel
+float sampleShadow(ShadowBuffer shadow, vec3 position, vec3 normal, float viewDepth)
+{
+    uint cascade = 0u;
+    while (cascade < shadow.cascadeCount && viewDepth > shadow.splitDepths[cascade]) {
+        cascade++;
+    }
+    if (cascade >= shadow.cascadeCount) {
+        return 1.0f;
+    }
+
+    // Pushing the lookup out along the normal keeps surfaces from shadowing themselves
+    vec3 offsetPosition = position + normal * shadow.texelSizes[cascade] * shadow.normalOffset;
+    vec4 lightPosition = shadow.viewProjection[cascade] * vec4(offsetPosition, 1.0f);
+    vec3 coords = lightPosition.xyz / lightPosition.w;
+    vec2 uv = coords.xy * 0.5f + 0.5f;
+
+    uint shadowMap = shadow.shadowMap;
+    vec2 texelSize = 1.0f / vec2(textureSize(shadowMaps[nonuniformEXT(shadowMap)], 0).xy);
+    int radius = int(shadow.filterRadius);
+    float lit = 0.0f;
+    for (int y = -radius; y <= radius; y++) {
+        for (int x = -radius; x <= radius; x++) {
+            vec2 offset = vec2(x, y) * texelSize;
+            vec4 lookup = vec4(uv + offset, float(cascade), coords.z);
+            lit += texture(shadowMaps[nonuniformEXT(shadowMap)], lookup);
+        }
+    }
+    float taps = float((2 * radius + 1) * (2 * radius + 1));
+    return lit / taps;
+}
@@ -0,0 +1,21 @@
+#version 460
+
+#include "mesh.inc.glsl"
+#include "shadow.inc.glsl"
+
+// Matches ShadowPushConstants in src/shadow.rs
+layout (push_constant) uniform constants {
+    VertexBuffer vertexBuffer;
+    InstanceBuffer instanceBuffer;
+    ShadowBuffer shadowBuffer;
+    uint cascade;
+} pushConstants;
+
+void main()
+{
+    Vertex v = pushConstants.vertexBuffer.vertices[gl_VertexIndex];
+    Instance instance = pushConstants.instanceBuffer.instances[gl_InstanceIndex];
+
+    vec4 worldPosition = instance.model * vec4(v.position, 1.0f);
+    gl_Position = pushConstants.shadowBuffer.viewProjection[pushConstants.cascade] * worldPosition;
+}
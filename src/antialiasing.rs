@@ -0,0 +1,384 @@
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use ash::vk;
+use glam::Vec2;
+
+use crate::utility::parse_env;
+use crate::{lv, utility, VulkanApp, FRAME_OVERLAP};
+
+/// Environment variable selecting the mode by name, e.g. `taa`
+const MODE_ENV: &str = "LV_AA";
+/// Environment variable overriding how much of the current frame TAA blends into the history
+const TAA_BLEND_ENV: &str = "LV_TAA_BLEND";
+/// Number of jitter offsets TAA cycles through before repeating
+pub const JITTER_SEQUENCE_LENGTH: u64 = 8;
+
+/// How edges are antialiased after the geometry pass, on top of any MSAA
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AntiAliasing {
+    None,
+    /// Fast approximate antialiasing, an edge blur on the tonemapped image
+    Fxaa,
+    /// Temporal antialiasing, accumulating jittered frames into a reprojected history
+    Taa,
+}
+
+impl AntiAliasing {
+    pub const ALL: [AntiAliasing; 3] = [AntiAliasing::None, AntiAliasing::Fxaa, AntiAliasing::Taa];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AntiAliasing::None => "none",
+            AntiAliasing::Fxaa => "fxaa",
+            AntiAliasing::Taa => "taa",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<AntiAliasing> {
+        AntiAliasing::ALL
+            .iter()
+            .copied()
+            .find(|mode| mode.name() == name)
+    }
+
+    /// The mode after this one, wrapping around
+    pub fn next(&self) -> AntiAliasing {
+        let index = AntiAliasing::ALL
+            .iter()
+            .position(|mode| mode == self)
+            .unwrap();
+        AntiAliasing::ALL[(index + 1) % AntiAliasing::ALL.len()]
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct AntiAliasingSettings {
+    pub mode: AntiAliasing,
+    /// Weight of the current frame when TAA blends it into the history
+    pub taa_blend: f32,
+}
+
+impl Default for AntiAliasingSettings {
+    fn default() -> Self {
+        AntiAliasingSettings {
+            mode: AntiAliasing::None,
+            taa_blend: 0.1,
+        }
+    }
+}
+
+impl AntiAliasingSettings {
+    /// Default settings with the mode and TAA blend overridden from the environment
+    pub fn from_env() -> AntiAliasingSettings {
+        let mut settings = AntiAliasingSettings::default();
+        if let Ok(name) = std::env::var(MODE_ENV) {
+            match AntiAliasing::from_name(name.trim()) {
+                Some(mode) => settings.mode = mode,
+                None => log::warn!("Ignoring unknown {}={:?}", MODE_ENV, name),
+            }
+        }
+        if let Some(blend) = parse_env::<f32>(TAA_BLEND_ENV) {
+            settings.taa_blend = blend.clamp(0.01, 1.0);
+        }
+        settings
+    }
+}
+
+/// Element `index` of the Halton low discrepancy sequence in `base`, in [0, 1)
+pub fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+/// Subpixel offset of the projection for `frame`, in pixels within [-0.5, 0.5)
+pub fn jitter_offset(frame: u64) -> Vec2 {
+    // Halton starts at 0 for index 0, which would repeat the center
+    let index = (frame % JITTER_SEQUENCE_LENGTH) as u32 + 1;
+    Vec2::new(halton(index, 2) - 0.5, halton(index, 3) - 0.5)
+}
+
+/// Push constants of the TAA resolve, see `shaders/taa.comp`
+#[repr(C)]
+struct TaaPushConstants {
+    draw_image: u32,
+    velocity_texture: u32,
+    previous_history: u32,
+    history_view: u32,
+    blend: f32,
+    history_valid: u32,
+}
+
+/// Push constants of the FXAA pass
+#[repr(C)]
+struct FxaaPushConstants {
+    input_texture: u32,
+    output_view: u32,
+}
+
+impl VulkanApp {
+    /// Compute pipelines of the TAA resolve and of FXAA, see `shaders/taa.comp` and
+    /// `shaders/fxaa.comp`
+    pub fn create_antialiasing_pipelines(
+        device: Arc<lv::Device>,
+        resource_layout: vk::DescriptorSetLayout,
+    ) -> (Rc<lv::ComputePipeline>, Rc<lv::ComputePipeline>) {
+        let taa_pipeline = Rc::new(VulkanApp::create_compute_pipeline(
+            device.clone(),
+            std::path::Path::new("./shaders/taa.comp.spv"),
+            "taa",
+            vec![resource_layout],
+            std::mem::size_of::<TaaPushConstants>() as u32,
+        ));
+        let fxaa_pipeline = Rc::new(VulkanApp::create_compute_pipeline(
+            device,
+            std::path::Path::new("./shaders/fxaa.comp.spv"),
+            "fxaa",
+            vec![resource_layout],
+            std::mem::size_of::<FxaaPushConstants>() as u32,
+        ));
+        (taa_pipeline, fxaa_pipeline)
+    }
+
+    /// TAA output of each frame in flight, sampled by the next frame and written through a
+    /// storage view
+    pub fn create_taa_history(
+        extent: vk::Extent3D,
+        format: vk::Format,
+        sampler: Rc<lv::Sampler>,
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+    ) -> Vec<(lv::Texture, lv::ImageView)> {
+        (0..FRAME_OVERLAP)
+            .map(|frame_index| {
+                let image = lv::AllocatedImage::new(
+                    utility::init::image_create_info(
+                        format,
+                        vk::ImageUsageFlags::STORAGE
+                            | vk::ImageUsageFlags::SAMPLED
+                            | vk::ImageUsageFlags::TRANSFER_SRC,
+                        extent,
+                    ),
+                    vk::ImageAspectFlags::COLOR,
+                    device.clone(),
+                    allocator.clone(),
+                    Some(&format!("Frame {} TAA history", frame_index)),
+                );
+                let view = lv::ImageView::mip(
+                    &image,
+                    0,
+                    vk::ImageAspectFlags::COLOR,
+                    Some(&format!("Frame {} TAA history view", frame_index)),
+                );
+                (
+                    lv::Texture::from_storage_image(image, sampler.clone()),
+                    view,
+                )
+            })
+            .collect()
+    }
+
+    /// Blend the draw image into the history reprojected from the previous frame, then copy the
+    /// result back into the draw image. The draw image must be in `GENERAL`
+    pub fn resolve_taa(&self) {
+        let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
+        let _label = lv::DebugLabel::new(&self.logical_device, command_buffer, "taa");
+        let _scope = self.profiler.scope(command_buffer, "taa");
+        let device = &self.logical_device.handle;
+        let frame_slot = (self.frame_count % self.frames.len() as u64) as usize;
+        let previous_slot = (frame_slot + self.frames.len() - 1) % self.frames.len();
+        let history_valid = self
+            .last_taa_frame
+            .is_some_and(|frame| frame + 1 == self.frame_count);
+        let history = |slot: usize| {
+            self.gpu_resource_table
+                .get_texture(self.targets.taa_history_indices[slot] as usize)
+                .as_ref()
+                .unwrap()
+                .get_image()
+        };
+        // This frame's history is overwritten entirely
+        utility::transition_image(
+            device,
+            command_buffer,
+            history(frame_slot).get_handle(),
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+            vk::QUEUE_FAMILY_IGNORED,
+            vk::QUEUE_FAMILY_IGNORED,
+        );
+        // A stale history is not sampled, so only its layout matters
+        let previous_layout = if history_valid {
+            vk::ImageLayout::GENERAL
+        } else {
+            vk::ImageLayout::UNDEFINED
+        };
+        utility::transition_image(
+            device,
+            command_buffer,
+            history(previous_slot).get_handle(),
+            previous_layout,
+            vk::ImageLayout::GENERAL,
+            vk::QUEUE_FAMILY_IGNORED,
+            vk::QUEUE_FAMILY_IGNORED,
+        );
+        let velocity_image = self
+            .gpu_resource_table
+            .get_texture(self.targets.velocity_texture_index as usize)
+            .as_ref()
+            .unwrap()
+            .get_image();
+        utility::transition_image(
+            device,
+            command_buffer,
+            velocity_image.get_handle(),
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::QUEUE_FAMILY_IGNORED,
+            vk::QUEUE_FAMILY_IGNORED,
+        );
+        let push_constants = TaaPushConstants {
+            draw_image: self.targets.draw_image_index,
+            velocity_texture: self.targets.velocity_texture_index,
+            previous_history: self.targets.taa_history_indices[previous_slot],
+            history_view: self.targets.taa_history_view_indices[frame_slot],
+            blend: self.anti_aliasing_settings.taa_blend,
+            history_valid: history_valid as u32,
+        };
+        self.dispatch_compute(
+            &self.taa_pipeline,
+            &push_constants,
+            [
+                self.draw_extent.width.div_ceil(16),
+                self.draw_extent.height.div_ceil(16),
+                1,
+            ],
+            true,
+        );
+
+        // Neighbors read the draw image until the whole dispatch is done, so the result is only
+        // copied back afterwards
+        utility::memory_barrier(
+            device,
+            command_buffer,
+            vk::PipelineStageFlags2::COMPUTE_SHADER,
+            vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            vk::PipelineStageFlags2::TRANSFER,
+            vk::AccessFlags2::TRANSFER_READ | vk::AccessFlags2::TRANSFER_WRITE,
+        );
+        let draw_image = self
+            .gpu_resource_table
+            .get_storage_image(self.targets.draw_image_index as usize)
+            .as_ref()
+            .unwrap();
+        let subresource = vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level: 0,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+        let region = vk::ImageCopy {
+            src_subresource: subresource,
+            dst_subresource: subresource,
+            extent: vk::Extent3D {
+                width: self.draw_extent.width,
+                height: self.draw_extent.height,
+                depth: 1,
+            },
+            ..Default::default()
+        };
+        unsafe {
+            device.cmd_copy_image(
+                command_buffer,
+                history(frame_slot).get_handle(),
+                vk::ImageLayout::GENERAL,
+                draw_image.get_handle(),
+                vk::ImageLayout::GENERAL,
+                &[region],
+            );
+        }
+        utility::memory_barrier(
+            device,
+            command_buffer,
+            vk::PipelineStageFlags2::TRANSFER,
+            vk::AccessFlags2::TRANSFER_WRITE,
+            vk::PipelineStageFlags2::COMPUTE_SHADER,
+            vk::AccessFlags2::SHADER_STORAGE_READ
+                | vk::AccessFlags2::SHADER_STORAGE_WRITE
+                | vk::AccessFlags2::SHADER_SAMPLED_READ,
+        );
+    }
+
+    /// Smooth the edges of the tonemapped image into the FXAA image, which is presented instead
+    pub fn fxaa(&self) {
+        let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
+        let _label = lv::DebugLabel::new(&self.logical_device, command_buffer, "fxaa");
+        let _scope = self.profiler.scope(command_buffer, "fxaa");
+        let fxaa_image = self
+            .gpu_resource_table
+            .get_texture(self.targets.fxaa_texture_index as usize)
+            .as_ref()
+            .unwrap()
+            .get_image();
+        utility::transition_image(
+            &self.logical_device.handle,
+            command_buffer,
+            fxaa_image.get_handle(),
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+            vk::QUEUE_FAMILY_IGNORED,
+            vk::QUEUE_FAMILY_IGNORED,
+        );
+        // Tonemapping wrote the LDR image through its storage view
+        self.compute_barrier();
+        let push_constants = FxaaPushConstants {
+            input_texture: self.targets.ldr_texture_index,
+            output_view: self.targets.fxaa_view_index,
+        };
+        self.dispatch_compute(
+            &self.fxaa_pipeline,
+            &push_constants,
+            [
+                self.draw_extent.width.div_ceil(16),
+                self.draw_extent.height.div_ceil(16),
+                1,
+            ],
+            true,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halton_sequence() {
+        assert_eq!(halton(0, 2), 0.0);
+        assert_eq!(halton(1, 2), 0.5);
+        assert_eq!(halton(2, 2), 0.25);
+        assert_eq!(halton(3, 2), 0.75);
+        assert!((halton(1, 3) - 1.0 / 3.0).abs() < 1e-6);
+        assert!((halton(5, 3) - 7.0 / 9.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn jitter_stays_within_a_pixel_and_repeats() {
+        let offsets: Vec<Vec2> = (0..JITTER_SEQUENCE_LENGTH).map(jitter_offset).collect();
+        for offset in &offsets {
+            assert!((-0.5..0.5).contains(&offset.x) && (-0.5..0.5).contains(&offset.y));
+        }
+        // Skips the center Halton(0) would give
+        assert_eq!(offsets[0], Vec2::new(0.0, halton(1, 3) - 0.5));
+        assert_eq!(jitter_offset(JITTER_SEQUENCE_LENGTH), offsets[0]);
+        for (index, offset) in offsets.iter().enumerate() {
+            assert!(!offsets[..index].contains(offset));
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use ash::vk;
+
+use crate::utility::parse_env;
+use crate::{lv, utility, VulkanApp};
+
+/// Most mip levels the bloom chain has, small draw images get fewer
+pub const MAX_MIP_LEVELS: u32 = 6;
+
+/// Environment variable overriding the brightness above which pixels start to bloom
+const THRESHOLD_ENV: &str = "LV_BLOOM_THRESHOLD";
+/// Environment variable overriding how much bloom is blended into the image
+const INTENSITY_ENV: &str = "LV_BLOOM_INTENSITY";
+/// Environment variable overriding the upsampling filter radius
+const RADIUS_ENV: &str = "LV_BLOOM_RADIUS";
+/// Format of the mip chain bloom blurs the draw image through
+const BLOOM_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+
+/// Bloom configuration. The defaults let every pixel bloom a little, like light scattering in a
+/// lens, rather than only those above a threshold
+#[derive(Clone, Copy, Debug)]
+pub struct BloomSettings {
+    /// Brightness above which pixels bloom, 0 lets everything bloom
+    pub threshold: f32,
+    /// Width of the soft transition below the threshold
+    pub knee: f32,
+    /// Fraction of the final image that is bloom
+    pub intensity: f32,
+    /// Radius of the upsampling tent filter in texels of the smaller mip
+    pub radius: f32,
+}
+
+impl Default for BloomSettings {
+    fn default() -> Self {
+        BloomSettings {
+            threshold: 0.0,
+            knee: 0.5,
+            intensity: 0.04,
+            radius: 1.0,
+        }
+    }
+}
+
+impl BloomSettings {
+    /// Default settings with the threshold, intensity and radius overridden from the environment
+    pub fn from_env() -> BloomSettings {
+        let mut settings = BloomSettings::default();
+        if let Some(threshold) = parse_env::<f32>(THRESHOLD_ENV) {
+            settings.threshold = threshold.max(0.0);
+        }
+        if let Some(intensity) = parse_env::<f32>(INTENSITY_ENV) {
+            settings.intensity = intensity.clamp(0.0, 1.0);
+        }
+        if let Some(radius) = parse_env::<f32>(RADIUS_ENV) {
+            settings.radius = radius.max(0.0);
+        }
+        settings
+    }
+}
+
+/// Width and height of the first bloom mip for a draw image of `width` by `height`
+pub fn chain_extent(width: u32, height: u32) -> (u32, u32) {
+    ((width / 2).max(1), (height / 2).max(1))
+}
+
+/// Number of mip levels of a bloom chain starting at `width` by `height`, stopping before either
+/// side drops below 2 texels
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    let smallest_side = width.min(height).max(2);
+    smallest_side.ilog2().clamp(1, MAX_MIP_LEVELS)
+}
+
+/// Push constants of the bloom passes, see `shaders/bloom.inc.glsl`
+#[repr(C)]
+struct BloomPushConstants {
+    draw_image: u32,
+    chain_texture: u32,
+    target_view: u32,
+    source_level: f32,
+    level_count: u32,
+    threshold: f32,
+    knee: f32,
+    radius: f32,
+    intensity: f32,
+}
+
+impl VulkanApp {
+    /// Compute pipelines of the bloom prefilter, downsample, upsample and composite passes, see
+    /// `shaders/bloom.inc.glsl`
+    pub fn create_bloom_pipelines(
+        device: Arc<lv::Device>,
+        resource_layout: vk::DescriptorSetLayout,
+    ) -> (
+        Rc<lv::ComputePipeline>,
+        Rc<lv::ComputePipeline>,
+        Rc<lv::ComputePipeline>,
+        Rc<lv::ComputePipeline>,
+    ) {
+        let pipeline = |pass: &str| {
+            Rc::new(VulkanApp::create_compute_pipeline(
+                device.clone(),
+                std::path::Path::new(&format!("./shaders/bloom_{}.comp.spv", pass)),
+                &format!("bloom {}", pass),
+                vec![resource_layout],
+                std::mem::size_of::<BloomPushConstants>() as u32,
+            ))
+        };
+        (
+            pipeline("prefilter"),
+            pipeline("downsample"),
+            pipeline("upsample"),
+            pipeline("composite"),
+        )
+    }
+
+    /// Mip chain starting at half of `draw_extent` that bloom blurs the draw image through, with a
+    /// storage view of every level
+    pub fn create_bloom_chain(
+        draw_extent: vk::Extent2D,
+        sampler: Rc<lv::Sampler>,
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+    ) -> (lv::Texture, Vec<lv::ImageView>) {
+        let (width, height) = chain_extent(draw_extent.width, draw_extent.height);
+        let mut image_ci = utility::init::image_create_info(
+            BLOOM_FORMAT,
+            vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::SAMPLED,
+            vk::Extent3D {
+                width,
+                height,
+                depth: 1,
+            },
+        );
+        image_ci.mip_levels = mip_level_count(width, height);
+        let image = lv::AllocatedImage::new(
+            image_ci,
+            vk::ImageAspectFlags::COLOR,
+            device.clone(),
+            allocator,
+            Some("Bloom chain"),
+        );
+        let views = (0..image.get_mip_levels())
+            .map(|level| {
+                lv::ImageView::mip(
+                    &image,
+                    level,
+                    vk::ImageAspectFlags::COLOR,
+                    Some(&format!("Bloom chain mip {} view", level)),
+                )
+            })
+            .collect();
+        (lv::Texture::from_storage_image(image, sampler), views)
+    }
+
+    /// Blend a blurred copy of the draw image over it, so bright parts glow. The draw image must
+    /// be in `GENERAL`
+    pub fn bloom(&self) {
+        let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
+        let _label = lv::DebugLabel::new(&self.logical_device, command_buffer, "bloom");
+        let _scope = self.profiler.scope(command_buffer, "bloom");
+        let chain = self
+            .gpu_resource_table
+            .get_texture(self.targets.bloom_texture_index as usize)
+            .as_ref()
+            .unwrap()
+            .get_image();
+        // Every level is overwritten before it is read
+        utility::transition_image(
+            &self.logical_device.handle,
+            command_buffer,
+            chain.get_handle(),
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+            vk::QUEUE_FAMILY_IGNORED,
+            vk::QUEUE_FAMILY_IGNORED,
+        );
+        let group_counts =
+            |extent: vk::Extent2D| [extent.width.div_ceil(8), extent.height.div_ceil(8), 1];
+        let settings = &self.bloom_settings;
+        let level_count = self.targets.bloom_view_indices.len() as u32;
+        let mut push_constants = BloomPushConstants {
+            draw_image: self.targets.draw_image_index,
+            chain_texture: self.targets.bloom_texture_index,
+            target_view: self.targets.bloom_view_indices[0],
+            source_level: 0.0,
+            level_count,
+            threshold: settings.threshold,
+            knee: settings.knee,
+            radius: settings.radius,
+            intensity: settings.intensity,
+        };
+        self.dispatch_compute(
+            &self.bloom_prefilter_pipeline,
+            &push_constants,
+            group_counts(chain.get_mip_extent(0)),
+            true,
+        );
+        for level in 1..level_count {
+            self.compute_barrier();
+            push_constants.target_view = self.targets.bloom_view_indices[level as usize];
+            push_constants.source_level = (level - 1) as f32;
+            self.dispatch_compute(
+                &self.bloom_downsample_pipeline,
+                &push_constants,
+                group_counts(chain.get_mip_extent(level)),
+                true,
+            );
+        }
+        for level in (0..level_count - 1).rev() {
+            self.compute_barrier();
+            push_constants.target_view = self.targets.bloom_view_indices[level as usize];
+            push_constants.source_level = (level + 1) as f32;
+            self.dispatch_compute(
+                &self.bloom_upsample_pipeline,
+                &push_constants,
+                group_counts(chain.get_mip_extent(level)),
+                true,
+            );
+        }
+        self.compute_barrier();
+        push_constants.source_level = 0.0;
+        self.dispatch_compute(
+            &self.bloom_composite_pipeline,
+            &push_constants,
+            group_counts(self.draw_extent),
+            true,
+        );
+        self.compute_barrier();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chain_starts_at_half_resolution() {
+        assert_eq!(chain_extent(1920, 1080), (960, 540));
+        assert_eq!(chain_extent(1, 3), (1, 1));
+    }
+
+    #[test]
+    fn mips_stop_before_two_texels() {
+        // 4x8, 2x4
+        assert_eq!(mip_level_count(4, 8), 2);
+        // 15x15, 7x7, 3x3
+        assert_eq!(mip_level_count(15, 15), 3);
+        assert_eq!(mip_level_count(1, 1), 1);
+    }
+
+    #[test]
+    fn mips_are_capped() {
+        assert_eq!(mip_level_count(1 << 20, 1 << 20), MAX_MIP_LEVELS);
+        assert!(mip_level_count(960, 540) <= MAX_MIP_LEVELS);
+    }
+}
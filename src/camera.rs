@@ -0,0 +1,374 @@
+use crate::input::{Action, Input};
+use glam::{Mat4, Vec2, Vec3};
+
+/// Pitch is clamped just short of straight up or down to keep the view matrix well defined
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+/// Distance in front of the camera that orbiting starts around, and at which objects keep their
+/// size when switching projections while flying
+const FOCUS_DISTANCE: f32 = 3.0;
+const MIN_FOV_Y: f32 = 5.0 * std::f32::consts::PI / 180.0;
+const MAX_FOV_Y: f32 = 120.0 * std::f32::consts::PI / 180.0;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Projection {
+    Perspective {
+        /// Vertical field of view in radians
+        fov_y: f32,
+        near: f32,
+        far: f32,
+    },
+    Orthographic {
+        /// Height of the view volume in world units, the width follows from the aspect ratio
+        height: f32,
+        near: f32,
+        far: f32,
+    },
+}
+
+impl Projection {
+    /// Distances to the near and far planes
+    pub fn get_depth_range(&self) -> (f32, f32) {
+        match *self {
+            Projection::Perspective { near, far, .. } => (near, far),
+            Projection::Orthographic { near, far, .. } => (near, far),
+        }
+    }
+
+    /// Narrow the view by `factor`, or widen it for factors above 1
+    pub fn zoomed(&self, factor: f32) -> Projection {
+        match *self {
+            Projection::Perspective { fov_y, near, far } => Projection::Perspective {
+                fov_y: (fov_y * factor).clamp(MIN_FOV_Y, MAX_FOV_Y),
+                near,
+                far,
+            },
+            Projection::Orthographic { height, near, far } => Projection::Orthographic {
+                height: (height * factor).max(0.01),
+                near,
+                far,
+            },
+        }
+    }
+
+    /// The other kind of projection, showing objects `distance` away at the same size
+    pub fn toggled(&self, distance: f32) -> Projection {
+        match *self {
+            Projection::Perspective { fov_y, near, far } => Projection::Orthographic {
+                height: 2.0 * distance * (fov_y * 0.5).tan(),
+                near,
+                far,
+            },
+            Projection::Orthographic { height, near, far } => Projection::Perspective {
+                fov_y: 2.0 * (height * 0.5 / distance).atan(),
+                near,
+                far,
+            },
+        }
+    }
+}
+
+/// Right-handed, Y-up camera producing matrices for Vulkan's clip space (Y down, depth 0 to 1)
+#[derive(Clone, Debug)]
+pub struct Camera {
+    pub position: Vec3,
+    /// Rotation around the Y axis in radians, 0 looks down -Z
+    pub yaw: f32,
+    /// Rotation above the horizon in radians
+    pub pitch: f32,
+    pub projection: Projection,
+    /// Width divided by height of the render target
+    pub aspect_ratio: f32,
+}
+
+impl Camera {
+    pub fn new(position: Vec3, projection: Projection, aspect_ratio: f32) -> Self {
+        Camera {
+            position,
+            yaw: 0.0,
+            pitch: 0.0,
+            projection,
+            aspect_ratio,
+        }
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        Vec3::new(
+            -self.yaw.sin() * self.pitch.cos(),
+            self.pitch.sin(),
+            -self.yaw.cos() * self.pitch.cos(),
+        )
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.forward().cross(Vec3::Y).normalize()
+    }
+
+    pub fn view_matrix(&self) -> Mat4 {
+        Mat4::look_to_rh(self.position, self.forward(), Vec3::Y)
+    }
+
+    pub fn projection_matrix(&self) -> Mat4 {
+        let mut projection = match self.projection {
+            Projection::Perspective { fov_y, near, far } => {
+                Mat4::perspective_rh(fov_y, self.aspect_ratio, near, far)
+            }
+            Projection::Orthographic { height, near, far } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * self.aspect_ratio;
+                Mat4::orthographic_rh(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    near,
+                    far,
+                )
+            }
+        };
+        // Vulkan's clip space Y points down
+        projection.y_axis.y *= -1.0;
+        projection
+    }
+
+    /// Uniforms with the projection shifted by `jitter` in normalized device coordinates, and the
+    /// unjittered view projection of the previous frame for computing motion
+    pub fn get_uniforms(&self, jitter: Vec2, previous_view_projection: Mat4) -> CameraUniforms {
+        let view = self.view_matrix();
+        let unjittered_projection = self.projection_matrix();
+        let projection = Mat4::from_translation(jitter.extend(0.0)) * unjittered_projection;
+        CameraUniforms {
+            view: view.to_cols_array_2d(),
+            projection: projection.to_cols_array_2d(),
+            view_projection: (projection * view).to_cols_array_2d(),
+            inverse_projection: projection.inverse().to_cols_array_2d(),
+            unjittered_view_projection: (unjittered_projection * view).to_cols_array_2d(),
+            previous_view_projection: previous_view_projection.to_cols_array_2d(),
+            position: self.position.extend(1.0).to_array(),
+        }
+    }
+}
+
+/// Camera data as laid out for shaders, see `shaders/camera.inc.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct CameraUniforms {
+    pub view: [[f32; 4]; 4],
+    pub projection: [[f32; 4]; 4],
+    pub view_projection: [[f32; 4]; 4],
+    pub inverse_projection: [[f32; 4]; 4],
+    /// `view_projection` without the jitter
+    pub unjittered_view_projection: [[f32; 4]; 4],
+    /// Unjittered view projection of the previous frame
+    pub previous_view_projection: [[f32; 4]; 4],
+    pub position: [f32; 4],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CameraMode {
+    /// Free movement, looking around with the mouse
+    Fly,
+    /// Rotate around `target` at `distance`, zooming with the scroll wheel
+    Orbit { target: Vec3, distance: f32 },
+}
+
+/// Moves a [`Camera`] from the [`Action`]s in an [`Input`].
+///
+/// With the default bindings WASD moves, E and Q move up and down, Shift speeds up and dragging
+/// with the right mouse button looks around. The scroll wheel changes the movement speed when
+/// flying and the distance when orbiting. Tab switches between flying and orbiting around the
+/// point in front of the camera.
+pub struct CameraController {
+    pub mode: CameraMode,
+    /// Movement speed in world units per second
+    pub speed: f32,
+    /// Radians turned per pixel of mouse movement
+    pub sensitivity: f32,
+}
+
+impl CameraController {
+    pub fn new(mode: CameraMode) -> Self {
+        CameraController {
+            mode,
+            speed: 2.0,
+            sensitivity: 0.003,
+        }
+    }
+
+    fn toggle_mode(&mut self, camera: &Camera) {
+        self.mode = match self.mode {
+            CameraMode::Fly => CameraMode::Orbit {
+                target: camera.position + camera.forward() * FOCUS_DISTANCE,
+                distance: FOCUS_DISTANCE,
+            },
+            CameraMode::Orbit { .. } => CameraMode::Fly,
+        };
+    }
+
+    /// Apply this frame's input to `camera`
+    pub fn update(&mut self, camera: &mut Camera, input: &Input, delta_time: f32) {
+        if input.is_pressed(Action::ToggleCameraMode) {
+            self.toggle_mode(camera);
+        }
+        if input.is_pressed(Action::ToggleProjection) {
+            let distance = match self.mode {
+                CameraMode::Fly => FOCUS_DISTANCE,
+                CameraMode::Orbit { distance, .. } => distance,
+            };
+            camera.projection = camera.projection.toggled(distance);
+        }
+        if input.is_held(Action::Look) {
+            // Not every platform reports raw mouse motion
+            let mouse_motion = if input.mouse_motion() != Vec2::ZERO {
+                input.mouse_motion()
+            } else {
+                input.cursor_delta()
+            };
+            camera.yaw -= mouse_motion.x * self.sensitivity;
+            camera.pitch =
+                (camera.pitch - mouse_motion.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+        let mut scroll = input.scroll();
+        // Ctrl + scroll zooms, instead of changing the speed or orbit distance
+        if input.get_modifiers().control_key() && scroll != 0.0 {
+            camera.projection = camera.projection.zoomed(0.9f32.powf(scroll));
+            scroll = 0.0;
+        }
+
+        let speed = if input.is_held(Action::MoveFast) {
+            self.speed * 4.0
+        } else {
+            self.speed
+        };
+        let movement = camera.forward() * input.axis(Action::MoveForward, Action::MoveBackward)
+            + camera.right() * input.axis(Action::MoveRight, Action::MoveLeft)
+            + Vec3::Y * input.axis(Action::MoveUp, Action::MoveDown);
+        let movement = movement.normalize_or_zero() * speed * delta_time;
+
+        match &mut self.mode {
+            CameraMode::Fly => {
+                camera.position += movement;
+                if scroll != 0.0 {
+                    self.speed = (self.speed * 1.1f32.powf(scroll)).clamp(0.1, 100.0);
+                }
+            }
+            CameraMode::Orbit { target, distance } => {
+                *target += movement;
+                *distance = (*distance * 0.9f32.powf(scroll)).max(0.1);
+                camera.position = *target - camera.forward() * *distance;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec4;
+
+    fn camera(projection: Projection) -> Camera {
+        Camera::new(Vec3::ZERO, projection, 2.0)
+    }
+
+    /// Normalized device coordinates of view space `position`
+    fn project(camera: &Camera, position: Vec3) -> Vec3 {
+        let clip = camera.projection_matrix() * position.extend(1.0);
+        clip.truncate() / clip.w
+    }
+
+    #[test]
+    fn orthographic_matches_glam_with_y_flipped() {
+        let camera = camera(Projection::Orthographic {
+            height: 4.0,
+            near: 0.5,
+            far: 50.0,
+        });
+        let mut expected = Mat4::orthographic_rh(-4.0, 4.0, -2.0, 2.0, 0.5, 50.0);
+        expected.y_axis.y = -expected.y_axis.y;
+        assert!(camera.projection_matrix().abs_diff_eq(expected, 1e-6));
+    }
+
+    #[test]
+    fn orthographic_maps_the_view_volume_like_perspective() {
+        let orthographic = camera(Projection::Orthographic {
+            height: 4.0,
+            near: 0.5,
+            far: 50.0,
+        });
+        let perspective = camera(Projection::Perspective {
+            fov_y: 90.0f32.to_radians(),
+            near: 0.5,
+            far: 50.0,
+        });
+        for camera in [&orthographic, &perspective] {
+            // Depth goes from 0 at the near plane to 1 at the far plane, in front of the camera
+            assert!(project(camera, Vec3::new(0.0, 0.0, -0.5)).z.abs() < 1e-6);
+            assert!((project(camera, Vec3::new(0.0, 0.0, -50.0)).z - 1.0).abs() < 1e-5);
+            // Up in view space is up on screen, where Vulkan's Y points down
+            assert!(project(camera, Vec3::new(0.0, 0.1, -1.0)).y < 0.0);
+        }
+        // The top right corner of the view volume lands in the top right corner of the screen
+        let corner = project(&orthographic, Vec3::new(4.0, 2.0, -10.0));
+        assert!(corner
+            .truncate()
+            .abs_diff_eq(glam::Vec2::new(1.0, -1.0), 1e-6));
+    }
+
+    #[test]
+    fn orthographic_inverse_projection_round_trips() {
+        let camera = camera(Projection::Orthographic {
+            height: 4.0,
+            near: 0.5,
+            far: 50.0,
+        });
+        let uniforms = camera.get_uniforms(glam::Vec2::ZERO, Mat4::IDENTITY);
+        let inverse = Mat4::from_cols_array_2d(&uniforms.inverse_projection);
+        let position = Vec4::new(1.0, -0.5, -20.0, 1.0);
+        let round_trip = inverse * (camera.projection_matrix() * position);
+        assert!((round_trip / round_trip.w).abs_diff_eq(position, 1e-4));
+    }
+
+    #[test]
+    fn zooming_stays_within_limits() {
+        let perspective = Projection::Perspective {
+            fov_y: 70.0f32.to_radians(),
+            near: 0.1,
+            far: 1000.0,
+        };
+        let Projection::Perspective { fov_y, .. } = perspective.zoomed(0.5) else {
+            panic!("zooming changed the kind of projection");
+        };
+        assert!((fov_y - 35.0f32.to_radians()).abs() < 1e-5);
+        let Projection::Perspective { fov_y, .. } = perspective.zoomed(100.0) else {
+            panic!("zooming changed the kind of projection");
+        };
+        assert_eq!(fov_y, MAX_FOV_Y);
+        let Projection::Perspective { fov_y, .. } = perspective.zoomed(0.0) else {
+            panic!("zooming changed the kind of projection");
+        };
+        assert_eq!(fov_y, MIN_FOV_Y);
+    }
+
+    #[test]
+    fn toggling_keeps_the_focus_size_and_round_trips() {
+        let perspective = Projection::Perspective {
+            fov_y: 70.0f32.to_radians(),
+            near: 0.1,
+            far: 1000.0,
+        };
+        let orthographic = perspective.toggled(FOCUS_DISTANCE);
+        let Projection::Orthographic { height, near, far } = orthographic else {
+            panic!(
+                "expected an orthographic projection, got {:?}",
+                orthographic
+            );
+        };
+        // Half the height is visible at the focus distance in both
+        let half_height = FOCUS_DISTANCE * (35.0f32.to_radians()).tan();
+        assert!((height * 0.5 - half_height).abs() < 1e-5);
+        assert_eq!((near, far), (0.1, 1000.0));
+        let Projection::Perspective { fov_y, .. } = orthographic.toggled(FOCUS_DISTANCE) else {
+            panic!("expected a perspective projection");
+        };
+        assert!((fov_y - 70.0f32.to_radians()).abs() < 1e-5);
+    }
+}
@@ -0,0 +1,224 @@
+use crate::utility::{self, parse_env};
+use ash::vk;
+
+/// Environment variable preferring an HDR swapchain when set to `1`
+const HDR_ENV: &str = "LV_HDR";
+/// Environment variable overriding the brightness of SDR white on HDR displays, in nits
+const PAPER_WHITE_ENV: &str = "LV_PAPER_WHITE";
+
+/// Swapchain formats for SDR displays. Tonemapping encodes for the UNORM ones itself
+const SDR_SURFACE_FORMATS: [vk::SurfaceFormatKHR; 4] = [
+    surface_format(
+        vk::Format::B8G8R8A8_UNORM,
+        vk::ColorSpaceKHR::SRGB_NONLINEAR,
+    ),
+    surface_format(
+        vk::Format::R8G8B8A8_UNORM,
+        vk::ColorSpaceKHR::SRGB_NONLINEAR,
+    ),
+    surface_format(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+    surface_format(vk::Format::R8G8B8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+];
+/// Swapchain formats for HDR displays, needing `VK_EXT_swapchain_colorspace`
+const HDR_SURFACE_FORMATS: [vk::SurfaceFormatKHR; 3] = [
+    surface_format(
+        vk::Format::A2B10G10R10_UNORM_PACK32,
+        vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+    ),
+    surface_format(
+        vk::Format::A2R10G10B10_UNORM_PACK32,
+        vk::ColorSpaceKHR::HDR10_ST2084_EXT,
+    ),
+    surface_format(
+        vk::Format::R16G16B16A16_SFLOAT,
+        vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+    ),
+];
+
+const fn surface_format(
+    format: vk::Format,
+    color_space: vk::ColorSpaceKHR,
+) -> vk::SurfaceFormatKHR {
+    vk::SurfaceFormatKHR {
+        format,
+        color_space,
+    }
+}
+
+/// Transfer function tonemapping applies so its output displays right in the swapchain's color
+/// space
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OutputEncoding {
+    /// Linear values, for sRGB formats which encode on write
+    Linear,
+    /// The sRGB transfer function, for UNORM formats in the sRGB color space
+    Srgb,
+    /// HDR10, Rec. 2020 primaries with the ST 2084 perceptual quantizer curve
+    Pq,
+    /// scRGB, linear Rec. 709 where 1 is 80 nits and values may exceed 1
+    ScRgb,
+}
+
+impl OutputEncoding {
+    pub fn from_surface_format(surface_format: vk::SurfaceFormatKHR) -> OutputEncoding {
+        match surface_format.color_space {
+            vk::ColorSpaceKHR::HDR10_ST2084_EXT => OutputEncoding::Pq,
+            vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => OutputEncoding::ScRgb,
+            _ if utility::is_srgb_format(surface_format.format) => OutputEncoding::Linear,
+            _ => OutputEncoding::Srgb,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputEncoding::Linear => "linear",
+            OutputEncoding::Srgb => "sRGB",
+            OutputEncoding::Pq => "HDR10 PQ",
+            OutputEncoding::ScRgb => "scRGB",
+        }
+    }
+
+    /// Value the tonemap shader selects the encoding by, see `shaders/tonemap.comp`
+    pub fn get_index(&self) -> u32 {
+        match self {
+            OutputEncoding::Linear => 0,
+            OutputEncoding::Srgb => 1,
+            OutputEncoding::Pq => 2,
+            OutputEncoding::ScRgb => 3,
+        }
+    }
+
+    /// Format of the images between tonemapping and the swapchain. HDR encodings need more
+    /// than 8 bits to avoid banding, and scRGB values above 1
+    pub fn get_image_format(&self) -> vk::Format {
+        match self {
+            OutputEncoding::Linear | OutputEncoding::Srgb => vk::Format::R8G8B8A8_UNORM,
+            OutputEncoding::Pq | OutputEncoding::ScRgb => vk::Format::R16G16B16A16_SFLOAT,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DisplaySettings {
+    /// Prefer HDR swapchain formats over SDR ones when the surface supports them
+    pub hdr: bool,
+    /// Brightness of SDR white on HDR displays in nits, 203 as recommended by BT.2408
+    pub paper_white: f32,
+}
+
+impl Default for DisplaySettings {
+    fn default() -> Self {
+        DisplaySettings {
+            hdr: false,
+            paper_white: 203.0,
+        }
+    }
+}
+
+impl DisplaySettings {
+    /// Default settings with HDR and paper white overridden from the environment
+    pub fn from_env() -> DisplaySettings {
+        let mut settings = DisplaySettings::default();
+        if let Ok(value) = std::env::var(HDR_ENV) {
+            settings.hdr = value.trim() == "1";
+        }
+        if let Some(paper_white) = parse_env::<f32>(PAPER_WHITE_ENV) {
+            settings.paper_white = paper_white.clamp(80.0, 1000.0);
+        }
+        settings
+    }
+
+    /// Swapchain format and color space pairs in order of preference. HDR ones are only listed
+    /// when HDR is preferred and `extended_color_spaces` says the instance can present them
+    pub fn get_preferred_surface_formats(
+        &self,
+        extended_color_spaces: bool,
+    ) -> Vec<vk::SurfaceFormatKHR> {
+        let mut formats = Vec::new();
+        if self.hdr {
+            if extended_color_spaces {
+                formats.extend_from_slice(&HDR_SURFACE_FORMATS);
+            } else {
+                log::warn!("HDR needs VK_EXT_swapchain_colorspace, falling back to SDR");
+            }
+        }
+        formats.extend_from_slice(&SDR_SURFACE_FORMATS);
+        formats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn formats(settings: &DisplaySettings, extended_color_spaces: bool) -> Vec<vk::Format> {
+        settings
+            .get_preferred_surface_formats(extended_color_spaces)
+            .iter()
+            .map(|surface_format| surface_format.format)
+            .collect()
+    }
+
+    #[test]
+    fn encoding_follows_color_space_and_format() {
+        let encoding = |format, color_space| {
+            OutputEncoding::from_surface_format(surface_format(format, color_space))
+        };
+        let srgb = vk::ColorSpaceKHR::SRGB_NONLINEAR;
+        assert_eq!(
+            encoding(vk::Format::B8G8R8A8_UNORM, srgb),
+            OutputEncoding::Srgb
+        );
+        assert_eq!(
+            encoding(vk::Format::B8G8R8A8_SRGB, srgb),
+            OutputEncoding::Linear
+        );
+        assert_eq!(
+            encoding(
+                vk::Format::A2B10G10R10_UNORM_PACK32,
+                vk::ColorSpaceKHR::HDR10_ST2084_EXT
+            ),
+            OutputEncoding::Pq
+        );
+        assert_eq!(
+            encoding(
+                vk::Format::R16G16B16A16_SFLOAT,
+                vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT
+            ),
+            OutputEncoding::ScRgb
+        );
+    }
+
+    #[test]
+    fn sdr_lists_only_sdr_formats() {
+        let settings = DisplaySettings::default();
+        let sdr: Vec<vk::Format> = SDR_SURFACE_FORMATS
+            .iter()
+            .map(|surface_format| surface_format.format)
+            .collect();
+        assert_eq!(formats(&settings, true), sdr);
+        assert!(settings
+            .get_preferred_surface_formats(true)
+            .iter()
+            .all(|surface_format| surface_format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR));
+    }
+
+    #[test]
+    fn hdr_prefers_hdr_formats_with_sdr_fallback() {
+        let settings = DisplaySettings {
+            hdr: true,
+            ..Default::default()
+        };
+        let preferred = formats(&settings, true);
+        assert_eq!(
+            preferred.len(),
+            HDR_SURFACE_FORMATS.len() + SDR_SURFACE_FORMATS.len()
+        );
+        assert_eq!(preferred[0], vk::Format::A2B10G10R10_UNORM_PACK32);
+        assert_eq!(
+            preferred[HDR_SURFACE_FORMATS.len()],
+            vk::Format::B8G8R8A8_UNORM
+        );
+        assert_eq!(formats(&settings, false).len(), SDR_SURFACE_FORMATS.len());
+    }
+}
@@ -1,4 +1,5 @@
 use crate::lv;
+use crate::screenshot::PendingScreenshot;
 
 pub struct FrameData {
     pub pool: lv::CommandPool,
@@ -7,5 +8,30 @@ pub struct FrameData {
     // Sync
     pub swapchain_semaphore: lv::Semaphore, // Indicate when image has been acquired
     pub render_semaphore: lv::Semaphore,    // Indicated when render of queue is done for GPU
-    pub render_fence: lv::Fence,            // Indicate when render of queue is done for CPU
+
+    /// Camera matrices for this frame, read by shaders through its device address
+    pub camera_buffer: lv::AllocatedBuffer,
+    /// Instance data of this frame's draw list, grown when the scene outgrows it
+    pub instance_buffer: lv::AllocatedBuffer,
+    /// Material parameters for this frame, indexed by material id
+    pub material_buffer: lv::AllocatedBuffer,
+    /// Lighting header followed by the lights, see `light::get_lighting_data`
+    pub light_buffer: lv::AllocatedBuffer,
+    /// Lights binned into clusters by the light culling pass
+    pub cluster_buffer: lv::AllocatedBuffer,
+    /// Shadow cascades fitted to this frame's camera
+    pub shadow_buffer: lv::AllocatedBuffer,
+    /// Batched overlay vertices and indices, grown when the overlay outgrows them
+    pub overlay_vertex_buffer: lv::AllocatedBuffer,
+    pub overlay_index_buffer: lv::AllocatedBuffer,
+
+    /// Screenshot copied during this frame, saved once the frame has finished on the GPU
+    pub screenshot: Option<PendingScreenshot>,
+}
+
+/// Value the frame timeline reaches once the GPU has finished frame `frame`.
+///
+/// The timeline starts at 0, so frame 0 completes at 1, frame 1 at 2 and so on.
+pub fn frame_complete_value(frame: u64) -> u64 {
+    frame + 1
 }
@@ -0,0 +1,270 @@
+use crate::utility::parse_env;
+use ash::vk;
+use std::time::{Duration, Instant};
+
+/// Environment variable selecting the present mode by name, e.g. `fifo`
+const PRESENT_MODE_ENV: &str = "LV_PRESENT_MODE";
+/// Environment variable enabling the frame limiter at the given frames per second
+const TARGET_FPS_ENV: &str = "LV_TARGET_FPS";
+/// How long before the deadline the limiter stops sleeping and spins, as sleeps overshoot
+const SPIN_DURATION: Duration = Duration::from_millis(1);
+/// How often frame time statistics are summarized
+pub const STATS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How presented images are queued up for the display
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PresentMode {
+    /// Vsync, presenting every image in order and blocking when the queue is full
+    Fifo,
+    /// Vsync, but late images are presented right away and may tear
+    FifoRelaxed,
+    /// Vsync without blocking, newer images replace queued ones
+    Mailbox,
+    /// No vsync, images are presented right away and may tear
+    Immediate,
+}
+
+impl PresentMode {
+    pub const ALL: [PresentMode; 4] = [
+        PresentMode::Fifo,
+        PresentMode::FifoRelaxed,
+        PresentMode::Mailbox,
+        PresentMode::Immediate,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PresentMode::Fifo => "fifo",
+            PresentMode::FifoRelaxed => "fifo_relaxed",
+            PresentMode::Mailbox => "mailbox",
+            PresentMode::Immediate => "immediate",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<PresentMode> {
+        PresentMode::ALL
+            .iter()
+            .copied()
+            .find(|mode| mode.name() == name)
+    }
+
+    pub fn from_vk(mode: vk::PresentModeKHR) -> Option<PresentMode> {
+        PresentMode::ALL
+            .iter()
+            .copied()
+            .find(|present_mode| present_mode.get_vk() == mode)
+    }
+
+    /// The mode after this one, wrapping around
+    pub fn next(&self) -> PresentMode {
+        let index = PresentMode::ALL
+            .iter()
+            .position(|mode| mode == self)
+            .unwrap();
+        PresentMode::ALL[(index + 1) % PresentMode::ALL.len()]
+    }
+
+    pub fn get_vk(&self) -> vk::PresentModeKHR {
+        match self {
+            PresentMode::Fifo => vk::PresentModeKHR::FIFO,
+            PresentMode::FifoRelaxed => vk::PresentModeKHR::FIFO_RELAXED,
+            PresentMode::Mailbox => vk::PresentModeKHR::MAILBOX,
+            PresentMode::Immediate => vk::PresentModeKHR::IMMEDIATE,
+        }
+    }
+
+    pub fn is_vsync(&self) -> bool {
+        matches!(self, PresentMode::Fifo | PresentMode::FifoRelaxed)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FramePacingSettings {
+    /// Requested present mode, the swapchain falls back to FIFO when it is not supported
+    pub present_mode: PresentMode,
+    /// Frames per second the CPU limiter holds the frame rate to
+    pub target_fps: f32,
+    pub limit_frame_rate: bool,
+}
+
+impl Default for FramePacingSettings {
+    fn default() -> Self {
+        FramePacingSettings {
+            present_mode: PresentMode::Mailbox,
+            target_fps: 60.0,
+            limit_frame_rate: false,
+        }
+    }
+}
+
+impl FramePacingSettings {
+    /// Default settings with the present mode and frame limit overridden from the environment
+    pub fn from_env() -> FramePacingSettings {
+        let mut settings = FramePacingSettings::default();
+        if let Ok(name) = std::env::var(PRESENT_MODE_ENV) {
+            match PresentMode::from_name(name.trim()) {
+                Some(mode) => settings.present_mode = mode,
+                None => log::warn!("Ignoring unknown {}={:?}", PRESENT_MODE_ENV, name),
+            }
+        }
+        if let Some(target_fps) = parse_env::<f32>(TARGET_FPS_ENV) {
+            settings.target_fps = target_fps.clamp(1.0, 1000.0);
+            settings.limit_frame_rate = true;
+        }
+        settings
+    }
+}
+
+/// Holds the CPU back so frames start no more often than the target frame rate
+pub struct FrameLimiter {
+    next_frame: Instant,
+}
+
+impl Default for FrameLimiter {
+    fn default() -> Self {
+        FrameLimiter {
+            next_frame: Instant::now(),
+        }
+    }
+}
+
+impl FrameLimiter {
+    /// Block until the next frame is due at `target_fps`
+    pub fn wait(&mut self, target_fps: f32) {
+        let frame_duration = Duration::from_secs_f32(1.0 / target_fps);
+        let now = Instant::now();
+        if self.next_frame > now {
+            let remaining = self.next_frame - now;
+            if remaining > SPIN_DURATION {
+                std::thread::sleep(remaining - SPIN_DURATION);
+            }
+            while Instant::now() < self.next_frame {
+                std::hint::spin_loop();
+            }
+        } else {
+            // Frames that ran late do not make the following ones catch up
+            self.next_frame = now;
+        }
+        self.next_frame += frame_duration;
+    }
+}
+
+/// Summary of the frame times over a [`FrameTimeStats`] interval, in milliseconds
+#[derive(Clone, Copy, Debug)]
+pub struct FrameTimeSummary {
+    pub min: f32,
+    pub average: f32,
+    /// 99th percentile, the frame time only the slowest 1% of frames exceed
+    pub p99: f32,
+}
+
+/// Frame times collected over [`STATS_INTERVAL`]
+pub struct FrameTimeStats {
+    frame_times: Vec<f32>,
+    interval_start: Instant,
+}
+
+impl Default for FrameTimeStats {
+    fn default() -> Self {
+        FrameTimeStats {
+            frame_times: Vec::new(),
+            interval_start: Instant::now(),
+        }
+    }
+}
+
+impl FrameTimeStats {
+    pub fn record(&mut self, seconds: f32) {
+        self.frame_times.push(seconds * 1000.0);
+    }
+
+    /// Summarize and reset the collected frame times once the interval is over
+    pub fn take_summary(&mut self) -> Option<FrameTimeSummary> {
+        if self.interval_start.elapsed() < STATS_INTERVAL || self.frame_times.is_empty() {
+            return None;
+        }
+        self.interval_start = Instant::now();
+        let mut frame_times = std::mem::take(&mut self.frame_times);
+        frame_times.sort_by(f32::total_cmp);
+        let p99_index = ((frame_times.len() as f32 * 0.99).ceil() as usize).max(1) - 1;
+        Some(FrameTimeSummary {
+            min: frame_times[0],
+            average: frame_times.iter().sum::<f32>() / frame_times.len() as f32,
+            p99: frame_times[p99_index],
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Stats whose interval is already over, holding frame times of 1 to `count` milliseconds
+    fn stats_with_frames(count: u32) -> FrameTimeStats {
+        let mut stats = FrameTimeStats {
+            frame_times: Vec::new(),
+            interval_start: Instant::now() - STATS_INTERVAL,
+        };
+        // Recorded in reverse to check the summary sorts them
+        for milliseconds in (1..=count).rev() {
+            stats.record(milliseconds as f32 / 1000.0);
+        }
+        stats
+    }
+
+    #[test]
+    fn summary_waits_for_the_interval() {
+        let mut stats = FrameTimeStats::default();
+        stats.record(0.016);
+        assert!(stats.take_summary().is_none());
+        assert!(stats_with_frames(0).take_summary().is_none());
+    }
+
+    #[test]
+    fn summary_resets_the_interval() {
+        let mut stats = stats_with_frames(10);
+        assert!(stats.take_summary().is_some());
+        assert!(stats.frame_times.is_empty());
+        stats.record(0.016);
+        assert!(stats.take_summary().is_none());
+    }
+
+    #[test]
+    fn p99_is_exceeded_by_one_percent_of_frames() {
+        let p99 = |count| stats_with_frames(count).take_summary().unwrap().p99;
+        assert!((p99(100) - 99.0).abs() < 1e-3);
+        assert!((p99(200) - 198.0).abs() < 1e-3);
+        // With fewer than 100 frames the slowest one is the 99th percentile
+        assert!((p99(10) - 10.0).abs() < 1e-3);
+        assert!((p99(1) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn summary_min_and_average() {
+        let summary = stats_with_frames(4).take_summary().unwrap();
+        assert!((summary.min - 1.0).abs() < 1e-3);
+        assert!((summary.average - 2.5).abs() < 1e-3);
+    }
+
+    #[test]
+    fn limiter_spaces_frames_at_the_target_rate() {
+        let mut limiter = FrameLimiter::default();
+        let start = Instant::now();
+        for _ in 0..5 {
+            limiter.wait(100.0);
+        }
+        // The first frame is due immediately
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn limiter_does_not_catch_up_after_a_late_frame() {
+        let mut limiter = FrameLimiter::default();
+        limiter.wait(100.0);
+        std::thread::sleep(Duration::from_millis(50));
+        limiter.wait(100.0);
+        let start = Instant::now();
+        limiter.wait(100.0);
+        assert!(start.elapsed() >= Duration::from_millis(9));
+    }
+}
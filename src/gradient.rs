@@ -0,0 +1,113 @@
+use crate::{lv, VulkanApp};
+use ash::vk::{self, TaggedStructure};
+use glam::Vec4;
+use std::ffi::CString;
+use std::sync::Arc;
+
+/// Colors of the background gradient, see `shaders/gradient.comp`
+#[derive(Clone, Copy, Debug)]
+pub struct GradientSettings {
+    /// Linear color at the top of the draw image
+    pub top: Vec4,
+    /// Linear color at the bottom of the draw image
+    pub bottom: Vec4,
+}
+
+impl Default for GradientSettings {
+    fn default() -> Self {
+        GradientSettings {
+            top: Vec4::new(1.0, 0.0, 0.0, 1.0),
+            bottom: Vec4::new(0.0, 0.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[repr(C)]
+struct ComputePushConstants {
+    data1: [f32; 4],
+    data2: [f32; 4],
+    data3: [f32; 4],
+    data4: [f32; 4],
+    image: u32,
+}
+
+impl VulkanApp {
+    pub fn init_background_pipelines(
+        device: Arc<lv::Device>,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+    ) -> lv::ComputePipeline {
+        let draw_shader = lv::Shader::new(
+            std::path::Path::new("./shaders/gradient.comp.spv"),
+            device.clone(),
+            None,
+        );
+        let shader_entry_point = CString::new("main").unwrap();
+        let shader_stage_ci = vk::PipelineShaderStageCreateInfo {
+            s_type: vk::PipelineShaderStageCreateInfo::STRUCTURE_TYPE,
+            stage: vk::ShaderStageFlags::COMPUTE,
+            module: draw_shader.handle,
+            p_name: shader_entry_point.as_ptr(),
+            ..Default::default()
+        };
+        let push_constant = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::COMPUTE,
+            offset: 0,
+            size: std::mem::size_of::<ComputePushConstants>() as u32,
+        };
+        println!(
+            "Size: {:?}",
+            std::mem::size_of::<ComputePushConstants>() as u32
+        );
+        let pipeline_builder = lv::ComputePipelineBuilder::new()
+            .set_name("gradient")
+            .attach_stages(shader_stage_ci)
+            .set_layouts(vec![descriptor_set_layout])
+            .attach_push_constant(push_constant);
+        let pipeline = lv::ComputePipeline::from_builder(pipeline_builder, device.clone());
+        pipeline
+    }
+
+    pub fn draw_background(&self) {
+        let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
+        let _label = lv::DebugLabel::new(&self.logical_device, command_buffer, "gradient");
+        let _scope = self.profiler.scope(command_buffer, "gradient");
+        unsafe {
+            self.logical_device.handle.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.gradient_pipeline.get_handle(),
+            );
+            self.logical_device.handle.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::COMPUTE,
+                self.gradient_pipeline.get_layout(),
+                0,
+                &[*self.gpu_resource_table.get_descriptor()],
+                &[],
+            );
+            let pc = ComputePushConstants {
+                data1: self.gradient_settings.top.to_array(),
+                data2: self.gradient_settings.bottom.to_array(),
+                data3: glam::Vec4::ZERO.to_array(),
+                data4: glam::Vec4::ZERO.to_array(),
+                image: self.targets.draw_image_index,
+            };
+            self.logical_device.handle.cmd_push_constants(
+                command_buffer,
+                self.gradient_pipeline.get_layout(),
+                vk::ShaderStageFlags::COMPUTE,
+                0,
+                std::slice::from_raw_parts(
+                    &pc as *const _ as *const u8,
+                    std::mem::size_of::<ComputePushConstants>(),
+                ),
+            );
+            self.logical_device.handle.cmd_dispatch(
+                command_buffer,
+                (self.draw_extent.width as f32 / 16.0f32).ceil() as u32,
+                (self.draw_extent.height as f32 / 16.0f32).ceil() as u32,
+                1,
+            );
+        }
+    }
+}
@@ -0,0 +1,780 @@
+use glam::Vec2;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use winit::event::{DeviceEvent, ElementState, MouseButton, MouseScrollDelta, WindowEvent};
+use winit::keyboard::{KeyCode, ModifiersState, PhysicalKey};
+
+/// Everything the application can be asked to do from the keyboard or mouse
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    MoveFast,
+    /// Held to turn the camera with the mouse
+    Look,
+    ToggleCameraMode,
+    /// Switch between perspective and orthographic projection
+    ToggleProjection,
+    Screenshot,
+    ScreenshotExr,
+    /// Screenshot of the presented image, with the overlay and display encoding applied
+    ScreenshotSwapchain,
+    CycleTonemapOperator,
+    ToggleAutoExposure,
+    IncreaseExposure,
+    DecreaseExposure,
+    ToggleBloom,
+    CycleAntiAliasing,
+    IncreaseRenderScale,
+    DecreaseRenderScale,
+    CycleUpscaleFilter,
+    CyclePresentMode,
+    ToggleFrameLimiter,
+    ToggleOverlay,
+    /// Take the satellites out of the demo scene, or put them back
+    ToggleSatellites,
+    /// Read the bindings file again
+    ReloadBindings,
+}
+
+impl Action {
+    pub const ALL: [Action; 27] = [
+        Action::MoveForward,
+        Action::MoveBackward,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveFast,
+        Action::Look,
+        Action::ToggleCameraMode,
+        Action::ToggleProjection,
+        Action::Screenshot,
+        Action::ScreenshotExr,
+        Action::ScreenshotSwapchain,
+        Action::CycleTonemapOperator,
+        Action::ToggleAutoExposure,
+        Action::IncreaseExposure,
+        Action::DecreaseExposure,
+        Action::ToggleBloom,
+        Action::CycleAntiAliasing,
+        Action::IncreaseRenderScale,
+        Action::DecreaseRenderScale,
+        Action::CycleUpscaleFilter,
+        Action::CyclePresentMode,
+        Action::ToggleFrameLimiter,
+        Action::ToggleOverlay,
+        Action::ToggleSatellites,
+        Action::ReloadBindings,
+    ];
+
+    /// Name used for the action in binding files
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::MoveForward => "move_forward",
+            Action::MoveBackward => "move_backward",
+            Action::MoveLeft => "move_left",
+            Action::MoveRight => "move_right",
+            Action::MoveUp => "move_up",
+            Action::MoveDown => "move_down",
+            Action::MoveFast => "move_fast",
+            Action::Look => "look",
+            Action::ToggleCameraMode => "toggle_camera_mode",
+            Action::ToggleProjection => "toggle_projection",
+            Action::Screenshot => "screenshot",
+            Action::ScreenshotExr => "screenshot_exr",
+            Action::ScreenshotSwapchain => "screenshot_swapchain",
+            Action::CycleTonemapOperator => "cycle_tonemap_operator",
+            Action::ToggleAutoExposure => "toggle_auto_exposure",
+            Action::IncreaseExposure => "increase_exposure",
+            Action::DecreaseExposure => "decrease_exposure",
+            Action::ToggleBloom => "toggle_bloom",
+            Action::CycleAntiAliasing => "cycle_anti_aliasing",
+            Action::IncreaseRenderScale => "increase_render_scale",
+            Action::DecreaseRenderScale => "decrease_render_scale",
+            Action::CycleUpscaleFilter => "cycle_upscale_filter",
+            Action::CyclePresentMode => "cycle_present_mode",
+            Action::ToggleFrameLimiter => "toggle_frame_limiter",
+            Action::ToggleOverlay => "toggle_overlay",
+            Action::ToggleSatellites => "toggle_satellites",
+            Action::ReloadBindings => "reload_bindings",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name() == name)
+    }
+}
+
+/// Physical key or mouse button a binding is triggered by
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+/// A button together with the modifiers that have to be held along with it.
+///
+/// When several bindings share a button, the one requiring the most held modifiers wins, so
+/// `Shift+F12` does not also trigger a plain `F12` binding.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Binding {
+    pub button: Button,
+    pub modifiers: ModifiersState,
+}
+
+impl Binding {
+    pub fn key(key: KeyCode) -> Self {
+        Binding {
+            button: Button::Key(key),
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    pub fn mouse(button: MouseButton) -> Self {
+        Binding {
+            button: Button::Mouse(button),
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    pub fn with_modifiers(mut self, modifiers: ModifiersState) -> Self {
+        self.modifiers = modifiers;
+        self
+    }
+
+    /// Parse a binding such as `W`, `Shift+F12`, `Ctrl+Alt+Space` or `MouseRight`
+    pub fn parse(value: &str) -> Option<Binding> {
+        let mut modifiers = ModifiersState::empty();
+        let mut parts: Vec<&str> = value.split('+').map(str::trim).collect();
+        let button = parts.pop()?;
+        for modifier in parts {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "shift" => ModifiersState::SHIFT,
+                "ctrl" | "control" => ModifiersState::CONTROL,
+                "alt" => ModifiersState::ALT,
+                "super" | "meta" => ModifiersState::SUPER,
+                _ => return None,
+            };
+        }
+        let button = match button.to_ascii_lowercase().as_str() {
+            "mouseleft" => Button::Mouse(MouseButton::Left),
+            "mouseright" => Button::Mouse(MouseButton::Right),
+            "mousemiddle" => Button::Mouse(MouseButton::Middle),
+            _ => Button::Key(parse_key(button)?),
+        };
+        Some(Binding { button, modifiers })
+    }
+}
+
+/// Map the name of a key, as printed by `KeyCode`'s `Debug` or as a single letter or digit, back
+/// to the key
+fn parse_key(name: &str) -> Option<KeyCode> {
+    let name = name.to_ascii_lowercase();
+    let key = match name.as_str() {
+        "space" => KeyCode::Space,
+        "tab" => KeyCode::Tab,
+        "enter" => KeyCode::Enter,
+        "escape" | "esc" => KeyCode::Escape,
+        "backspace" => KeyCode::Backspace,
+        "minus" => KeyCode::Minus,
+        "equal" => KeyCode::Equal,
+        "bracketleft" => KeyCode::BracketLeft,
+        "bracketright" => KeyCode::BracketRight,
+        "up" | "arrowup" => KeyCode::ArrowUp,
+        "down" | "arrowdown" => KeyCode::ArrowDown,
+        "left" | "arrowleft" => KeyCode::ArrowLeft,
+        "right" | "arrowright" => KeyCode::ArrowRight,
+        "shiftleft" | "lshift" => KeyCode::ShiftLeft,
+        "shiftright" | "rshift" => KeyCode::ShiftRight,
+        "controlleft" | "lctrl" => KeyCode::ControlLeft,
+        "controlright" | "rctrl" => KeyCode::ControlRight,
+        "altleft" | "lalt" => KeyCode::AltLeft,
+        "altright" | "ralt" => KeyCode::AltRight,
+        _ => {
+            const LETTERS: [KeyCode; 26] = [
+                KeyCode::KeyA,
+                KeyCode::KeyB,
+                KeyCode::KeyC,
+                KeyCode::KeyD,
+                KeyCode::KeyE,
+                KeyCode::KeyF,
+                KeyCode::KeyG,
+                KeyCode::KeyH,
+                KeyCode::KeyI,
+                KeyCode::KeyJ,
+                KeyCode::KeyK,
+                KeyCode::KeyL,
+                KeyCode::KeyM,
+                KeyCode::KeyN,
+                KeyCode::KeyO,
+                KeyCode::KeyP,
+                KeyCode::KeyQ,
+                KeyCode::KeyR,
+                KeyCode::KeyS,
+                KeyCode::KeyT,
+                KeyCode::KeyU,
+                KeyCode::KeyV,
+                KeyCode::KeyW,
+                KeyCode::KeyX,
+                KeyCode::KeyY,
+                KeyCode::KeyZ,
+            ];
+            const DIGITS: [KeyCode; 10] = [
+                KeyCode::Digit0,
+                KeyCode::Digit1,
+                KeyCode::Digit2,
+                KeyCode::Digit3,
+                KeyCode::Digit4,
+                KeyCode::Digit5,
+                KeyCode::Digit6,
+                KeyCode::Digit7,
+                KeyCode::Digit8,
+                KeyCode::Digit9,
+            ];
+            const FUNCTION_KEYS: [KeyCode; 12] = [
+                KeyCode::F1,
+                KeyCode::F2,
+                KeyCode::F3,
+                KeyCode::F4,
+                KeyCode::F5,
+                KeyCode::F6,
+                KeyCode::F7,
+                KeyCode::F8,
+                KeyCode::F9,
+                KeyCode::F10,
+                KeyCode::F11,
+                KeyCode::F12,
+            ];
+            let name = name
+                .strip_prefix("key")
+                .or_else(|| name.strip_prefix("digit"))
+                .unwrap_or(&name);
+            let bytes = name.as_bytes();
+            if bytes.len() == 1 && bytes[0].is_ascii_lowercase() {
+                LETTERS[(bytes[0] - b'a') as usize]
+            } else if bytes.len() == 1 && bytes[0].is_ascii_digit() {
+                DIGITS[(bytes[0] - b'0') as usize]
+            } else if let Some(number) = name
+                .strip_prefix('f')
+                .and_then(|number| number.parse::<usize>().ok())
+                .filter(|number| (1..=12).contains(number))
+            {
+                FUNCTION_KEYS[number - 1]
+            } else {
+                return None;
+            }
+        }
+    };
+    Some(key)
+}
+
+/// Which bindings trigger each [`Action`]
+#[derive(Clone, Debug)]
+pub struct InputMap {
+    bindings: HashMap<Action, Vec<Binding>>,
+}
+
+impl Default for InputMap {
+    fn default() -> Self {
+        let mut map = InputMap::empty();
+        map.bind(Action::MoveForward, Binding::key(KeyCode::KeyW));
+        map.bind(Action::MoveBackward, Binding::key(KeyCode::KeyS));
+        map.bind(Action::MoveLeft, Binding::key(KeyCode::KeyA));
+        map.bind(Action::MoveRight, Binding::key(KeyCode::KeyD));
+        map.bind(Action::MoveUp, Binding::key(KeyCode::KeyE));
+        map.bind(Action::MoveDown, Binding::key(KeyCode::KeyQ));
+        map.bind(Action::MoveFast, Binding::key(KeyCode::ShiftLeft));
+        map.bind(Action::MoveFast, Binding::key(KeyCode::ShiftRight));
+        map.bind(Action::Look, Binding::mouse(MouseButton::Right));
+        map.bind(Action::ToggleCameraMode, Binding::key(KeyCode::Tab));
+        map.bind(Action::ToggleProjection, Binding::key(KeyCode::KeyP));
+        map.bind(Action::Screenshot, Binding::key(KeyCode::F12));
+        map.bind(
+            Action::ScreenshotExr,
+            Binding::key(KeyCode::F12).with_modifiers(ModifiersState::SHIFT),
+        );
+        map.bind(
+            Action::ScreenshotSwapchain,
+            Binding::key(KeyCode::F12).with_modifiers(ModifiersState::CONTROL),
+        );
+        map.bind(Action::CycleTonemapOperator, Binding::key(KeyCode::KeyT));
+        map.bind(Action::ToggleAutoExposure, Binding::key(KeyCode::KeyY));
+        map.bind(Action::IncreaseExposure, Binding::key(KeyCode::Equal));
+        map.bind(Action::DecreaseExposure, Binding::key(KeyCode::Minus));
+        map.bind(Action::ToggleBloom, Binding::key(KeyCode::KeyB));
+        map.bind(Action::CycleAntiAliasing, Binding::key(KeyCode::KeyX));
+        map.bind(
+            Action::IncreaseRenderScale,
+            Binding::key(KeyCode::BracketRight),
+        );
+        map.bind(
+            Action::DecreaseRenderScale,
+            Binding::key(KeyCode::BracketLeft),
+        );
+        map.bind(Action::CycleUpscaleFilter, Binding::key(KeyCode::KeyU));
+        map.bind(Action::CyclePresentMode, Binding::key(KeyCode::KeyV));
+        map.bind(Action::ToggleFrameLimiter, Binding::key(KeyCode::KeyL));
+        map.bind(Action::ToggleOverlay, Binding::key(KeyCode::F1));
+        map.bind(Action::ToggleSatellites, Binding::key(KeyCode::KeyG));
+        map.bind(Action::ReloadBindings, Binding::key(KeyCode::F5));
+        map
+    }
+}
+
+impl InputMap {
+    pub fn empty() -> Self {
+        InputMap {
+            bindings: HashMap::new(),
+        }
+    }
+
+    pub fn bind(&mut self, action: Action, binding: Binding) {
+        let bindings = self.bindings.entry(action).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn unbind_all(&mut self, action: Action) {
+        self.bindings.remove(&action);
+    }
+
+    pub fn get_bindings(&self, action: Action) -> &[Binding] {
+        self.bindings
+            .get(&action)
+            .map(|bindings| bindings.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Apply overrides in the form of one `action = binding, binding` per line.
+    ///
+    /// Listed actions lose their previous bindings, an empty list unbinds them. Empty lines and
+    /// lines starting with `#` are ignored.
+    pub fn apply_config(&mut self, config: &str) -> Result<(), String> {
+        for (line_number, line) in config.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, bindings) = line
+                .split_once('=')
+                .ok_or_else(|| format!("line {}: expected `action = bindings`", line_number + 1))?;
+            let action = Action::from_name(name.trim()).ok_or_else(|| {
+                format!("line {}: unknown action {:?}", line_number + 1, name.trim())
+            })?;
+            let mut parsed = Vec::new();
+            for binding in bindings.split(',').map(str::trim).filter(|b| !b.is_empty()) {
+                parsed.push(Binding::parse(binding).ok_or_else(|| {
+                    format!("line {}: invalid binding {:?}", line_number + 1, binding)
+                })?);
+            }
+            self.unbind_all(action);
+            for binding in parsed {
+                self.bind(action, binding);
+            }
+        }
+        Ok(())
+    }
+
+    /// Load the default bindings with the overrides in `path` applied, if the file exists
+    pub fn load(path: &Path) -> Self {
+        let mut map = InputMap::default();
+        match std::fs::read_to_string(path) {
+            Ok(config) => {
+                if let Err(err) = map.apply_config(&config) {
+                    log::warn!("Ignoring invalid bindings in {}: {}", path.display(), err);
+                    map = InputMap::default();
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => log::warn!("Failed to read bindings from {}: {}", path.display(), err),
+        }
+        map
+    }
+}
+
+/// Keyboard and mouse state gathered from winit events over a frame.
+///
+/// Feed it every window and device event, query it while updating the frame and call
+/// [`Input::end_frame`] once the frame is done to clear the per-frame edges and deltas.
+pub struct Input {
+    map: InputMap,
+    held: HashSet<Button>,
+    pressed: HashSet<Button>,
+    released: HashSet<Button>,
+    modifiers: ModifiersState,
+    cursor_position: Option<Vec2>,
+    cursor_delta: Vec2,
+    mouse_motion: Vec2,
+    scroll: f32,
+    focused: bool,
+}
+
+impl Input {
+    pub fn new(map: InputMap) -> Self {
+        Input {
+            map,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            modifiers: ModifiersState::empty(),
+            cursor_position: None,
+            cursor_delta: Vec2::ZERO,
+            mouse_motion: Vec2::ZERO,
+            scroll: 0.0,
+            focused: true,
+        }
+    }
+
+    pub fn get_map(&self) -> &InputMap {
+        &self.map
+    }
+
+    pub fn get_map_mut(&mut self) -> &mut InputMap {
+        &mut self.map
+    }
+
+    fn set_button(&mut self, button: Button, down: bool) {
+        if down {
+            if self.held.insert(button) {
+                self.pressed.insert(button);
+            }
+        } else if self.held.remove(&button) {
+            self.released.insert(button);
+        }
+    }
+
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput { event, .. } => {
+                if let PhysicalKey::Code(key) = event.physical_key {
+                    self.set_button(Button::Key(key), event.state == ElementState::Pressed);
+                }
+            }
+            WindowEvent::ModifiersChanged(modifiers) => {
+                self.modifiers = modifiers.state();
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.set_button(Button::Mouse(*button), *state == ElementState::Pressed);
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = Vec2::new(position.x as f32, position.y as f32);
+                if let Some(previous) = self.cursor_position {
+                    self.cursor_delta += position - previous;
+                }
+                self.cursor_position = Some(position);
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.cursor_position = None;
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(position) => position.y as f32 / 40.0,
+                };
+            }
+            WindowEvent::Focused(focused) => {
+                self.focused = *focused;
+                if !focused {
+                    // Releases that happen while unfocused never arrive
+                    let held: Vec<Button> = self.held.iter().copied().collect();
+                    for button in held {
+                        self.set_button(button, false);
+                    }
+                    self.modifiers = ModifiersState::empty();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Raw mouse motion is used for [`Input::mouse_motion`], as it keeps working when the
+    /// cursor hits the edge of the window
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            if self.focused {
+                self.mouse_motion += Vec2::new(delta.0 as f32, delta.1 as f32);
+            }
+        }
+    }
+
+    /// Clear everything that only lasts a single frame
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.cursor_delta = Vec2::ZERO;
+        self.mouse_motion = Vec2::ZERO;
+        self.scroll = 0.0;
+    }
+
+    /// Whether `binding` is active with the current modifiers, and not shadowed by a more
+    /// specific binding of the same button
+    fn binding_matches(&self, binding: &Binding) -> bool {
+        if !self.modifiers.contains(binding.modifiers) {
+            return false;
+        }
+        let specificity = binding.modifiers.bits().count_ones();
+        !self.map.bindings.values().flatten().any(|other| {
+            other.button == binding.button
+                && self.modifiers.contains(other.modifiers)
+                && other.modifiers.bits().count_ones() > specificity
+        })
+    }
+
+    fn any_binding(&self, action: Action, buttons: &HashSet<Button>) -> bool {
+        self.map
+            .get_bindings(action)
+            .iter()
+            .any(|binding| buttons.contains(&binding.button) && self.binding_matches(binding))
+    }
+
+    /// Whether `action` started this frame
+    pub fn is_pressed(&self, action: Action) -> bool {
+        self.any_binding(action, &self.pressed)
+    }
+
+    /// Whether `action` is currently active
+    pub fn is_held(&self, action: Action) -> bool {
+        self.any_binding(action, &self.held)
+    }
+
+    /// Whether `action` stopped this frame
+    pub fn is_released(&self, action: Action) -> bool {
+        self.any_binding(action, &self.released)
+    }
+
+    /// -1, 0 or 1 depending on which of two opposing actions are held
+    pub fn axis(&self, positive: Action, negative: Action) -> f32 {
+        self.is_held(positive) as i32 as f32 - self.is_held(negative) as i32 as f32
+    }
+
+    pub fn get_modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    /// Cursor position in physical pixels, `None` while it is outside the window
+    pub fn cursor_position(&self) -> Option<Vec2> {
+        self.cursor_position
+    }
+
+    /// How far the cursor moved over the window this frame, in physical pixels
+    pub fn cursor_delta(&self) -> Vec2 {
+        self.cursor_delta
+    }
+
+    /// Raw, unaccelerated mouse movement this frame
+    pub fn mouse_motion(&self) -> Vec2 {
+        self.mouse_motion
+    }
+
+    /// Scroll wheel movement this frame in lines, positive away from the user
+    pub fn scroll(&self) -> f32 {
+        self.scroll
+    }
+
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_keys_and_mouse_buttons() {
+        assert_eq!(Binding::parse("W"), Some(Binding::key(KeyCode::KeyW)));
+        assert_eq!(Binding::parse("KeyW"), Some(Binding::key(KeyCode::KeyW)));
+        assert_eq!(Binding::parse("7"), Some(Binding::key(KeyCode::Digit7)));
+        assert_eq!(Binding::parse("f12"), Some(Binding::key(KeyCode::F12)));
+        assert_eq!(Binding::parse("Esc"), Some(Binding::key(KeyCode::Escape)));
+        assert_eq!(
+            Binding::parse("MouseRight"),
+            Some(Binding::mouse(MouseButton::Right))
+        );
+    }
+
+    #[test]
+    fn parse_modifiers() {
+        assert_eq!(
+            Binding::parse("Shift+F12"),
+            Some(Binding::key(KeyCode::F12).with_modifiers(ModifiersState::SHIFT))
+        );
+        assert_eq!(
+            Binding::parse(" ctrl + alt + Space "),
+            Some(
+                Binding::key(KeyCode::Space)
+                    .with_modifiers(ModifiersState::CONTROL | ModifiersState::ALT)
+            )
+        );
+    }
+
+    #[test]
+    fn parse_rejects_unknown_names() {
+        assert_eq!(Binding::parse(""), None);
+        assert_eq!(Binding::parse("F13"), None);
+        assert_eq!(Binding::parse("Hyper+W"), None);
+        assert_eq!(Binding::parse("Shift+"), None);
+        assert_eq!(Binding::parse("MouseSide"), None);
+    }
+
+    #[test]
+    fn apply_config_replaces_listed_actions() {
+        let mut map = InputMap::default();
+        map.apply_config(
+            "# comment\n\
+             \n\
+             move_forward = Up, Z\n\
+             move_fast =\n",
+        )
+        .unwrap();
+        assert_eq!(
+            map.get_bindings(Action::MoveForward),
+            &[Binding::key(KeyCode::ArrowUp), Binding::key(KeyCode::KeyZ)]
+        );
+        assert!(map.get_bindings(Action::MoveFast).is_empty());
+        assert_eq!(
+            map.get_bindings(Action::MoveBackward),
+            &[Binding::key(KeyCode::KeyS)]
+        );
+    }
+
+    #[test]
+    fn apply_config_reports_the_failing_line() {
+        let mut map = InputMap::default();
+        assert_eq!(
+            map.apply_config("move_left = A\nmove_right D"),
+            Err("line 2: expected `action = bindings`".to_string())
+        );
+        assert_eq!(
+            map.apply_config("jump = Space"),
+            Err("line 1: unknown action \"jump\"".to_string())
+        );
+        assert_eq!(
+            map.apply_config("\nlook = MouseRight, Nope"),
+            Err("line 2: invalid binding \"Nope\"".to_string())
+        );
+    }
+
+    #[test]
+    fn apply_config_keeps_bindings_of_a_failing_line() {
+        let mut map = InputMap::default();
+        assert!(map.apply_config("move_left = Left, Nope").is_err());
+        assert_eq!(
+            map.get_bindings(Action::MoveLeft),
+            &[Binding::key(KeyCode::KeyA)]
+        );
+    }
+
+    fn input_with(map: InputMap, modifiers: ModifiersState) -> Input {
+        let mut input = Input::new(map);
+        input.modifiers = modifiers;
+        input
+    }
+
+    #[test]
+    fn binding_requires_its_modifiers() {
+        let binding = Binding::key(KeyCode::KeyS).with_modifiers(ModifiersState::CONTROL);
+        let mut map = InputMap::empty();
+        map.bind(Action::Screenshot, binding);
+        assert!(!input_with(map.clone(), ModifiersState::empty()).binding_matches(&binding));
+        assert!(input_with(map.clone(), ModifiersState::CONTROL).binding_matches(&binding));
+        assert!(
+            input_with(map, ModifiersState::CONTROL | ModifiersState::SHIFT)
+                .binding_matches(&binding)
+        );
+    }
+
+    #[test]
+    fn more_modifiers_shadow_a_plain_binding() {
+        let plain = Binding::key(KeyCode::KeyS);
+        let ctrl = plain.with_modifiers(ModifiersState::CONTROL);
+        let mut map = InputMap::empty();
+        map.bind(Action::MoveBackward, plain);
+        map.bind(Action::Screenshot, ctrl);
+
+        let input = input_with(map.clone(), ModifiersState::empty());
+        assert!(input.binding_matches(&plain));
+        assert!(!input.binding_matches(&ctrl));
+
+        let input = input_with(map.clone(), ModifiersState::CONTROL);
+        assert!(!input.binding_matches(&plain));
+        assert!(input.binding_matches(&ctrl));
+
+        // Shift is not part of either binding, so it leaves the plain one active
+        let input = input_with(map, ModifiersState::SHIFT);
+        assert!(input.binding_matches(&plain));
+    }
+
+    #[test]
+    fn pressed_action_follows_shadowing() {
+        let mut map = InputMap::empty();
+        map.bind(Action::MoveBackward, Binding::key(KeyCode::KeyS));
+        map.bind(
+            Action::Screenshot,
+            Binding::key(KeyCode::KeyS).with_modifiers(ModifiersState::CONTROL),
+        );
+        let mut input = input_with(map, ModifiersState::CONTROL);
+        input.set_button(Button::Key(KeyCode::KeyS), true);
+        assert!(input.is_pressed(Action::Screenshot));
+        assert!(!input.is_pressed(Action::MoveBackward));
+        input.end_frame();
+        assert!(!input.is_pressed(Action::Screenshot));
+        assert!(input.is_held(Action::Screenshot));
+    }
+
+    fn look_input() -> Input {
+        let mut map = InputMap::empty();
+        map.bind(Action::Look, Binding::mouse(MouseButton::Right));
+        Input::new(map)
+    }
+
+    #[test]
+    fn release_lasts_a_single_frame() {
+        let mut input = look_input();
+        input.set_button(Button::Mouse(MouseButton::Right), true);
+        assert!(!input.is_released(Action::Look));
+        input.end_frame();
+        input.set_button(Button::Mouse(MouseButton::Right), false);
+        assert!(input.is_released(Action::Look));
+        assert!(!input.is_held(Action::Look));
+        input.end_frame();
+        assert!(!input.is_released(Action::Look));
+    }
+
+    #[test]
+    fn release_needs_a_held_button() {
+        let mut input = look_input();
+        input.set_button(Button::Mouse(MouseButton::Right), false);
+        assert!(!input.is_released(Action::Look));
+    }
+
+    #[test]
+    fn press_and_release_in_one_frame_report_both_edges() {
+        let mut input = look_input();
+        input.set_button(Button::Mouse(MouseButton::Right), true);
+        input.set_button(Button::Mouse(MouseButton::Right), false);
+        assert!(input.is_pressed(Action::Look));
+        assert!(input.is_released(Action::Look));
+        assert!(!input.is_held(Action::Look));
+    }
+
+    #[test]
+    fn losing_focus_releases_held_buttons() {
+        let mut input = look_input();
+        input.set_button(Button::Mouse(MouseButton::Right), true);
+        input.end_frame();
+        input.handle_window_event(&WindowEvent::Focused(false));
+        assert!(!input.is_focused());
+        assert!(input.is_released(Action::Look));
+        assert!(!input.is_held(Action::Look));
+    }
+}
@@ -0,0 +1,282 @@
+use crate::utility;
+use crate::{lv, VulkanApp};
+use ash::vk;
+use glam::Vec3;
+use std::sync::Arc;
+
+/// Screen tiles along X and Y and depth slices along Z that lights are binned into
+pub const CLUSTER_GRID: [u32; 3] = [16, 9, 24];
+/// Lights beyond this many in a single cluster are dropped
+pub const MAX_LIGHTS_PER_CLUSTER: u32 = 127;
+
+/// Number of `u32`s each cluster takes in the cluster buffer: its light count and the indices
+pub const CLUSTER_STRIDE: u32 = MAX_LIGHTS_PER_CLUSTER + 1;
+
+pub fn cluster_count() -> u32 {
+    CLUSTER_GRID[0] * CLUSTER_GRID[1] * CLUSTER_GRID[2]
+}
+
+/// Size in bytes of the buffer the light culling pass writes the clusters to
+pub fn cluster_buffer_size() -> u64 {
+    cluster_count() as u64 * CLUSTER_STRIDE as u64 * std::mem::size_of::<u32>() as u64
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LightKind {
+    /// Infinitely far away light shining along the light's direction, like the sun
+    Directional,
+    Point,
+    /// Point light limited to a cone around the light's direction
+    Spot {
+        /// Angle from the direction in radians where the light starts to fall off
+        inner_cone_angle: f32,
+        /// Angle from the direction in radians where the light reaches zero
+        outer_cone_angle: f32,
+    },
+}
+
+/// Punctual light with glTF `KHR_lights_punctual` units: lux for directional lights and candela
+/// otherwise
+#[derive(Clone, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub position: Vec3,
+    /// Direction the light shines in, unused for point lights
+    pub direction: Vec3,
+    /// Linear color
+    pub color: Vec3,
+    pub intensity: f32,
+    /// Distance at which the light is cut off, unused for directional lights
+    pub range: f32,
+}
+
+impl Light {
+    pub fn directional(direction: Vec3, color: Vec3, intensity: f32) -> Self {
+        Light {
+            kind: LightKind::Directional,
+            position: Vec3::ZERO,
+            direction: direction.normalize(),
+            color,
+            intensity,
+            range: f32::INFINITY,
+        }
+    }
+
+    pub fn point(position: Vec3, color: Vec3, intensity: f32, range: f32) -> Self {
+        Light {
+            kind: LightKind::Point,
+            position,
+            direction: Vec3::NEG_Y,
+            color,
+            intensity,
+            range,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn spot(
+        position: Vec3,
+        direction: Vec3,
+        color: Vec3,
+        intensity: f32,
+        range: f32,
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    ) -> Self {
+        Light {
+            kind: LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+            position,
+            direction: direction.normalize(),
+            color,
+            intensity,
+            range,
+        }
+    }
+
+    pub fn get_data(&self) -> LightData {
+        let (kind, spot_scale, spot_offset) = match self.kind {
+            LightKind::Directional => (LIGHT_DIRECTIONAL, 0.0, 1.0),
+            LightKind::Point => (LIGHT_POINT, 0.0, 1.0),
+            LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => {
+                // Angular attenuation is clamp(cos(angle) * scale + offset), see
+                // KHR_lights_punctual
+                let cos_outer = outer_cone_angle.cos();
+                let scale = 1.0 / (inner_cone_angle.cos() - cos_outer).max(0.001);
+                (LIGHT_SPOT, scale, -cos_outer * scale)
+            }
+        };
+        LightData {
+            position: self.position.to_array(),
+            range: self.range,
+            direction: self.direction.to_array(),
+            kind,
+            color: self.color.to_array(),
+            intensity: self.intensity,
+            spot_scale,
+            spot_offset,
+            _padding: [0.0; 2],
+        }
+    }
+}
+
+const LIGHT_DIRECTIONAL: u32 = 0;
+const LIGHT_POINT: u32 = 1;
+const LIGHT_SPOT: u32 = 2;
+
+/// Light as laid out for shaders, see `shaders/lighting.inc.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct LightData {
+    pub position: [f32; 3],
+    pub range: f32,
+    pub direction: [f32; 3],
+    pub kind: u32,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub spot_scale: f32,
+    pub spot_offset: f32,
+    _padding: [f32; 2],
+}
+
+/// Start of the light buffer, followed by the [`LightData`] of every light. Directional lights
+/// come first, they are not binned into clusters.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct LightingHeader {
+    /// Cluster counts along X, Y and Z, and the cluster stride
+    pub cluster_grid: [u32; 4],
+    /// Size of the render target in pixels
+    pub screen_size: [f32; 2],
+    pub near: f32,
+    pub far: f32,
+    pub light_count: u32,
+    pub directional_count: u32,
+    _padding: [u32; 2],
+}
+
+/// Contents of the light buffer for `lights`, with the directional lights moved to the front
+pub fn get_lighting_data(
+    lights: &[Light],
+    screen_size: [f32; 2],
+    near: f32,
+    far: f32,
+) -> (LightingHeader, Vec<LightData>) {
+    let (directional, local): (Vec<&Light>, Vec<&Light>) = lights
+        .iter()
+        .partition(|light| light.kind == LightKind::Directional);
+    let header = LightingHeader {
+        cluster_grid: [
+            CLUSTER_GRID[0],
+            CLUSTER_GRID[1],
+            CLUSTER_GRID[2],
+            CLUSTER_STRIDE,
+        ],
+        screen_size,
+        near,
+        far,
+        light_count: lights.len() as u32,
+        directional_count: directional.len() as u32,
+        _padding: [0; 2],
+    };
+    let data = directional
+        .into_iter()
+        .chain(local)
+        .map(Light::get_data)
+        .collect();
+    (header, data)
+}
+
+/// Push constants of the light culling pass
+#[repr(C)]
+struct LightCullPushConstants {
+    camera: vk::DeviceAddress,
+    lights: vk::DeviceAddress,
+    clusters: vk::DeviceAddress,
+}
+
+impl VulkanApp {
+    /// Compute pipeline binning lights into clusters, see `shaders/light_cull.comp`
+    pub fn create_light_cull_pipeline(device: Arc<lv::Device>) -> lv::ComputePipeline {
+        VulkanApp::create_compute_pipeline(
+            device,
+            std::path::Path::new("./shaders/light_cull.comp.spv"),
+            "light cull",
+            Vec::new(),
+            std::mem::size_of::<LightCullPushConstants>() as u32,
+        )
+    }
+
+    /// A sun, a spot light above the scene and rings of colored point lights over the floor
+    pub fn create_demo_lights() -> Vec<Light> {
+        let mut lights = vec![
+            Light::directional(
+                glam::Vec3::new(-0.4, -1.0, -0.3),
+                glam::Vec3::new(1.0, 0.95, 0.9),
+                2.0,
+            ),
+            Light::spot(
+                glam::Vec3::new(0.0, 3.0, 0.0),
+                glam::Vec3::NEG_Y,
+                glam::Vec3::ONE,
+                15.0,
+                8.0,
+                15.0f32.to_radians(),
+                25.0f32.to_radians(),
+            ),
+        ];
+        const RINGS: u32 = 4;
+        const LIGHTS_PER_RING: u32 = 32;
+        for ring in 0..RINGS {
+            let radius = 1.0 + ring as f32;
+            for index in 0..LIGHTS_PER_RING {
+                let turns = index as f32 / LIGHTS_PER_RING as f32;
+                let angle = turns * std::f32::consts::TAU;
+                // Cycle through the hues around each ring
+                let color = (glam::Vec3::new(0.0, 1.0 / 3.0, 2.0 / 3.0) + turns)
+                    .to_array()
+                    .map(|phase| 0.5 + 0.5 * (phase * std::f32::consts::TAU).cos());
+                lights.push(Light::point(
+                    glam::Vec3::new(radius * angle.cos(), -1.2, radius * angle.sin()),
+                    glam::Vec3::from_array(color),
+                    1.5,
+                    1.0,
+                ));
+            }
+        }
+        lights
+    }
+
+    /// Bin the local lights into the clusters the geometry pass reads
+    pub fn cull_lights(&self) {
+        let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
+        let _label = lv::DebugLabel::new(&self.logical_device, command_buffer, "light culling");
+        let _scope = self.profiler.scope(command_buffer, "light culling");
+        let frame = self.get_current_frame();
+        let push_constants = LightCullPushConstants {
+            camera: frame.camera_buffer.get_device_address(),
+            lights: frame.light_buffer.get_device_address(),
+            clusters: frame.cluster_buffer.get_device_address(),
+        };
+        self.dispatch_compute(
+            &self.light_cull_pipeline,
+            &push_constants,
+            [cluster_count().div_ceil(64), 1, 1],
+            false,
+        );
+        // Make the clusters visible to the fragment shaders of the geometry pass
+        utility::memory_barrier(
+            &self.logical_device.handle,
+            command_buffer,
+            vk::PipelineStageFlags2::COMPUTE_SHADER,
+            vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            vk::AccessFlags2::SHADER_STORAGE_READ,
+        );
+    }
+}
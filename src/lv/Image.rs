@@ -9,9 +9,12 @@ use std::sync::{Arc, Mutex};
 pub struct AllocatedImage {
     handle: vk::Image,
     view: vk::ImageView,
+    /// Single layer views of array images, for rendering into one layer at a time
+    layer_views: Vec<vk::ImageView>,
     allocation: gpu_allocator::vulkan::Allocation,
     extent: vk::Extent3D,
     format: vk::Format,
+    mip_levels: u32,
 
     device: Arc<lv::Device>,
     allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
@@ -23,6 +26,41 @@ impl AllocatedImage {
         image_aspect_flags: ImageAspectFlags,
         device: Arc<lv::Device>,
         allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+        name: Option<&str>,
+    ) -> Self {
+        let view_type = if image_ci.array_layers > 1 {
+            vk::ImageViewType::TYPE_2D_ARRAY
+        } else {
+            vk::ImageViewType::TYPE_2D
+        };
+        Self::with_view_type(image_ci, image_aspect_flags, view_type, device, allocator, name)
+    }
+
+    /// Image viewed as an array even if it has a single layer, with a view of every layer
+    pub fn new_array(
+        image_ci: vk::ImageCreateInfo,
+        image_aspect_flags: ImageAspectFlags,
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+        name: Option<&str>,
+    ) -> Self {
+        Self::with_view_type(
+            image_ci,
+            image_aspect_flags,
+            vk::ImageViewType::TYPE_2D_ARRAY,
+            device,
+            allocator,
+            name,
+        )
+    }
+
+    fn with_view_type(
+        image_ci: vk::ImageCreateInfo,
+        image_aspect_flags: ImageAspectFlags,
+        view_type: vk::ImageViewType,
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+        name: Option<&str>,
     ) -> Self {
         let handle = unsafe { device.handle.create_image(&image_ci, None).unwrap() };
         let requirements = unsafe { device.handle.get_image_memory_requirements(handle) };
@@ -33,7 +71,7 @@ impl AllocatedImage {
             allocation = Some(
                 allocator_lock
                     .allocate(&gpu_allocator::vulkan::AllocationCreateDesc {
-                        name: "Image",
+                        name: name.unwrap_or("Image"),
                         requirements,
                         location: gpu_allocator::MemoryLocation::GpuOnly,
                         linear: true,
@@ -51,18 +89,45 @@ impl AllocatedImage {
             };
         }
 
-        let view_ci =
+        let mut view_ci =
             utility::init::image_view_create_info(image_ci.format, handle, image_aspect_flags);
+        view_ci.view_type = view_type;
         let view = unsafe { device.handle.create_image_view(&view_ci, None).unwrap() };
+        let layer_views: Vec<vk::ImageView> = if view_type == vk::ImageViewType::TYPE_2D_ARRAY {
+            (0..image_ci.array_layers)
+                .map(|layer| {
+                    let mut layer_view_ci = utility::init::image_view_create_info(
+                        image_ci.format,
+                        handle,
+                        image_aspect_flags,
+                    );
+                    layer_view_ci.subresource_range.base_array_layer = layer;
+                    layer_view_ci.subresource_range.layer_count = 1;
+                    unsafe { device.handle.create_image_view(&layer_view_ci, None).unwrap() }
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        if let Some(name) = name {
+            device.set_object_name(handle, name);
+            device.set_object_name(view, &format!("{} view", name));
+            for (layer, layer_view) in layer_views.iter().enumerate() {
+                device.set_object_name(*layer_view, &format!("{} layer {} view", name, layer));
+            }
+        }
         let extent = image_ci.extent;
         let format = image_ci.format;
+        let mip_levels = image_ci.mip_levels;
 
         AllocatedImage {
             handle,
             view,
+            layer_views,
             allocation: allocation.unwrap(),
             extent,
             format,
+            mip_levels,
 
             device,
             allocator,
@@ -77,15 +142,35 @@ impl AllocatedImage {
         self.view
     }
 
+    /// View of a single array layer, only available for images with an array view
+    pub fn get_layer_view(&self, layer: u32) -> vk::ImageView {
+        self.layer_views[layer as usize]
+    }
+
     pub fn get_format(&self) -> vk::Format {
         self.format
     }
+
+    pub fn get_mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    /// Extent of mip level `level`
+    pub fn get_mip_extent(&self, level: u32) -> vk::Extent2D {
+        vk::Extent2D {
+            width: (self.extent.width >> level).max(1),
+            height: (self.extent.height >> level).max(1),
+        }
+    }
 }
 
 impl Drop for AllocatedImage {
     fn drop(&mut self) {
         println!("Dropping image!");
         unsafe {
+            for layer_view in self.layer_views.drain(..) {
+                self.device.handle.destroy_image_view(layer_view, None);
+            }
             self.device.handle.destroy_image_view(self.view, None);
             self.device.handle.destroy_image(self.handle, None);
             let mut allocator = self.allocator.lock().unwrap();
@@ -96,6 +181,56 @@ impl Drop for AllocatedImage {
     }
 }
 
+/// View of a single mip level of an [`AllocatedImage`], so compute passes can write each level of
+/// a mip chain as a storage image. Must not outlive the image
+pub struct ImageView {
+    handle: vk::ImageView,
+
+    device: Arc<lv::Device>,
+}
+
+impl ImageView {
+    pub fn mip(
+        image: &AllocatedImage,
+        level: u32,
+        image_aspect_flags: ImageAspectFlags,
+        name: Option<&str>,
+    ) -> Self {
+        let mut view_ci = utility::init::image_view_create_info(
+            image.get_format(),
+            image.get_handle(),
+            image_aspect_flags,
+        );
+        view_ci.subresource_range.base_mip_level = level;
+        view_ci.subresource_range.level_count = 1;
+        let device = image.device.clone();
+        let handle = unsafe { device.handle.create_image_view(&view_ci, None).unwrap() };
+        if let Some(name) = name {
+            device.set_object_name(handle, name);
+        }
+
+        ImageView { handle, device }
+    }
+}
+
+impl Drop for ImageView {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.handle.destroy_image_view(self.handle, None);
+        }
+    }
+}
+
+impl lv::traits::Resource for ImageView {
+    fn get_descriptor(&self) -> DescriptorInfo {
+        DescriptorInfo::Image(vk::DescriptorImageInfo {
+            image_view: self.handle,
+            image_layout: vk::ImageLayout::GENERAL,
+            sampler: vk::Sampler::null(),
+        })
+    }
+}
+
 impl lv::traits::Resource for AllocatedImage {
     fn get_descriptor(&self) -> DescriptorInfo {
         DescriptorInfo::Image(vk::DescriptorImageInfo {
@@ -0,0 +1,131 @@
+use crate::lv;
+use ash::vk;
+use std::sync::{Arc, Mutex};
+
+pub struct AllocatedBuffer {
+    handle: vk::Buffer,
+    allocation: gpu_allocator::vulkan::Allocation,
+    size: vk::DeviceSize,
+
+    device: Arc<lv::Device>,
+    allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+}
+
+impl AllocatedBuffer {
+    pub fn new(
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        location: gpu_allocator::MemoryLocation,
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+        name: Option<&str>,
+    ) -> Self {
+        let buffer_ci = vk::BufferCreateInfo {
+            s_type: vk::StructureType::BUFFER_CREATE_INFO,
+            size,
+            usage,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            ..Default::default()
+        };
+        let handle = unsafe { device.handle.create_buffer(&buffer_ci, None).unwrap() };
+        let requirements = unsafe { device.handle.get_buffer_memory_requirements(handle) };
+        let allocation = allocator
+            .lock()
+            .unwrap()
+            .allocate(&gpu_allocator::vulkan::AllocationCreateDesc {
+                name: name.unwrap_or("Buffer"),
+                requirements,
+                location,
+                linear: true,
+                allocation_scheme: gpu_allocator::vulkan::AllocationScheme::GpuAllocatorManaged,
+            })
+            .unwrap();
+        unsafe {
+            device
+                .handle
+                .bind_buffer_memory(handle, allocation.memory(), allocation.offset())
+                .unwrap()
+        };
+        if let Some(name) = name {
+            device.set_object_name(handle, name);
+        }
+
+        AllocatedBuffer {
+            handle,
+            allocation,
+            size,
+
+            device,
+            allocator,
+        }
+    }
+
+    pub fn get_handle(&self) -> vk::Buffer {
+        self.handle
+    }
+
+    pub fn get_size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    /// GPU address of the buffer, which must have been created with `SHADER_DEVICE_ADDRESS` usage
+    pub fn get_device_address(&self) -> vk::DeviceAddress {
+        let address_info = vk::BufferDeviceAddressInfo {
+            s_type: vk::StructureType::BUFFER_DEVICE_ADDRESS_INFO,
+            buffer: self.handle,
+            ..Default::default()
+        };
+        unsafe { self.device.handle.get_buffer_device_address(&address_info) }
+    }
+
+    /// Copy `data` to the start of the buffer, which must be host visible
+    pub fn write<T: Copy>(&mut self, data: &T) {
+        self.write_slice(std::slice::from_ref(data));
+    }
+
+    /// Copy `data` to the start of the buffer, which must be host visible
+    pub fn write_slice<T: Copy>(&mut self, data: &[T]) {
+        self.write_slice_at(0, data);
+    }
+
+    /// Copy `data` to `offset` bytes into the buffer, which must be host visible
+    pub fn write_slice_at<T: Copy>(&mut self, offset: usize, data: &[T]) {
+        let size = std::mem::size_of_val(data);
+        assert!(
+            (offset + size) as vk::DeviceSize <= self.size,
+            "Data does not fit in the buffer"
+        );
+        let bytes = unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, size) };
+        self.mapped_slice_mut().expect("Buffer is not host visible")[offset..offset + size]
+            .copy_from_slice(bytes);
+    }
+
+    /// Contents of the buffer, if it was allocated in host-visible memory.
+    ///
+    /// The caller is responsible for making sure the GPU is done writing to it.
+    pub fn mapped_slice(&self) -> Option<&[u8]> {
+        self.allocation
+            .mapped_slice()
+            .map(|slice| &slice[..self.size as usize])
+    }
+
+    /// Writable contents of the buffer, if it was allocated in host-visible memory
+    pub fn mapped_slice_mut(&mut self) -> Option<&mut [u8]> {
+        let size = self.size as usize;
+        self.allocation
+            .mapped_slice_mut()
+            .map(|slice| &mut slice[..size])
+    }
+}
+
+impl Drop for AllocatedBuffer {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.handle.destroy_buffer(self.handle, None);
+        };
+        let mut allocator = self.allocator.lock().unwrap();
+        allocator
+            .free(std::mem::take(&mut self.allocation))
+            .unwrap();
+    }
+}
@@ -10,6 +10,7 @@ impl CommandBuffer {
         command_pool: &lv::CommandPool,
         level: vk::CommandBufferLevel,
         device: &lv::Device,
+        name: Option<&str>,
     ) -> Self {
         let command_buffer_ai = vk::CommandBufferAllocateInfo {
             s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
@@ -26,6 +27,9 @@ impl CommandBuffer {
                 .pop()
                 .unwrap()
         };
+        if let Some(name) = name {
+            device.set_object_name(handle, name);
+        }
         CommandBuffer { handle }
     }
 
@@ -0,0 +1,35 @@
+use crate::lv;
+use ash::vk;
+
+/// Labeled region of a command buffer, shown by validation output and capture tools.
+///
+/// The region is opened on creation and closed when the label is dropped.
+pub struct DebugLabel<'a> {
+    device: &'a lv::Device,
+    command_buffer: vk::CommandBuffer,
+}
+
+impl<'a> DebugLabel<'a> {
+    pub fn new(device: &'a lv::Device, command_buffer: vk::CommandBuffer, name: &str) -> Self {
+        DebugLabel::with_color(device, command_buffer, name, [0.0, 0.0, 0.0, 0.0])
+    }
+
+    pub fn with_color(
+        device: &'a lv::Device,
+        command_buffer: vk::CommandBuffer,
+        name: &str,
+        color: [f32; 4],
+    ) -> Self {
+        device.cmd_begin_label(command_buffer, name, color);
+        DebugLabel {
+            device,
+            command_buffer,
+        }
+    }
+}
+
+impl Drop for DebugLabel<'_> {
+    fn drop(&mut self) {
+        self.device.cmd_end_label(self.command_buffer);
+    }
+}
@@ -2,29 +2,205 @@ use crate::lv;
 use ash::vk;
 use std::ffi::{c_void, CStr};
 use std::ptr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A single message reported by the validation layers
+#[derive(Clone, Debug)]
+pub struct ValidationMessage {
+    pub severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+    pub message_id_name: String,
+    pub message: String,
+    /// Type, raw handle and debug name (if any) of each object the message refers to
+    pub objects: Vec<(vk::ObjectType, u64, Option<String>)>,
+}
+
+impl ValidationMessage {
+    pub fn is_error(&self) -> bool {
+        self.severity
+            .contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR)
+    }
+}
+
+type CaptureSink = Arc<Mutex<Vec<ValidationMessage>>>;
+/// Sinks installed on one messenger, which the callback gets as its user data
+type CaptureSinks = Mutex<Vec<CaptureSink>>;
+
+/// Collects every message a [`DebugMessenger`] reports while it is alive, so automatic captures
+/// can fail on them.
+///
+/// Messages are still logged as usual while a capture is installed. Only the messenger it is
+/// installed on feeds it, so captures of different devices do not see each other's messages.
+/// Messages from instance creation and destruction are only logged.
+pub struct ValidationCapture {
+    sink: CaptureSink,
+    sinks: Arc<CaptureSinks>,
+}
+
+impl ValidationCapture {
+    pub fn install(messenger: &DebugMessenger) -> ValidationCapture {
+        ValidationCapture::attach(messenger.sinks.clone())
+    }
+
+    fn attach(sinks: Arc<CaptureSinks>) -> ValidationCapture {
+        let sink: CaptureSink = Arc::new(Mutex::new(Vec::new()));
+        sinks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(sink.clone());
+        ValidationCapture { sink, sinks }
+    }
+
+    pub fn messages(&self) -> Vec<ValidationMessage> {
+        self.sink
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn errors(&self) -> Vec<ValidationMessage> {
+        self.messages()
+            .into_iter()
+            .filter(|message| message.is_error())
+            .collect()
+    }
+
+    /// Panic with every captured validation error, if there were any
+    pub fn assert_no_errors(&self) {
+        let errors = self.errors();
+        if !errors.is_empty() {
+            let messages: Vec<String> = errors
+                .iter()
+                .map(|error| {
+                    format!(
+                        "[{}] {} {}",
+                        message_type_names(error.message_type),
+                        error.message_id_name,
+                        error.message
+                    )
+                })
+                .collect();
+            panic!(
+                "{} validation error(s) were reported:\n{}",
+                errors.len(),
+                messages.join("\n")
+            );
+        }
+    }
+}
+
+impl Drop for ValidationCapture {
+    fn drop(&mut self) {
+        self.sinks
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .retain(|sink| !Arc::ptr_eq(sink, &self.sink));
+    }
+}
+
+fn severity_to_level(
+    severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+) -> log::Level {
+    if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR) {
+        log::Level::Error
+    } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING) {
+        log::Level::Warn
+    } else if severity.contains(vk::DebugUtilsMessageSeverityFlagsEXT::INFO) {
+        // Debug printf output is reported as validation info, general info is mostly loader noise
+        if message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION) {
+            log::Level::Info
+        } else {
+            log::Level::Debug
+        }
+    } else {
+        log::Level::Trace
+    }
+}
+
+fn message_type_names(message_type: vk::DebugUtilsMessageTypeFlagsEXT) -> String {
+    let mut names: Vec<&str> = Vec::new();
+    if message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::GENERAL) {
+        names.push("General");
+    }
+    if message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION) {
+        names.push("Validation");
+    }
+    if message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE) {
+        names.push("Performance");
+    }
+    if message_type.contains(vk::DebugUtilsMessageTypeFlagsEXT::DEVICE_ADDRESS_BINDING) {
+        names.push("DeviceAddressBinding");
+    }
+    if names.is_empty() {
+        return "Unknown".to_string();
+    }
+    names.join("|")
+}
+
+unsafe fn optional_c_str(pointer: *const std::ffi::c_char) -> Option<String> {
+    if pointer.is_null() {
+        None
+    } else {
+        Some(CStr::from_ptr(pointer).to_string_lossy().into_owned())
+    }
+}
 
 unsafe extern "system" fn vulkan_debug_utils_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT,
-    _p_user_data: *mut c_void,
+    p_user_data: *mut c_void,
 ) -> vk::Bool32 {
-    let severity = match message_severity {
-        vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE => "[Verbose]",
-        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => "[Warning]",
-        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => "[Error]",
-        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => "[Info]",
-        _ => "[Unknown]",
+    let callback_data = &*p_callback_data;
+    let objects: Vec<(vk::ObjectType, u64, Option<String>)> = if callback_data.p_objects.is_null() {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(callback_data.p_objects, callback_data.object_count as usize)
+            .iter()
+            .map(|object| {
+                (
+                    object.object_type,
+                    object.object_handle,
+                    optional_c_str(object.p_object_name),
+                )
+            })
+            .collect()
     };
-    let types = match message_type {
-        vk::DebugUtilsMessageTypeFlagsEXT::GENERAL => "[General]",
-        vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE => "[Performance]",
-        vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION => "[Validation]",
-        _ => "[Unknown]",
+    let message = ValidationMessage {
+        severity: message_severity,
+        message_type,
+        message_id_name: optional_c_str(callback_data.p_message_id_name).unwrap_or_default(),
+        message: optional_c_str(callback_data.p_message).unwrap_or_default(),
+        objects,
     };
-    let message = CStr::from_ptr((*p_callback_data).p_message);
-    println!("[Debug]{}{} {:?}", severity, types, message);
+
+    let mut object_descriptions = String::new();
+    for (object_type, handle, name) in message.objects.iter() {
+        object_descriptions.push_str(&format!("\n    {:?} 0x{:x}", object_type, handle));
+        if let Some(name) = name {
+            object_descriptions.push_str(&format!(" \"{}\"", name));
+        }
+    }
+    log::log!(
+        target: "vulkan",
+        severity_to_level(message_severity, message_type),
+        "[{}] {} {}{}",
+        message_type_names(message_type),
+        message.message_id_name,
+        message.message,
+        object_descriptions
+    );
+
+    // A panic must not unwind into the driver, so poisoned locks are used as they are
+    if !p_user_data.is_null() {
+        let sinks = &*(p_user_data as *const CaptureSinks);
+        for sink in sinks.lock().unwrap_or_else(PoisonError::into_inner).iter() {
+            sink.lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .push(message.clone());
+        }
+    }
 
     vk::FALSE
 }
@@ -32,18 +208,23 @@ unsafe extern "system" fn vulkan_debug_utils_callback(
 pub struct DebugMessenger {
     loader: ash::extensions::ext::DebugUtils,
     handle: vk::DebugUtilsMessengerEXT,
+    /// Captures installed on this messenger, pointed to by the callback's user data
+    sinks: Arc<CaptureSinks>,
 
     // Reference-counting
     instance: Arc<lv::Instance>,
 }
 
 impl DebugMessenger {
-    fn get_debug_create_info() -> vk::DebugUtilsMessengerCreateInfoEXT {
+    /// Settings shared by the messenger and the one chained into `VkInstanceCreateInfo`
+    pub(crate) fn create_info() -> vk::DebugUtilsMessengerCreateInfoEXT {
         vk::DebugUtilsMessengerCreateInfoEXT {
             s_type: vk::StructureType::DEBUG_UTILS_MESSENGER_CREATE_INFO_EXT,
             p_next: ptr::null(),
             flags: vk::DebugUtilsMessengerCreateFlagsEXT::empty(),
-            message_severity: vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+            // Info is needed for debug printf output
+            message_severity: vk::DebugUtilsMessageSeverityFlagsEXT::INFO
+                | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
                 | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
             message_type: vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
                 | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
@@ -54,20 +235,18 @@ impl DebugMessenger {
         }
     }
 
+    /// Route messages through the callback for the lifetime of the messenger. `instance` must
+    /// have been created with validation enabled
     pub fn new(instance: Arc<lv::Instance>) -> Arc<DebugMessenger> {
-        // Check if validation layers support
-        if !lv::Instance::check_validation_layer_support(
-            &instance.entry,
-            &["VK_LAYER_KHRONOS_validation".to_string()],
-        ) {
-            panic!("Validation layers were requested, but not found");
-        }
-
         // Create debug messenger
         let debug_utils_loader =
             ash::extensions::ext::DebugUtils::new(&instance.entry, &instance.instance);
 
-        let create_info = DebugMessenger::get_debug_create_info();
+        let sinks: Arc<CaptureSinks> = Arc::new(Mutex::new(Vec::new()));
+        let create_info = vk::DebugUtilsMessengerCreateInfoEXT {
+            p_user_data: Arc::as_ptr(&sinks) as *mut c_void,
+            ..DebugMessenger::create_info()
+        };
         let utils_messenger = unsafe {
             debug_utils_loader
                 .create_debug_utils_messenger(&create_info, None)
@@ -77,6 +256,7 @@ impl DebugMessenger {
         Arc::new(DebugMessenger {
             loader: debug_utils_loader,
             handle: utils_messenger,
+            sinks,
             instance: instance.clone(),
         })
     }
@@ -89,3 +269,125 @@ impl Drop for DebugMessenger {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Report a validation message with `severity` through the callback as if it came from a
+    /// messenger whose user data is `sinks`
+    fn report(sinks: &Arc<CaptureSinks>, severity: vk::DebugUtilsMessageSeverityFlagsEXT) {
+        let message = std::ffi::CString::new("message").unwrap();
+        let callback_data = vk::DebugUtilsMessengerCallbackDataEXT {
+            p_message: message.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            vulkan_debug_utils_callback(
+                severity,
+                vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+                &callback_data,
+                Arc::as_ptr(sinks) as *mut c_void,
+            );
+        }
+    }
+
+    #[test]
+    fn maps_each_severity_to_a_level() {
+        let validation = vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION;
+        let general = vk::DebugUtilsMessageTypeFlagsEXT::GENERAL;
+        let severity_level = |severity| severity_to_level(severity, validation);
+        assert_eq!(
+            severity_level(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR),
+            log::Level::Error
+        );
+        assert_eq!(
+            severity_level(vk::DebugUtilsMessageSeverityFlagsEXT::WARNING),
+            log::Level::Warn
+        );
+        assert_eq!(
+            severity_level(vk::DebugUtilsMessageSeverityFlagsEXT::INFO),
+            log::Level::Info
+        );
+        assert_eq!(
+            severity_level(vk::DebugUtilsMessageSeverityFlagsEXT::VERBOSE),
+            log::Level::Trace
+        );
+        // General info is loader noise
+        assert_eq!(
+            severity_to_level(vk::DebugUtilsMessageSeverityFlagsEXT::INFO, general),
+            log::Level::Debug
+        );
+    }
+
+    #[test]
+    fn combined_severities_use_the_most_severe() {
+        assert_eq!(
+            severity_to_level(
+                vk::DebugUtilsMessageSeverityFlagsEXT::WARNING
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::ERROR,
+                vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+            ),
+            log::Level::Error
+        );
+    }
+
+    #[test]
+    fn names_combined_message_types() {
+        assert_eq!(
+            message_type_names(vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION),
+            "Validation"
+        );
+        assert_eq!(
+            message_type_names(
+                vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                    | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE
+            ),
+            "Validation|Performance"
+        );
+        assert_eq!(
+            message_type_names(
+                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                    | vk::DebugUtilsMessageTypeFlagsEXT::DEVICE_ADDRESS_BINDING
+            ),
+            "General|DeviceAddressBinding"
+        );
+        assert_eq!(
+            message_type_names(vk::DebugUtilsMessageTypeFlagsEXT::empty()),
+            "Unknown"
+        );
+    }
+
+    #[test]
+    fn captures_only_their_messenger() {
+        let (first, second) = (Arc::default(), Arc::default());
+        let first_capture = ValidationCapture::attach(Arc::clone(&first));
+        let second_capture = ValidationCapture::attach(Arc::clone(&second));
+        report(&first, vk::DebugUtilsMessageSeverityFlagsEXT::ERROR);
+        report(&second, vk::DebugUtilsMessageSeverityFlagsEXT::WARNING);
+        assert_eq!(first_capture.errors().len(), 1);
+        assert_eq!(second_capture.messages().len(), 1);
+        assert!(second_capture.errors().is_empty());
+    }
+
+    #[test]
+    fn survives_a_poisoned_capture() {
+        let sinks = Arc::default();
+        let capture = ValidationCapture::attach(Arc::clone(&sinks));
+        let sink = capture.sink.clone();
+        let _ = std::thread::spawn(move || {
+            let _guard = sink.lock().unwrap();
+            panic!("poison the capture");
+        })
+        .join();
+        report(&sinks, vk::DebugUtilsMessageSeverityFlagsEXT::ERROR);
+        assert_eq!(capture.errors().len(), 1);
+    }
+
+    #[test]
+    fn uninstalls_on_drop() {
+        let sinks: Arc<CaptureSinks> = Arc::default();
+        drop(ValidationCapture::attach(Arc::clone(&sinks)));
+        assert!(sinks.lock().unwrap().is_empty());
+    }
+}
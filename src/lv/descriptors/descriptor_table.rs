@@ -52,14 +52,14 @@ impl<T> DescriptorTable<T> {
             self.writes.push(id);
         } else {
             id = self.free_ids.pop().unwrap();
-            self.resources.insert(id as usize, Some(resource));
+            self.resources[id as usize] = Some(resource);
             self.writes.push(id);
         }
         id
     }
 
     pub fn free_resource(&mut self, index: u32) {
-        self.resources.insert(index as usize, None);
+        self.resources[index as usize] = None;
         self.free_ids.push(index);
     }
 }
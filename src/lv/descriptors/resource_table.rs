@@ -19,6 +19,18 @@ struct DescriptorWrite {
     id: u64,
 }
 
+/// Binding of the `image2D` array in the resource table
+pub const STORAGE_IMAGE_BINDING: u32 = 0;
+/// Binding of the `sampler2D` array in the resource table
+pub const TEXTURE_BINDING: u32 = 1;
+/// Binding of the `image2D` array of single mip views in the resource table
+pub const STORAGE_VIEW_BINDING: u32 = 2;
+
+/// Descriptors in each of the image and texture bindings
+const MAX_RESOURCES: u32 = u16::MAX as u32;
+/// Descriptors in the storage view binding, kept small as it shares the storage image limits
+const MAX_STORAGE_VIEWS: u32 = 1024;
+
 /// SHAder Resource Table
 pub struct ShaRT {
     handle: vk::DescriptorSet,
@@ -26,13 +38,20 @@ pub struct ShaRT {
     layout: vk::DescriptorSetLayout,
 
     storage_image: DescriptorTable<lv::AllocatedImage>,
+    texture: DescriptorTable<lv::Texture>,
+    storage_view: DescriptorTable<lv::ImageView>,
 
     device: Arc<lv::Device>,
 }
 
 impl ShaRT {
     pub fn new(device: Arc<lv::Device>) -> Self {
-        let types = [vk::DescriptorType::STORAGE_IMAGE];
+        // Indexed by the binding constants above
+        let types = [
+            (vk::DescriptorType::STORAGE_IMAGE, MAX_RESOURCES),
+            (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, MAX_RESOURCES),
+            (vk::DescriptorType::STORAGE_IMAGE, MAX_STORAGE_VIEWS),
+        ];
         let descriptor_flags: Vec<vk::DescriptorBindingFlags> = types
             .iter()
             .map(|_| {
@@ -43,9 +62,9 @@ impl ShaRT {
 
         let pool_sizes: Vec<vk::DescriptorPoolSize> = types
             .iter()
-            .map(|ty| vk::DescriptorPoolSize {
+            .map(|(ty, count)| vk::DescriptorPoolSize {
                 ty: *ty,
-                descriptor_count: u16::MAX as u32,
+                descriptor_count: *count,
             })
             .collect();
         let pool = unsafe {
@@ -82,10 +101,10 @@ impl ShaRT {
         let descriptor_bindings: Vec<vk::DescriptorSetLayoutBinding> = types
             .iter()
             .enumerate()
-            .map(|(index, ty)| vk::DescriptorSetLayoutBinding {
+            .map(|(index, (ty, count))| vk::DescriptorSetLayoutBinding {
                 binding: index as u32,
                 descriptor_type: *ty,
-                descriptor_count: u16::MAX as u32,
+                descriptor_count: *count,
                 stage_flags: vk::ShaderStageFlags::ALL,
                 ..Default::default()
             })
@@ -120,12 +139,18 @@ impl ShaRT {
                 .unwrap()
         };
 
+        device.set_object_name(handle, "ShaRT set");
+        device.set_object_name(pool, "ShaRT pool");
+        device.set_object_name(layout, "ShaRT layout");
+
         Self {
             handle,
             pool,
             layout,
 
             storage_image: DescriptorTable::new(),
+            texture: DescriptorTable::new(),
+            storage_view: DescriptorTable::new(),
 
             device,
         }
@@ -155,32 +180,83 @@ impl ShaRT {
         self.storage_image.get_resource(index)
     }
 
+    pub fn allocate_texture(&mut self, resource: lv::Texture) -> u32 {
+        self.texture.allocate_resource(resource)
+    }
+
+    pub fn free_texture(&mut self, index: u32) {
+        self.texture.free_resource(index)
+    }
+
+    pub fn get_texture(&self, index: usize) -> &Option<lv::Texture> {
+        self.texture.get_resource(index)
+    }
+
+    /// Views must be freed before the image they view is
+    pub fn allocate_storage_view(&mut self, resource: lv::ImageView) -> u32 {
+        self.storage_view.allocate_resource(resource)
+    }
+
+    pub fn free_storage_view(&mut self, index: u32) {
+        self.storage_view.free_resource(index)
+    }
+
+    /// Image infos of the pending writes of `table`, paired with the array element they go to
+    fn pending_image_writes<T: Resource>(
+        table: &DescriptorTable<T>,
+    ) -> Vec<(u32, vk::DescriptorImageInfo)> {
+        let resources = table.get_resources();
+        table
+            .get_writes()
+            .iter()
+            .filter_map(|write_index| {
+                let resource = resources.get(*write_index as usize).unwrap().as_ref()?;
+                match resource.get_descriptor() {
+                    DescriptorInfo::Image(info) => Some((*write_index, info)),
+                    _ => panic!("Expected an image descriptor info, but got otherwise."),
+                }
+            })
+            .collect()
+    }
+
     pub fn update(&mut self) {
-        let mut image_writes: Vec<vk::WriteDescriptorSet> =
-            Vec::with_capacity(self.storage_image.get_resources().len());
-        let mut image_infos: Vec<vk::DescriptorImageInfo> = Vec::new();
-        let resources = self.storage_image.get_resources();
-        for write_index in self.storage_image.get_writes() {
-            let write = resources.get(*write_index as usize).unwrap();
-            if let Some(write) = write {
-                let info = match write.get_descriptor() {
-                    DescriptorInfo::Image(info) => info,
-                    _ => panic!("Storage image expected a storage image descriptor info, but got otherwise.")
-                };
-                image_writes.push(vk::WriteDescriptorSet {
-                    s_type: vk::WriteDescriptorSet::STRUCTURE_TYPE,
-                    dst_set: self.handle,
-                    dst_binding: 0,
-                    dst_array_element: *write_index,
-                    descriptor_count: 1,
-                    descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
-                    p_image_info: &info,
-                    p_buffer_info: ptr::null(),
-                    p_texel_buffer_view: ptr::null(),
-                    ..Default::default()
-                });
-            }
-        }
+        let pending = [
+            (
+                STORAGE_IMAGE_BINDING,
+                vk::DescriptorType::STORAGE_IMAGE,
+                ShaRT::pending_image_writes(&self.storage_image),
+            ),
+            (
+                TEXTURE_BINDING,
+                vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+                ShaRT::pending_image_writes(&self.texture),
+            ),
+            (
+                STORAGE_VIEW_BINDING,
+                vk::DescriptorType::STORAGE_IMAGE,
+                ShaRT::pending_image_writes(&self.storage_view),
+            ),
+        ];
+        // The infos live in `pending` until the update, so the pointers stay valid
+        let image_writes: Vec<vk::WriteDescriptorSet> = pending
+            .iter()
+            .flat_map(|(binding, descriptor_type, infos)| {
+                infos
+                    .iter()
+                    .map(|(write_index, info)| vk::WriteDescriptorSet {
+                        s_type: vk::WriteDescriptorSet::STRUCTURE_TYPE,
+                        dst_set: self.handle,
+                        dst_binding: *binding,
+                        dst_array_element: *write_index,
+                        descriptor_count: 1,
+                        descriptor_type: *descriptor_type,
+                        p_image_info: info,
+                        p_buffer_info: ptr::null(),
+                        p_texel_buffer_view: ptr::null(),
+                        ..Default::default()
+                    })
+            })
+            .collect();
 
         // allocate writes
         unsafe {
@@ -190,6 +266,8 @@ impl ShaRT {
         }
 
         self.storage_image.clear_writes();
+        self.texture.clear_writes();
+        self.storage_view.clear_writes();
     }
 }
 
@@ -1,20 +1,87 @@
 use crate::lv;
 use crate::utility::tools::vk_to_string;
 use ash::vk;
-use ash::vk::TaggedStructure;
-use std::collections::{HashMap, HashSet};
-use std::ffi::{c_char, c_void, CString};
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CStr, CString};
 use std::sync::Arc;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default)]
 pub struct QueueFamilyIndices {
     pub graphics_family: Option<u32>,
     pub present_family: Option<u32>,
+    /// Compute-capable family without graphics support, if the device has one
+    pub compute_family: Option<u32>,
+    /// Transfer-only family (no graphics or compute), if the device has one
+    pub transfer_family: Option<u32>,
+}
+
+impl QueueFamilyIndices {
+    /// Every distinct family that was found
+    pub fn unique_families(&self) -> Vec<u32> {
+        let mut families: Vec<u32> = Vec::new();
+        for family in [
+            self.graphics_family,
+            self.present_family,
+            self.compute_family,
+            self.transfer_family,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if !families.contains(&family) {
+                families.push(family);
+            }
+        }
+        families
+    }
+}
+
+/// Rank a device against others, higher is better.
+///
+/// Built from the device type, the amount of VRAM, a few optional features and whether the device
+/// has dedicated compute and transfer families. It does not check whether the device is usable
+/// at all.
+pub fn device_score(
+    device_type: vk::PhysicalDeviceType,
+    device_local_memory: u64,
+    features: &vk::PhysicalDeviceFeatures,
+    queue_families: &QueueFamilyIndices,
+) -> u64 {
+    let mut score: u64 = match device_type {
+        vk::PhysicalDeviceType::DISCRETE_GPU => 10_000,
+        vk::PhysicalDeviceType::INTEGRATED_GPU => 5_000,
+        vk::PhysicalDeviceType::VIRTUAL_GPU => 2_000,
+        vk::PhysicalDeviceType::CPU => 1_000,
+        _ => 0,
+    };
+    // 1 point per 64 MiB of VRAM, so 8 GiB adds 128
+    score += device_local_memory / (64 * 1024 * 1024);
+
+    for optional_feature in [
+        features.sampler_anisotropy,
+        features.geometry_shader,
+        features.shader_int64,
+        features.pipeline_statistics_query,
+        features.fill_mode_non_solid,
+    ] {
+        if optional_feature == vk::TRUE {
+            score += 10;
+        }
+    }
+    if queue_families.compute_family.is_some() {
+        score += 10;
+    }
+    if queue_families.transfer_family.is_some() {
+        score += 10;
+    }
+
+    score
 }
 
 pub struct PhysicalDevice {
     pub handle: vk::PhysicalDevice,
     pub properties: vk::PhysicalDeviceProperties2,
+    pub memory_properties: vk::PhysicalDeviceMemoryProperties,
     pub features: vk::PhysicalDeviceFeatures2,
     pub features_1_3: vk::PhysicalDeviceVulkan13Features,
     pub features_1_2: vk::PhysicalDeviceVulkan12Features,
@@ -33,6 +100,8 @@ impl PhysicalDevice {
             lv.instance
                 .get_physical_device_properties2(vk_device, &mut physical_device_properties);
         };
+        let memory_properties =
+            unsafe { lv.instance.get_physical_device_memory_properties(vk_device) };
         let mut physical_device_features: vk::PhysicalDeviceFeatures2 = Default::default();
         let mut features_1_3 = vk::PhysicalDeviceVulkan13Features::default();
         let mut features_1_2 = vk::PhysicalDeviceVulkan12Features::default();
@@ -49,42 +118,85 @@ impl PhysicalDevice {
             handle: vk_device,
             instance: lv,
             properties: physical_device_properties,
+            memory_properties,
             features: physical_device_features,
             features_1_3,
             features_1_2,
             features_1_1,
-            queue_families: QueueFamilyIndices {
-                graphics_family: None,
-                present_family: None,
-            },
+            queue_families: QueueFamilyIndices::default(),
             swapchain_support: None,
         }
     }
 
+    pub fn get_name(&self) -> String {
+        vk_to_string(&self.properties.properties.device_name)
+    }
+
+    /// Total size in bytes of all device-local memory heaps
+    pub fn get_device_local_memory(&self) -> u64 {
+        self.memory_properties.memory_heaps[..self.memory_properties.memory_heap_count as usize]
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum()
+    }
+
+    /// Rank the device against others, higher is better, see [`device_score`]
+    pub fn score(&self) -> u64 {
+        device_score(
+            self.properties.properties.device_type,
+            self.get_device_local_memory(),
+            &self.features.features,
+            &self.queue_families,
+        )
+    }
+
+    pub fn get_queue_family_properties(&self) -> Vec<vk::QueueFamilyProperties> {
+        unsafe {
+            self.instance
+                .instance
+                .get_physical_device_queue_family_properties(self.handle)
+        }
+    }
+
     pub fn find_queue_families(
         &mut self,
         surface_loader: &ash::extensions::khr::Surface,
         surface: vk::SurfaceKHR,
     ) {
-        let queue_family_properties = unsafe {
-            self.instance
-                .instance
-                .get_physical_device_queue_family_properties(self.handle)
-        };
+        let queue_family_properties = self.get_queue_family_properties();
         for (index, queue_family) in queue_family_properties.iter().enumerate() {
-            if queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS) {
-                self.queue_families.graphics_family = Some(index as u32);
-            }
-            if unsafe {
+            let index = index as u32;
+            let flags = queue_family.queue_flags;
+            let supports_present = unsafe {
                 surface_loader
-                    .get_physical_device_surface_support(self.handle, index as u32, surface)
+                    .get_physical_device_surface_support(self.handle, index, surface)
                     .unwrap()
-            } {
-                self.queue_families.present_family = Some(index as u32);
+            };
+            if flags.contains(vk::QueueFlags::GRAPHICS) {
+                if self.queue_families.graphics_family.is_none() {
+                    self.queue_families.graphics_family = Some(index);
+                }
+                // Prefer presenting from the graphics family to avoid ownership transfers
+                if supports_present && self.queue_families.graphics_family == Some(index) {
+                    self.queue_families.present_family = Some(index);
+                }
+            } else if flags.contains(vk::QueueFlags::COMPUTE) {
+                if self.queue_families.compute_family.is_none() {
+                    self.queue_families.compute_family = Some(index);
+                }
+            } else if flags.contains(vk::QueueFlags::TRANSFER)
+                && self.queue_families.transfer_family.is_none()
+            {
+                self.queue_families.transfer_family = Some(index);
+            }
+            if supports_present && self.queue_families.present_family.is_none() {
+                self.queue_families.present_family = Some(index);
             }
         }
     }
-    pub fn has_extensions(&self, extensions: &[String]) -> bool {
+    /// Names of every extension the device supports
+    pub fn get_supported_extensions(&self) -> Vec<String> {
         let available_extensions = unsafe {
             self.instance
                 .instance
@@ -92,22 +204,10 @@ impl PhysicalDevice {
                 .unwrap()
         };
 
-        let mut available_extensions_names: Vec<String> = vec![];
-        for extension in available_extensions.iter() {
-            let extension_name = vk_to_string(&extension.extension_name);
-            available_extensions_names.push(extension_name);
-        }
-
-        let mut required_extensions = HashSet::new();
-        for extension in extensions.iter() {
-            required_extensions.insert(extension.to_string());
-        }
-
-        for extension_name in available_extensions_names.iter() {
-            required_extensions.remove(extension_name);
-        }
-
-        required_extensions.is_empty()
+        available_extensions
+            .iter()
+            .map(|extension| vk_to_string(&extension.extension_name))
+            .collect()
     }
 
     pub fn get_swapchain_support(
@@ -138,7 +238,15 @@ impl PhysicalDevice {
 
 pub struct Device {
     pub handle: ash::Device,
+    /// One queue per unique queue family, keyed by family index
     pub queues: HashMap<u32, lv::Queue>,
+    pub queue_families: QueueFamilyIndices,
+    /// Every extension that was enabled, required and optional
+    pub enabled_extensions: Vec<String>,
+    /// Every feature that was enabled, required and optional
+    pub enabled_features: Vec<lv::DeviceFeature>,
+    /// Loader used for object names and command buffer labels, if debug utils is enabled
+    debug_utils: Option<ash::extensions::ext::DebugUtils>,
 
     // Reference-count
     instance: Arc<lv::Instance>,
@@ -146,21 +254,19 @@ pub struct Device {
 }
 
 impl Device {
-    pub fn new(
+    pub fn from_builder(
+        builder: lv::DeviceBuilder,
         physical_device: Arc<PhysicalDevice>,
-        required_extensions: Option<Vec<String>>,
         instance: Arc<lv::Instance>,
-    ) -> Arc<Device> {
+    ) -> Result<Arc<Device>, String> {
+        builder.check_support(&physical_device)?;
+
         // Determine which queue family to use
         let queue_families: QueueFamilyIndices = physical_device.queue_families;
-        // TODO: deal with multiple queues
-        let unique_queue_families = vec![
-            queue_families.graphics_family.unwrap(),
-            queue_families.present_family.unwrap(),
-        ];
+        let unique_queue_families = queue_families.unique_families();
         let mut queue_cis: Vec<vk::DeviceQueueCreateInfo> =
             Vec::with_capacity(unique_queue_families.len());
-        for unique_queue in unique_queue_families {
+        for unique_queue in unique_queue_families.iter().copied() {
             let queue_ci = vk::DeviceQueueCreateInfo {
                 s_type: vk::StructureType::DEVICE_QUEUE_CREATE_INFO,
                 queue_family_index: unique_queue,
@@ -171,47 +277,16 @@ impl Device {
 
             queue_cis.push(queue_ci);
         }
-        let cstring_ext_names: Vec<CString> = required_extensions
-            .unwrap_or_default()
+
+        let enabled_extensions = builder.resolve_extensions(&physical_device);
+        let cstring_ext_names: Vec<CString> = enabled_extensions
             .iter()
             .map(|s| CString::new(s.clone()).unwrap())
             .collect();
         let c_str_ptrs: Vec<*const c_char> = cstring_ext_names.iter().map(|s| s.as_ptr()).collect();
 
-        let mut physical_device_features = physical_device.features;
-        // Dynamic rendering is a feature expected at the very minimum
-        let mut dynamic_rendering_feature = vk::PhysicalDeviceDynamicRenderingFeatures {
-            s_type: vk::StructureType::PHYSICAL_DEVICE_DYNAMIC_RENDERING_FEATURES,
-            dynamic_rendering: vk::TRUE,
-            ..Default::default()
-        };
-        // Same with sync2
-        let mut sync_2 = vk::PhysicalDeviceSynchronization2Features {
-            s_type: vk::StructureType::PHYSICAL_DEVICE_SYNCHRONIZATION_2_FEATURES,
-            synchronization2: vk::TRUE,
-            ..Default::default()
-        };
-        let mut bda = vk::PhysicalDeviceBufferDeviceAddressFeatures {
-            s_type: vk::StructureType::PHYSICAL_DEVICE_BUFFER_DEVICE_ADDRESS_FEATURES,
-            buffer_device_address: vk::TRUE,
-            buffer_device_address_capture_replay: vk::TRUE,
-            ..Default::default()
-        };
-        let mut descriptor_indexing = vk::PhysicalDeviceDescriptorIndexingFeatures {
-            s_type: vk::PhysicalDeviceDescriptorIndexingFeatures::STRUCTURE_TYPE,
-            shader_sampled_image_array_non_uniform_indexing: vk::TRUE,
-            shader_storage_image_array_non_uniform_indexing: vk::TRUE,
-            descriptor_binding_sampled_image_update_after_bind: vk::TRUE,
-            descriptor_binding_storage_image_update_after_bind: vk::TRUE,
-            descriptor_binding_update_unused_while_pending: vk::TRUE,
-            descriptor_binding_partially_bound: vk::TRUE,
-            runtime_descriptor_array: vk::TRUE,
-            ..Default::default()
-        };
-        dynamic_rendering_feature.p_next = &mut sync_2 as *mut _ as *mut c_void;
-        physical_device_features.p_next = &mut dynamic_rendering_feature as *mut _ as *mut c_void;
-        sync_2.p_next = &mut bda as *mut _ as *mut c_void;
-        bda.p_next = &mut descriptor_indexing as *mut _ as *mut c_void;
+        let (mut feature_set, enabled_features) = builder.resolve_features(&physical_device);
+        let physical_device_features = feature_set.chain();
 
         let device_ci = vk::DeviceCreateInfo {
             s_type: vk::StructureType::DEVICE_CREATE_INFO,
@@ -226,24 +301,160 @@ impl Device {
             instance
                 .instance
                 .create_device(physical_device.handle, &device_ci, None)
-                .unwrap()
+                .map_err(|err| format!("vkCreateDevice failed: {:?}", err))?
         };
         let mut queues = HashMap::new();
-        queues.insert(
-            queue_families.graphics_family.unwrap(),
-            lv::Queue::new(queue_families.graphics_family.unwrap(), &device),
-        );
-        queues.insert(
-            queue_families.present_family.unwrap(),
-            lv::Queue::new(queue_families.present_family.unwrap(), &device),
-        );
+        for family in unique_queue_families {
+            queues.insert(family, lv::Queue::new(family, 0, &device));
+        }
+
+        for feature in builder.optional_features.iter() {
+            if enabled_features.contains(feature) {
+                log::info!("Enabled optional feature {:?}", feature);
+            } else {
+                log::info!("Optional feature {:?} is not supported", feature);
+            }
+        }
+        for extension in builder.optional_extensions.iter() {
+            if enabled_extensions.contains(extension) {
+                log::info!("Enabled optional extension {}", extension);
+            } else {
+                log::info!("Optional extension {} is not supported", extension);
+            }
+        }
+
+        let debug_utils = if instance.debug_utils_enabled {
+            Some(ash::extensions::ext::DebugUtils::new(
+                &instance.entry,
+                &instance.instance,
+            ))
+        } else {
+            None
+        };
 
-        Arc::new(Device {
+        Ok(Arc::new(Device {
             handle: device,
             queues,
+            queue_families,
+            enabled_extensions,
+            enabled_features,
+            debug_utils,
             instance: instance.clone(),
             physical_device: physical_device.clone(),
-        })
+        }))
+    }
+
+    pub fn is_extension_enabled(&self, name: &CStr) -> bool {
+        let name = name.to_string_lossy();
+        self.enabled_extensions
+            .iter()
+            .any(|extension| *extension == name)
+    }
+
+    /// Budget and current usage in bytes of every memory heap, if `VK_EXT_memory_budget` is
+    /// enabled
+    pub fn get_memory_budgets(&self) -> Option<Vec<(vk::DeviceSize, vk::DeviceSize)>> {
+        if !self.is_extension_enabled(vk::ExtMemoryBudgetFn::name()) {
+            return None;
+        }
+        let mut budget = vk::PhysicalDeviceMemoryBudgetPropertiesEXT {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_MEMORY_BUDGET_PROPERTIES_EXT,
+            ..Default::default()
+        };
+        let mut memory_properties = vk::PhysicalDeviceMemoryProperties2 {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_MEMORY_PROPERTIES_2,
+            p_next: &mut budget as *mut _ as *mut c_void,
+            ..Default::default()
+        };
+        unsafe {
+            self.instance.instance.get_physical_device_memory_properties2(
+                self.physical_device.handle,
+                &mut memory_properties,
+            )
+        };
+        let heap_count = memory_properties.memory_properties.memory_heap_count as usize;
+        Some(
+            (0..heap_count)
+                .map(|heap| (budget.heap_budget[heap], budget.heap_usage[heap]))
+                .collect(),
+        )
+    }
+
+    pub fn is_feature_enabled(&self, feature: lv::DeviceFeature) -> bool {
+        self.enabled_features.contains(&feature)
+    }
+
+    /// Attach a name to `handle` which shows up in validation messages and capture tools.
+    ///
+    /// Does nothing when debug utils is not enabled.
+    pub fn set_object_name<T: vk::Handle>(&self, handle: T, name: &str) {
+        let debug_utils = match self.debug_utils.as_ref() {
+            Some(debug_utils) => debug_utils,
+            None => return,
+        };
+        let name = CString::new(name).unwrap();
+        let name_info = vk::DebugUtilsObjectNameInfoEXT {
+            s_type: vk::StructureType::DEBUG_UTILS_OBJECT_NAME_INFO_EXT,
+            object_type: T::TYPE,
+            object_handle: handle.as_raw(),
+            p_object_name: name.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            debug_utils
+                .set_debug_utils_object_name(self.handle.handle(), &name_info)
+                .unwrap()
+        };
+    }
+
+    /// Open a labeled region in `command_buffer`. Prefer [`lv::DebugLabel`] which closes it on drop
+    pub fn cmd_begin_label(&self, command_buffer: vk::CommandBuffer, name: &str, color: [f32; 4]) {
+        if let Some(debug_utils) = self.debug_utils.as_ref() {
+            let name = CString::new(name).unwrap();
+            let label = vk::DebugUtilsLabelEXT {
+                s_type: vk::StructureType::DEBUG_UTILS_LABEL_EXT,
+                p_label_name: name.as_ptr(),
+                color,
+                ..Default::default()
+            };
+            unsafe { debug_utils.cmd_begin_debug_utils_label(command_buffer, &label) };
+        }
+    }
+
+    pub fn cmd_end_label(&self, command_buffer: vk::CommandBuffer) {
+        if let Some(debug_utils) = self.debug_utils.as_ref() {
+            unsafe { debug_utils.cmd_end_debug_utils_label(command_buffer) };
+        }
+    }
+}
+
+impl Device {
+    pub fn graphics_queue(&self) -> &lv::Queue {
+        self.queues
+            .get(&self.queue_families.graphics_family.unwrap())
+            .unwrap()
+    }
+
+    pub fn present_queue(&self) -> &lv::Queue {
+        self.queues
+            .get(&self.queue_families.present_family.unwrap())
+            .unwrap()
+    }
+
+    /// Dedicated async compute queue, falling back to the graphics queue if there is none
+    pub fn compute_queue(&self) -> &lv::Queue {
+        self.queue_families
+            .compute_family
+            .and_then(|family| self.queues.get(&family))
+            .unwrap_or_else(|| self.graphics_queue())
+    }
+
+    /// Dedicated transfer queue, falling back to the compute and then graphics queue
+    pub fn transfer_queue(&self) -> &lv::Queue {
+        self.queue_families
+            .transfer_family
+            .and_then(|family| self.queues.get(&family))
+            .unwrap_or_else(|| self.compute_queue())
     }
 }
 
@@ -252,3 +463,68 @@ impl Drop for Device {
         unsafe { self.handle.destroy_device(None) };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GIB: u64 = 1024 * 1024 * 1024;
+
+    fn score(device_type: vk::PhysicalDeviceType, vram: u64) -> u64 {
+        device_score(
+            device_type,
+            vram,
+            &vk::PhysicalDeviceFeatures::default(),
+            &QueueFamilyIndices::default(),
+        )
+    }
+
+    #[test]
+    fn device_type_outweighs_vram() {
+        assert!(
+            score(vk::PhysicalDeviceType::DISCRETE_GPU, 2 * GIB)
+                > score(vk::PhysicalDeviceType::INTEGRATED_GPU, 64 * GIB)
+        );
+        assert!(
+            score(vk::PhysicalDeviceType::INTEGRATED_GPU, 0)
+                > score(vk::PhysicalDeviceType::CPU, 64 * GIB)
+        );
+        assert_eq!(score(vk::PhysicalDeviceType::OTHER, 0), 0);
+    }
+
+    #[test]
+    fn vram_adds_a_point_per_64_mib() {
+        let base = score(vk::PhysicalDeviceType::DISCRETE_GPU, 0);
+        assert_eq!(
+            score(vk::PhysicalDeviceType::DISCRETE_GPU, 8 * GIB),
+            base + 128
+        );
+        assert_eq!(
+            score(vk::PhysicalDeviceType::DISCRETE_GPU, 63 * 1024 * 1024),
+            base
+        );
+    }
+
+    #[test]
+    fn features_and_dedicated_queues_add_points() {
+        let base = score(vk::PhysicalDeviceType::DISCRETE_GPU, 0);
+        let features = vk::PhysicalDeviceFeatures {
+            sampler_anisotropy: vk::TRUE,
+            shader_int64: vk::TRUE,
+            ..Default::default()
+        };
+        let queue_families = QueueFamilyIndices {
+            graphics_family: Some(0),
+            present_family: Some(0),
+            compute_family: Some(1),
+            transfer_family: Some(2),
+        };
+        let score = device_score(
+            vk::PhysicalDeviceType::DISCRETE_GPU,
+            0,
+            &features,
+            &queue_families,
+        );
+        assert_eq!(score, base + 40);
+    }
+}
@@ -0,0 +1,244 @@
+use crate::lv;
+use ash::vk;
+use std::ffi::{c_void, CStr};
+
+/// Device features that can be requested through [`DeviceBuilder`].
+///
+/// Each one maps onto a single `VkBool32` in either `VkPhysicalDeviceFeatures` or one of the
+/// `VkPhysicalDeviceVulkan1XFeatures` structs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DeviceFeature {
+    // Vulkan 1.0
+    SampleRateShading,
+    SamplerAnisotropy,
+    PipelineStatisticsQuery,
+    OcclusionQueryPrecise,
+    ShaderStorageImageWriteWithoutFormat,
+    // Vulkan 1.2
+    DescriptorIndexing,
+    ShaderSampledImageArrayNonUniformIndexing,
+    ShaderStorageImageArrayNonUniformIndexing,
+    DescriptorBindingSampledImageUpdateAfterBind,
+    DescriptorBindingStorageImageUpdateAfterBind,
+    DescriptorBindingUpdateUnusedWhilePending,
+    DescriptorBindingPartiallyBound,
+    RuntimeDescriptorArray,
+    TimelineSemaphore,
+    BufferDeviceAddress,
+    // Vulkan 1.3
+    Synchronization2,
+    DynamicRendering,
+}
+
+/// Every feature struct the builder knows how to chain into `VkDeviceCreateInfo`
+#[derive(Clone, Copy, Default)]
+pub(crate) struct FeatureSet {
+    pub core: vk::PhysicalDeviceFeatures,
+    pub vulkan_1_1: vk::PhysicalDeviceVulkan11Features,
+    pub vulkan_1_2: vk::PhysicalDeviceVulkan12Features,
+    pub vulkan_1_3: vk::PhysicalDeviceVulkan13Features,
+}
+
+impl FeatureSet {
+    /// Features supported by `physical_device`
+    pub fn supported_by(physical_device: &lv::PhysicalDevice) -> Self {
+        FeatureSet {
+            core: physical_device.features.features,
+            vulkan_1_1: physical_device.features_1_1,
+            vulkan_1_2: physical_device.features_1_2,
+            vulkan_1_3: physical_device.features_1_3,
+        }
+    }
+
+    pub fn contains(&self, feature: DeviceFeature) -> bool {
+        let mut set = *self;
+        *feature.get_mut(&mut set) == vk::TRUE
+    }
+
+    pub fn enable(&mut self, feature: DeviceFeature) {
+        *feature.get_mut(self) = vk::TRUE;
+    }
+
+    /// Link the structs together so `core` heads a chain suitable for `VkDeviceCreateInfo::pNext`
+    pub fn chain(&mut self) -> vk::PhysicalDeviceFeatures2 {
+        self.vulkan_1_1.s_type = vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_1_FEATURES;
+        self.vulkan_1_2.s_type = vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_2_FEATURES;
+        self.vulkan_1_3.s_type = vk::StructureType::PHYSICAL_DEVICE_VULKAN_1_3_FEATURES;
+        self.vulkan_1_3.p_next = std::ptr::null_mut();
+        self.vulkan_1_2.p_next = &mut self.vulkan_1_3 as *mut _ as *mut c_void;
+        self.vulkan_1_1.p_next = &mut self.vulkan_1_2 as *mut _ as *mut c_void;
+        vk::PhysicalDeviceFeatures2 {
+            s_type: vk::StructureType::PHYSICAL_DEVICE_FEATURES_2,
+            p_next: &mut self.vulkan_1_1 as *mut _ as *mut c_void,
+            features: self.core,
+        }
+    }
+}
+
+impl DeviceFeature {
+    fn get_mut(self, set: &mut FeatureSet) -> &mut vk::Bool32 {
+        match self {
+            DeviceFeature::SampleRateShading => &mut set.core.sample_rate_shading,
+            DeviceFeature::SamplerAnisotropy => &mut set.core.sampler_anisotropy,
+            DeviceFeature::PipelineStatisticsQuery => &mut set.core.pipeline_statistics_query,
+            DeviceFeature::OcclusionQueryPrecise => &mut set.core.occlusion_query_precise,
+            DeviceFeature::ShaderStorageImageWriteWithoutFormat => {
+                &mut set.core.shader_storage_image_write_without_format
+            }
+            DeviceFeature::DescriptorIndexing => &mut set.vulkan_1_2.descriptor_indexing,
+            DeviceFeature::ShaderSampledImageArrayNonUniformIndexing => {
+                &mut set
+                    .vulkan_1_2
+                    .shader_sampled_image_array_non_uniform_indexing
+            }
+            DeviceFeature::ShaderStorageImageArrayNonUniformIndexing => {
+                &mut set
+                    .vulkan_1_2
+                    .shader_storage_image_array_non_uniform_indexing
+            }
+            DeviceFeature::DescriptorBindingSampledImageUpdateAfterBind => {
+                &mut set
+                    .vulkan_1_2
+                    .descriptor_binding_sampled_image_update_after_bind
+            }
+            DeviceFeature::DescriptorBindingStorageImageUpdateAfterBind => {
+                &mut set
+                    .vulkan_1_2
+                    .descriptor_binding_storage_image_update_after_bind
+            }
+            DeviceFeature::DescriptorBindingUpdateUnusedWhilePending => {
+                &mut set
+                    .vulkan_1_2
+                    .descriptor_binding_update_unused_while_pending
+            }
+            DeviceFeature::DescriptorBindingPartiallyBound => {
+                &mut set.vulkan_1_2.descriptor_binding_partially_bound
+            }
+            DeviceFeature::RuntimeDescriptorArray => &mut set.vulkan_1_2.runtime_descriptor_array,
+            DeviceFeature::TimelineSemaphore => &mut set.vulkan_1_2.timeline_semaphore,
+            DeviceFeature::BufferDeviceAddress => &mut set.vulkan_1_2.buffer_device_address,
+            DeviceFeature::Synchronization2 => &mut set.vulkan_1_3.synchronization2,
+            DeviceFeature::DynamicRendering => &mut set.vulkan_1_3.dynamic_rendering,
+        }
+    }
+}
+
+/// Declarative description of the logical device to create.
+///
+/// Required features and extensions must all be supported by the physical device, optional ones
+/// are enabled only when they are. Pass it to [`lv::Device::from_builder`] to create the device.
+#[derive(Clone, Default)]
+pub struct DeviceBuilder {
+    pub(crate) required_extensions: Vec<String>,
+    pub(crate) optional_extensions: Vec<String>,
+    pub(crate) required_features: Vec<DeviceFeature>,
+    pub(crate) optional_features: Vec<DeviceFeature>,
+}
+
+impl DeviceBuilder {
+    pub fn new() -> Self {
+        DeviceBuilder::default()
+    }
+
+    pub fn require_extension(mut self, name: &CStr) -> Self {
+        self.required_extensions
+            .push(name.to_string_lossy().into_owned());
+        self
+    }
+
+    pub fn optional_extension(mut self, name: &CStr) -> Self {
+        self.optional_extensions
+            .push(name.to_string_lossy().into_owned());
+        self
+    }
+
+    pub fn require_features(mut self, features: &[DeviceFeature]) -> Self {
+        self.required_features.extend_from_slice(features);
+        self
+    }
+
+    pub fn optional_features(mut self, features: &[DeviceFeature]) -> Self {
+        self.optional_features.extend_from_slice(features);
+        self
+    }
+
+    /// Check the required features and extensions against what `physical_device` supports,
+    /// returning what is missing if any of them are not
+    pub fn check_support(&self, physical_device: &lv::PhysicalDevice) -> Result<(), String> {
+        let api_version = physical_device.properties.properties.api_version;
+        if api_version < vk::API_VERSION_1_3 {
+            return Err(format!(
+                "device only supports Vulkan {}.{}, 1.3 is required",
+                vk::api_version_major(api_version),
+                vk::api_version_minor(api_version)
+            ));
+        }
+
+        let supported = FeatureSet::supported_by(physical_device);
+        let missing_features: Vec<String> = self
+            .required_features
+            .iter()
+            .filter(|feature| !supported.contains(**feature))
+            .map(|feature| format!("{:?}", feature))
+            .collect();
+        if !missing_features.is_empty() {
+            return Err(format!(
+                "missing required features: {}",
+                missing_features.join(", ")
+            ));
+        }
+
+        let supported_extensions = physical_device.get_supported_extensions();
+        let missing_extensions: Vec<&str> = self
+            .required_extensions
+            .iter()
+            .filter(|extension| !supported_extensions.contains(extension))
+            .map(|extension| extension.as_str())
+            .collect();
+        if !missing_extensions.is_empty() {
+            return Err(format!(
+                "missing required extensions: {}",
+                missing_extensions.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Resolve which features should be enabled on `physical_device`
+    pub(crate) fn resolve_features(
+        &self,
+        physical_device: &lv::PhysicalDevice,
+    ) -> (FeatureSet, Vec<DeviceFeature>) {
+        let supported = FeatureSet::supported_by(physical_device);
+        let mut enabled = FeatureSet::default();
+        let mut enabled_list: Vec<DeviceFeature> = Vec::new();
+        for feature in self.required_features.iter().chain(
+            self.optional_features
+                .iter()
+                .filter(|feature| supported.contains(**feature)),
+        ) {
+            if !enabled_list.contains(feature) {
+                enabled.enable(*feature);
+                enabled_list.push(*feature);
+            }
+        }
+        (enabled, enabled_list)
+    }
+
+    /// Resolve which extensions should be enabled on `physical_device`
+    pub(crate) fn resolve_extensions(&self, physical_device: &lv::PhysicalDevice) -> Vec<String> {
+        let supported_extensions = physical_device.get_supported_extensions();
+        let mut enabled: Vec<String> = Vec::new();
+        for extension in self.required_extensions.iter().chain(
+            self.optional_extensions
+                .iter()
+                .filter(|extension| supported_extensions.contains(extension)),
+        ) {
+            if !enabled.contains(extension) {
+                enabled.push(extension.clone());
+            }
+        }
+        enabled
+    }
+}
@@ -8,7 +8,11 @@ pub struct Fence {
 }
 
 impl Fence {
-    pub fn new(device: Arc<lv::Device>, flags: Option<vk::FenceCreateFlags>) -> Self {
+    pub fn new(
+        device: Arc<lv::Device>,
+        flags: Option<vk::FenceCreateFlags>,
+        name: Option<&str>,
+    ) -> Self {
         let fence_ci = vk::FenceCreateInfo {
             s_type: vk::StructureType::FENCE_CREATE_INFO,
             flags: flags.unwrap_or_default(),
@@ -16,6 +20,9 @@ impl Fence {
         };
 
         let handle = unsafe { device.handle.create_fence(&fence_ci, None).unwrap() };
+        if let Some(name) = name {
+            device.set_object_name(handle, name);
+        }
 
         Fence { device, handle }
     }
@@ -0,0 +1,134 @@
+use crate::lv;
+
+/// Environment variable used to override which GPU gets picked
+pub const GPU_PREFERENCE_ENV: &str = "LV_GPU";
+
+/// Explicit user choice of physical device, overriding the score based selection
+#[derive(Clone, Debug, PartialEq)]
+pub enum GpuPreference {
+    /// Index into the list returned by `vkEnumeratePhysicalDevices`
+    Index(usize),
+    /// Case-insensitive substring of the device name, e.g. "llvmpipe"
+    Name(String),
+    /// PCI vendor ID, e.g. 0x10DE for NVIDIA
+    VendorId(u32),
+}
+
+impl GpuPreference {
+    /// Parse a preference string.
+    ///
+    /// Accepts a plain index (`1`), a vendor ID (`vendor:0x10de` or `vendor:4318`) or
+    /// anything else as a name (`name:RTX` or just `RTX`).
+    pub fn parse(value: &str) -> Option<GpuPreference> {
+        let value = value.trim();
+        if value.is_empty() {
+            return None;
+        }
+        if let Some(vendor) = value.strip_prefix("vendor:") {
+            let vendor = vendor.trim();
+            let vendor_id = match vendor
+                .strip_prefix("0x")
+                .or_else(|| vendor.strip_prefix("0X"))
+            {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => vendor.parse::<u32>().ok(),
+            };
+            return vendor_id.map(GpuPreference::VendorId);
+        }
+        if let Some(name) = value.strip_prefix("name:") {
+            // An empty name is a substring of every name and would match any device
+            let name = name.trim();
+            return (!name.is_empty()).then(|| GpuPreference::Name(name.to_string()));
+        }
+        if let Ok(index) = value.parse::<usize>() {
+            return Some(GpuPreference::Index(index));
+        }
+        Some(GpuPreference::Name(value.to_string()))
+    }
+
+    /// Read the preference from [`GPU_PREFERENCE_ENV`], if set
+    pub fn from_env() -> Option<GpuPreference> {
+        let value = std::env::var(GPU_PREFERENCE_ENV).ok()?;
+        let preference = GpuPreference::parse(&value);
+        if preference.is_none() {
+            log::warn!(
+                "Ignoring invalid {}={:?}, expected an index, vendor:<id> or a device name",
+                GPU_PREFERENCE_ENV,
+                value
+            );
+        }
+        preference
+    }
+
+    /// Whether the device at `index` in the enumeration order matches this preference
+    pub fn matches(&self, index: usize, physical_device: &lv::PhysicalDevice) -> bool {
+        match self {
+            GpuPreference::Index(preferred) => *preferred == index,
+            GpuPreference::Name(name) => physical_device
+                .get_name()
+                .to_lowercase()
+                .contains(&name.to_lowercase()),
+            GpuPreference::VendorId(vendor_id) => {
+                physical_device.properties.properties.vendor_id == *vendor_id
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_indices() {
+        assert_eq!(GpuPreference::parse("1"), Some(GpuPreference::Index(1)));
+        assert_eq!(GpuPreference::parse(" 0 "), Some(GpuPreference::Index(0)));
+    }
+
+    #[test]
+    fn parses_vendor_ids() {
+        assert_eq!(
+            GpuPreference::parse("vendor:0x10de"),
+            Some(GpuPreference::VendorId(0x10DE))
+        );
+        assert_eq!(
+            GpuPreference::parse("vendor:0X1002"),
+            Some(GpuPreference::VendorId(0x1002))
+        );
+        assert_eq!(
+            GpuPreference::parse("vendor: 4318"),
+            Some(GpuPreference::VendorId(4318))
+        );
+        assert_eq!(GpuPreference::parse("vendor:nvidia"), None);
+        assert_eq!(GpuPreference::parse("vendor:0xZZ"), None);
+    }
+
+    #[test]
+    fn parses_names() {
+        assert_eq!(
+            GpuPreference::parse("name:RTX 4090"),
+            Some(GpuPreference::Name("RTX 4090".to_string()))
+        );
+        assert_eq!(
+            GpuPreference::parse("llvmpipe"),
+            Some(GpuPreference::Name("llvmpipe".to_string()))
+        );
+        // An explicit name wins over parsing it as an index
+        assert_eq!(
+            GpuPreference::parse("name:1"),
+            Some(GpuPreference::Name("1".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_empty_values() {
+        assert_eq!(GpuPreference::parse(""), None);
+        assert_eq!(GpuPreference::parse("   "), None);
+    }
+
+    #[test]
+    fn rejects_empty_names() {
+        assert_eq!(GpuPreference::parse("name:"), None);
+        assert_eq!(GpuPreference::parse("name:   "), None);
+    }
+}
@@ -0,0 +1,208 @@
+use crate::lv;
+use ash::vk;
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+/// Number of resolved samples the rolling averages are taken over
+const HISTORY_LENGTH: usize = 64;
+
+struct ProfilerScope {
+    name: String,
+    begin_query: u32,
+}
+
+#[derive(Default)]
+struct ProfilerState {
+    /// Scopes recorded for each frame in flight, resolved the next time that frame slot is used
+    frames: Vec<Vec<ProfilerScope>>,
+    current_frame: usize,
+    /// Durations in milliseconds of the most recently resolved frame, in recording order
+    latest: Vec<(String, f64)>,
+    history: HashMap<String, VecDeque<f64>>,
+}
+
+/// Measures how long passes take on the GPU using timestamp queries.
+///
+/// Every frame in flight owns its own range of queries. Results for a frame slot are read back in
+/// [`GpuProfiler::begin_frame`], which must only be called once the GPU has finished the last
+/// frame that used that slot, so reading them never stalls.
+pub struct GpuProfiler {
+    pool: Option<lv::QueryPool>,
+    /// Nanoseconds per timestamp tick
+    timestamp_period: f64,
+    timestamp_mask: u64,
+    max_scopes: u32,
+    state: RefCell<ProfilerState>,
+
+    device: Arc<lv::Device>,
+}
+
+impl GpuProfiler {
+    pub fn new(
+        device: Arc<lv::Device>,
+        physical_device: &lv::PhysicalDevice,
+        frames_in_flight: u32,
+        max_scopes: u32,
+    ) -> Self {
+        let limits = physical_device.properties.properties.limits;
+        let valid_bits = physical_device
+            .get_queue_family_properties()
+            .get(device.graphics_queue().index as usize)
+            .map(|properties| properties.timestamp_valid_bits)
+            .unwrap_or(0);
+        let pool = if valid_bits == 0 || limits.timestamp_period == 0.0 {
+            log::warn!("Graphics queue does not support timestamps, GPU profiling is disabled");
+            None
+        } else {
+            Some(lv::QueryPool::new(
+                device.clone(),
+                vk::QueryType::TIMESTAMP,
+                frames_in_flight * max_scopes * 2,
+                Some("GPU profiler timestamps"),
+            ))
+        };
+        let timestamp_mask = if valid_bits >= 64 {
+            u64::MAX
+        } else {
+            (1u64 << valid_bits) - 1
+        };
+
+        GpuProfiler {
+            pool,
+            timestamp_period: limits.timestamp_period as f64,
+            timestamp_mask,
+            max_scopes,
+            state: RefCell::new(ProfilerState {
+                frames: (0..frames_in_flight).map(|_| Vec::new()).collect(),
+                ..Default::default()
+            }),
+            device,
+        }
+    }
+
+    fn first_query(&self, frame: usize) -> u32 {
+        frame as u32 * self.max_scopes * 2
+    }
+
+    /// Resolve the previous results of `frame` and reset its queries in `command_buffer`
+    pub fn begin_frame(&self, command_buffer: vk::CommandBuffer, frame: usize) {
+        let pool = match self.pool.as_ref() {
+            Some(pool) => pool,
+            None => return,
+        };
+        let mut state = self.state.borrow_mut();
+        let first_query = self.first_query(frame);
+        let scopes = std::mem::take(&mut state.frames[frame]);
+        if let Some(timestamps) = pool.get_results(first_query, scopes.len() as u32 * 2, 1) {
+            let mut latest: Vec<(String, f64)> = Vec::with_capacity(scopes.len());
+            for scope in scopes {
+                let local = (scope.begin_query - first_query) as usize;
+                let ticks =
+                    timestamps[local + 1].wrapping_sub(timestamps[local]) & self.timestamp_mask;
+                let milliseconds = ticks as f64 * self.timestamp_period / 1_000_000.0;
+
+                let history = state.history.entry(scope.name.clone()).or_default();
+                if history.len() == HISTORY_LENGTH {
+                    history.pop_front();
+                }
+                history.push_back(milliseconds);
+                latest.push((scope.name, milliseconds));
+            }
+            if !latest.is_empty() {
+                state.latest = latest;
+            }
+        }
+
+        pool.cmd_reset(command_buffer, first_query, self.max_scopes * 2);
+        state.current_frame = frame;
+    }
+
+    /// Write the starting timestamp of a pass called `name`.
+    ///
+    /// Returns the id to pass to [`GpuProfiler::end_scope`], or `None` if the profiler is disabled
+    /// or out of scopes for this frame.
+    pub fn begin_scope(&self, command_buffer: vk::CommandBuffer, name: &str) -> Option<u32> {
+        let pool = self.pool.as_ref()?;
+        let mut state = self.state.borrow_mut();
+        let frame = state.current_frame;
+        let scope_count = state.frames[frame].len() as u32;
+        if scope_count >= self.max_scopes {
+            log::warn!("GPU profiler ran out of scopes, skipping {}", name);
+            return None;
+        }
+        let begin_query = self.first_query(frame) + scope_count * 2;
+        unsafe {
+            self.device.handle.cmd_write_timestamp2(
+                command_buffer,
+                vk::PipelineStageFlags2::TOP_OF_PIPE,
+                pool.get_handle(),
+                begin_query,
+            )
+        };
+        state.frames[frame].push(ProfilerScope {
+            name: name.to_string(),
+            begin_query,
+        });
+        Some(begin_query)
+    }
+
+    /// Write the ending timestamp of the pass started by [`GpuProfiler::begin_scope`]
+    pub fn end_scope(&self, command_buffer: vk::CommandBuffer, id: Option<u32>) {
+        if let (Some(pool), Some(begin_query)) = (self.pool.as_ref(), id) {
+            unsafe {
+                self.device.handle.cmd_write_timestamp2(
+                    command_buffer,
+                    vk::PipelineStageFlags2::BOTTOM_OF_PIPE,
+                    pool.get_handle(),
+                    begin_query + 1,
+                )
+            };
+        }
+    }
+
+    /// Time everything recorded into `command_buffer` until the returned scope is dropped
+    pub fn scope(&self, command_buffer: vk::CommandBuffer, name: &str) -> GpuProfileScope<'_> {
+        let id = self.begin_scope(command_buffer, name);
+        GpuProfileScope {
+            profiler: self,
+            command_buffer,
+            id,
+        }
+    }
+
+    /// Per-pass durations in milliseconds of the most recently resolved frame
+    pub fn get_latest(&self) -> Vec<(String, f64)> {
+        self.state.borrow().latest.clone()
+    }
+
+    /// Per-pass durations in milliseconds averaged over the last few resolved frames
+    pub fn get_averages(&self) -> Vec<(String, f64)> {
+        let state = self.state.borrow();
+        let mut averages: Vec<(String, f64)> = state
+            .history
+            .iter()
+            .map(|(name, samples)| {
+                (
+                    name.clone(),
+                    samples.iter().sum::<f64>() / samples.len().max(1) as f64,
+                )
+            })
+            .collect();
+        averages.sort_by(|a, b| a.0.cmp(&b.0));
+        averages
+    }
+}
+
+/// Pass timed by a [`GpuProfiler`], ended when dropped
+pub struct GpuProfileScope<'a> {
+    profiler: &'a GpuProfiler,
+    command_buffer: vk::CommandBuffer,
+    id: Option<u32>,
+}
+
+impl Drop for GpuProfileScope<'_> {
+    fn drop(&mut self) {
+        self.profiler.end_scope(self.command_buffer, self.id);
+    }
+}
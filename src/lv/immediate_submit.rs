@@ -0,0 +1,261 @@
+use crate::{lv, utility};
+use ash::vk;
+use std::sync::Arc;
+
+/// Command buffer for one-off work outside the frame loop, like uploads, which blocks until the
+/// GPU has finished it
+pub struct ImmediateSubmit {
+    pool: lv::CommandPool,
+    command_buffer: lv::CommandBuffer,
+    fence: lv::Fence,
+    queue: lv::Queue,
+    /// Uploads go through the dedicated transfer queue when the device has one
+    transfer: Option<TransferSubmit>,
+
+    device: Arc<lv::Device>,
+}
+
+/// Transfer queue side of [`ImmediateSubmit`], whose work the main queue waits on through
+/// `semaphore` before acquiring the uploaded resources
+struct TransferSubmit {
+    pool: lv::CommandPool,
+    command_buffer: lv::CommandBuffer,
+    semaphore: lv::Semaphore,
+    queue: lv::Queue,
+}
+
+impl ImmediateSubmit {
+    /// Submit to `queue`, which owns everything recorded through this. Uploads are copied on
+    /// `transfer_queue` instead when it belongs to a different family
+    pub fn new(
+        device: Arc<lv::Device>,
+        queue: &lv::Queue,
+        transfer_queue: &lv::Queue,
+        name: Option<&str>,
+    ) -> Self {
+        let (pool, command_buffer) = ImmediateSubmit::create_command_buffer(&device, queue, name);
+        let fence = lv::Fence::new(
+            device.clone(),
+            None,
+            name.map(|name| format!("{} fence", name)).as_deref(),
+        );
+        let transfer = (transfer_queue.index != queue.index).then(|| {
+            let transfer_name = name.map(|name| format!("{} transfer", name));
+            let (pool, command_buffer) = ImmediateSubmit::create_command_buffer(
+                &device,
+                transfer_queue,
+                transfer_name.as_deref(),
+            );
+            let semaphore = lv::Semaphore::new(
+                device.clone(),
+                None,
+                transfer_name
+                    .map(|name| format!("{} semaphore", name))
+                    .as_deref(),
+            );
+            TransferSubmit {
+                pool,
+                command_buffer,
+                semaphore,
+                queue: transfer_queue.clone(),
+            }
+        });
+
+        ImmediateSubmit {
+            pool,
+            command_buffer,
+            fence,
+            queue: queue.clone(),
+            transfer,
+
+            device,
+        }
+    }
+
+    fn create_command_buffer(
+        device: &Arc<lv::Device>,
+        queue: &lv::Queue,
+        name: Option<&str>,
+    ) -> (lv::CommandPool, lv::CommandBuffer) {
+        let pool = lv::CommandPool::new(
+            vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
+            queue,
+            device.clone(),
+        );
+        let command_buffer = lv::CommandBuffer::new(
+            &pool,
+            vk::CommandBufferLevel::PRIMARY,
+            device,
+            name.map(|name| format!("{} command buffer", name))
+                .as_deref(),
+        );
+        (pool, command_buffer)
+    }
+
+    /// Reset `pool`, which holds only `command_buffer`, and start recording
+    fn begin(&self, pool: &lv::CommandPool, command_buffer: vk::CommandBuffer) {
+        unsafe {
+            self.device
+                .handle
+                .reset_command_pool(pool.get_handle(), vk::CommandPoolResetFlags::empty())
+                .unwrap();
+            self.device
+                .handle
+                .begin_command_buffer(
+                    command_buffer,
+                    &utility::init::command_buffer_begin_info(
+                        vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+                    ),
+                )
+                .unwrap();
+        }
+    }
+
+    /// Record commands with `record`, submit them and wait until they have executed
+    pub fn submit<F: FnOnce(vk::CommandBuffer)>(&self, record: F) {
+        let command_buffer = self.command_buffer.get_handle();
+        self.begin(&self.pool, command_buffer);
+        record(command_buffer);
+        unsafe {
+            self.device
+                .handle
+                .end_command_buffer(command_buffer)
+                .unwrap();
+        }
+        self.submit_and_wait(command_buffer, None);
+    }
+
+    /// Fill `image` with the copies `record` makes into it while it is in
+    /// `TRANSFER_DST_OPTIMAL`, leaving it in `new_layout` and owned by the main queue family.
+    ///
+    /// With a dedicated transfer queue the copies run there, and ownership of the image is
+    /// released to the main queue, which acquires it before signaling completion.
+    pub fn upload_image<F: FnOnce(vk::CommandBuffer)>(
+        &self,
+        image: vk::Image,
+        new_layout: vk::ImageLayout,
+        record: F,
+    ) {
+        let Some(transfer) = &self.transfer else {
+            return self.submit(|command_buffer| {
+                self.record_upload(command_buffer, image, record);
+                utility::transition_image(
+                    &self.device.handle,
+                    command_buffer,
+                    image,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    new_layout,
+                    vk::QUEUE_FAMILY_IGNORED,
+                    vk::QUEUE_FAMILY_IGNORED,
+                );
+            });
+        };
+
+        let release_command_buffer = transfer.command_buffer.get_handle();
+        let acquire_command_buffer = self.command_buffer.get_handle();
+        self.begin(&transfer.pool, release_command_buffer);
+        self.begin(&self.pool, acquire_command_buffer);
+        self.record_upload(release_command_buffer, image, record);
+        utility::transfer_image_ownership(
+            &self.device.handle,
+            release_command_buffer,
+            acquire_command_buffer,
+            image,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            new_layout,
+            &transfer.queue,
+            &self.queue,
+        );
+        unsafe {
+            self.device
+                .handle
+                .end_command_buffer(release_command_buffer)
+                .unwrap();
+            self.device
+                .handle
+                .end_command_buffer(acquire_command_buffer)
+                .unwrap();
+        }
+
+        let command_buffer_infos = [vk::CommandBufferSubmitInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_SUBMIT_INFO,
+            command_buffer: release_command_buffer,
+            ..Default::default()
+        }];
+        let signal_infos = [transfer
+            .semaphore
+            .submit_info(vk::PipelineStageFlags2::ALL_COMMANDS)];
+        let submit_info = vk::SubmitInfo2 {
+            s_type: vk::StructureType::SUBMIT_INFO_2,
+            command_buffer_info_count: command_buffer_infos.len() as u32,
+            p_command_buffer_infos: command_buffer_infos.as_ptr(),
+            signal_semaphore_info_count: signal_infos.len() as u32,
+            p_signal_semaphore_infos: signal_infos.as_ptr(),
+            ..Default::default()
+        };
+        unsafe {
+            self.device
+                .handle
+                .queue_submit2(transfer.queue.handle, &[submit_info], vk::Fence::null())
+                .unwrap();
+        }
+        self.submit_and_wait(acquire_command_buffer, Some(&transfer.semaphore));
+    }
+
+    /// Move `image` out of `UNDEFINED` and record the copies into it
+    fn record_upload<F: FnOnce(vk::CommandBuffer)>(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        record: F,
+    ) {
+        utility::transition_image(
+            &self.device.handle,
+            command_buffer,
+            image,
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::QUEUE_FAMILY_IGNORED,
+            vk::QUEUE_FAMILY_IGNORED,
+        );
+        record(command_buffer);
+    }
+
+    /// Submit `command_buffer` to the main queue, after `wait_semaphore` if any, and block until
+    /// it has executed
+    fn submit_and_wait(
+        &self,
+        command_buffer: vk::CommandBuffer,
+        wait_semaphore: Option<&lv::Semaphore>,
+    ) {
+        let command_buffer_infos = [vk::CommandBufferSubmitInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_SUBMIT_INFO,
+            command_buffer,
+            ..Default::default()
+        }];
+        let wait_infos: Vec<vk::SemaphoreSubmitInfo> = wait_semaphore
+            .map(|semaphore| semaphore.submit_info(vk::PipelineStageFlags2::ALL_COMMANDS))
+            .into_iter()
+            .collect();
+        let submit_info = vk::SubmitInfo2 {
+            s_type: vk::StructureType::SUBMIT_INFO_2,
+            wait_semaphore_info_count: wait_infos.len() as u32,
+            p_wait_semaphore_infos: wait_infos.as_ptr(),
+            command_buffer_info_count: command_buffer_infos.len() as u32,
+            p_command_buffer_infos: command_buffer_infos.as_ptr(),
+            ..Default::default()
+        };
+        let fence = self.fence.get_handle();
+        unsafe {
+            self.device
+                .handle
+                .queue_submit2(self.queue.handle, &[submit_info], fence)
+                .unwrap();
+            self.device
+                .handle
+                .wait_for_fences(&[fence], true, u64::MAX)
+                .unwrap();
+            self.device.handle.reset_fences(&[fence]).unwrap();
+        }
+    }
+}
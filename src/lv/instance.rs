@@ -1,65 +1,256 @@
-use std::ffi::CString;
-use std::ptr;
-use crate::{utility, VALIDATION, VulkanApp, WINDOW_TITLE};
-use std::sync::{Arc, RwLock};
+use crate::{lv, utility};
 use ash::vk;
+use std::ffi::{c_char, c_void, CStr, CString};
+use std::ptr;
+
+const VALIDATION_LAYER_NAME: &str = "VK_LAYER_KHRONOS_validation";
+
+/// Extra checks the validation layer can run on top of the default ones, enabled through
+/// `VkValidationFeaturesEXT`
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ValidationFeatures {
+    /// Instrument shaders to catch out of bounds descriptor and buffer accesses
+    pub gpu_assisted: bool,
+    /// Warn about API usage that is valid but slow
+    pub best_practices: bool,
+    /// Report missing or incorrect barriers and hazards
+    pub synchronization: bool,
+    /// Route `debugPrintfEXT` output from shaders to the debug messenger.
+    ///
+    /// Cannot be combined with `gpu_assisted`; debug printf wins if both are set.
+    pub debug_printf: bool,
+}
+
+impl ValidationFeatures {
+    fn enabled(&self) -> Vec<vk::ValidationFeatureEnableEXT> {
+        let mut enabled = Vec::new();
+        if self.gpu_assisted && !self.debug_printf {
+            enabled.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED);
+            enabled.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT);
+        }
+        if self.best_practices {
+            enabled.push(vk::ValidationFeatureEnableEXT::BEST_PRACTICES);
+        }
+        if self.synchronization {
+            enabled.push(vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION);
+        }
+        if self.debug_printf {
+            enabled.push(vk::ValidationFeatureEnableEXT::DEBUG_PRINTF);
+        }
+        enabled
+    }
+}
+
+pub struct InstanceBuilder {
+    app_name: CString,
+    app_version: u32,
+    engine_name: CString,
+    engine_version: u32,
+    api_version: u32,
+    layers: Vec<String>,
+    extensions: Vec<CString>,
+    optional_extensions: Vec<CString>,
+    validation: bool,
+    validation_features: ValidationFeatures,
+    debug_utils: bool,
+}
+
+impl InstanceBuilder {
+    pub fn new() -> Self {
+        InstanceBuilder {
+            app_name: CString::new("Vulkan Application").unwrap(),
+            app_version: 0,
+            engine_name: CString::new("Vulkan Engine").unwrap(),
+            engine_version: 0,
+            api_version: vk::API_VERSION_1_3,
+            layers: Vec::new(),
+            extensions: Vec::new(),
+            optional_extensions: Vec::new(),
+            validation: false,
+            validation_features: ValidationFeatures::default(),
+            debug_utils: false,
+        }
+    }
+
+    pub fn app_name(mut self, name: &str) -> Self {
+        self.app_name = CString::new(name).unwrap();
+        self
+    }
+
+    pub fn app_version(mut self, major: u32, minor: u32, patch: u32) -> Self {
+        self.app_version = vk::make_api_version(0, major, minor, patch);
+        self
+    }
+
+    /// Vulkan version the application targets, e.g. `vk::API_VERSION_1_3`
+    pub fn api_version(mut self, version: u32) -> Self {
+        self.api_version = version;
+        self
+    }
 
-const validation_layer_name: &str = "VK_LAYER_KHRONOS_validation";
+    /// Enable an instance layer if it is available, otherwise it is skipped with a warning
+    pub fn layer(mut self, name: &str) -> Self {
+        self.layers.push(name.to_string());
+        self
+    }
+
+    /// Enable an instance extension if it is available, otherwise it is skipped with a warning
+    pub fn optional_extension(mut self, name: &CStr) -> Self {
+        self.optional_extensions.push(name.to_owned());
+        self
+    }
+
+    /// Require every extension in `names`
+    pub fn extensions(mut self, names: &[&CStr]) -> Self {
+        self.extensions
+            .extend(names.iter().map(|name| (*name).to_owned()));
+        self
+    }
+
+    /// Enable the Khronos validation layer and the debug utils extension. If the layer is not
+    /// installed the instance is created without them and a warning is logged
+    pub fn validation(mut self, enabled: bool) -> Self {
+        self.validation = enabled;
+        self
+    }
+
+    /// Enable the debug utils extension for object names and labels without validation, e.g.
+    /// for capture tools, if it is available. Validation always enables it
+    pub fn debug_utils(mut self, enabled: bool) -> Self {
+        self.debug_utils = enabled;
+        self
+    }
+
+    /// Extra validation checks to turn on. Only has an effect when validation is enabled
+    pub fn validation_features(mut self, features: ValidationFeatures) -> Self {
+        self.validation_features = features;
+        self
+    }
+}
 
 pub struct Instance {
     pub entry: ash::Entry,
     pub instance: ash::Instance,
+    /// Whether the validation layer was found and enabled
+    pub validation_enabled: bool,
+    /// Whether `VK_EXT_debug_utils` is enabled, so objects can be named and labeled
+    pub debug_utils_enabled: bool,
+    enabled_extensions: Vec<CString>,
 }
 
 impl Instance {
-    
-    pub fn new(required_extensions: Vec<*const i8>, validation_layers: bool) -> Self {
+    pub fn from_builder(mut builder: InstanceBuilder) -> Self {
         let entry = ash::Entry::linked();
-        if !validation_layers || !Instance::check_validation_layer_support(&entry, &vec![validation_layer_name.to_string()]) {
-            panic!("Validation layer is enabled, but could not find validation layers!");
+        if builder.validation
+            && !Instance::check_validation_layer_support(
+                &entry,
+                &[VALIDATION_LAYER_NAME.to_string()],
+            )
+        {
+            log::warn!(
+                "Validation was requested, but {} is not installed, continuing without it",
+                VALIDATION_LAYER_NAME
+            );
+            builder.validation = false;
         }
-        
+        if builder.validation {
+            builder.layers.push(VALIDATION_LAYER_NAME.to_string());
+            builder
+                .extensions
+                .push(ash::extensions::ext::DebugUtils::name().to_owned());
+        } else if builder.debug_utils {
+            builder
+                .optional_extensions
+                .push(ash::extensions::ext::DebugUtils::name().to_owned());
+        }
+
+        // Only enable layers which are actually present
+        let mut layer_names: Vec<CString> = Vec::new();
+        for layer in builder.layers.iter() {
+            if !Instance::check_validation_layer_support(&entry, std::slice::from_ref(layer)) {
+                log::warn!("Instance layer {} is not available, skipping it", layer);
+                continue;
+            }
+            let layer = CString::new(layer.as_str()).unwrap();
+            if !layer_names.contains(&layer) {
+                layer_names.push(layer);
+            }
+        }
+        let enabled_layer_names: Vec<*const c_char> =
+            layer_names.iter().map(|name| name.as_ptr()).collect();
+
+        // Validation features are configured through VK_EXT_validation_features
+        let enabled_validation_features = builder.validation_features.enabled();
+        let use_validation_features = builder.validation && !enabled_validation_features.is_empty();
+        if builder.validation_features.gpu_assisted && builder.validation_features.debug_printf {
+            log::warn!(
+                "GPU-assisted validation and debug printf are exclusive, using debug printf"
+            );
+        }
+        if use_validation_features {
+            builder
+                .extensions
+                .push(vk::ExtValidationFeaturesFn::name().to_owned());
+        }
+        let validation_features = vk::ValidationFeaturesEXT {
+            s_type: vk::StructureType::VALIDATION_FEATURES_EXT,
+            enabled_validation_feature_count: enabled_validation_features.len() as u32,
+            p_enabled_validation_features: enabled_validation_features.as_ptr(),
+            ..Default::default()
+        };
+
+        // Only enable optional extensions which are actually present
+        let available_extensions = Instance::get_available_extensions(&entry);
+        for extension in builder.optional_extensions.iter() {
+            if available_extensions.contains(extension) {
+                builder.extensions.push(extension.clone());
+            } else {
+                log::warn!(
+                    "Instance extension {} is not available, skipping it",
+                    extension.to_string_lossy()
+                );
+            }
+        }
+
+        let mut extension_names: Vec<CString> = Vec::new();
+        for extension in builder.extensions.iter() {
+            if !extension_names.contains(extension) {
+                extension_names.push(extension.clone());
+            }
+        }
+        let enabled_extension_names: Vec<*const c_char> =
+            extension_names.iter().map(|name| name.as_ptr()).collect();
+
         // Create instance
-        let app_name = CString::new(WINDOW_TITLE).unwrap();
-        let engine_name = CString::new("Vulkan Engine").unwrap();
         let app_info = vk::ApplicationInfo {
             s_type: vk::StructureType::APPLICATION_INFO,
             p_next: ptr::null(),
-            p_application_name: app_name.as_ptr(),
-            application_version: 0,
-            p_engine_name: engine_name.as_ptr(),
-            engine_version: 0,
-            api_version: vk::make_api_version(0, 1, 3, 0),
+            p_application_name: builder.app_name.as_ptr(),
+            application_version: builder.app_version,
+            p_engine_name: builder.engine_name.as_ptr(),
+            engine_version: builder.engine_version,
+            api_version: builder.api_version,
         };
 
-        let required_extension_layer_names: Vec<CString> = VALIDATION
-            .required_validation_layers
-            .iter()
-            .map(|layer_name| CString::new(*layer_name).unwrap())
-            .collect();
-
-        let enabled_layer_names: Vec<*const i8> = required_extension_layer_names
-            .iter()
-            .map(|layer_name| layer_name.as_ptr())
-            .collect();
-
+        // Chaining a messenger into the create info reports messages from instance creation and
+        // destruction, which happen without the long-lived messenger
+        let mut debug_messenger_ci = lv::DebugMessenger::create_info();
+        if use_validation_features {
+            debug_messenger_ci.p_next = &validation_features as *const _ as *const c_void;
+        }
         let create_info = vk::InstanceCreateInfo {
             s_type: vk::StructureType::INSTANCE_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::InstanceCreateFlags::empty(),
-            p_application_info: &app_info,
-            enabled_layer_count: if VALIDATION.is_enabled {
-                enabled_layer_names.len()
-            } else {
-                0
-            } as u32,
-            pp_enabled_layer_names: if VALIDATION.is_enabled {
-                enabled_layer_names.as_ptr()
+            p_next: if builder.validation {
+                &debug_messenger_ci as *const _ as *const c_void
             } else {
                 ptr::null()
             },
-            pp_enabled_extension_names: required_extensions.as_ptr(),
-            enabled_extension_count: required_extensions.len() as u32,
+            flags: vk::InstanceCreateFlags::empty(),
+            p_application_info: &app_info,
+            enabled_layer_count: enabled_layer_names.len() as u32,
+            pp_enabled_layer_names: enabled_layer_names.as_ptr(),
+            pp_enabled_extension_names: enabled_extension_names.as_ptr(),
+            enabled_extension_count: enabled_extension_names.len() as u32,
         };
 
         let instance: ash::Instance = unsafe {
@@ -70,10 +261,41 @@ impl Instance {
 
         Self {
             entry,
-            instance
+            instance,
+            validation_enabled: builder.validation,
+            debug_utils_enabled: extension_names
+                .iter()
+                .any(|extension| extension.as_c_str() == ash::extensions::ext::DebugUtils::name()),
+            enabled_extensions: extension_names,
         }
     }
-    
+
+    /// Whether instance extension `name` was enabled, required or optional
+    pub fn is_extension_enabled(&self, name: &CStr) -> bool {
+        self.enabled_extensions
+            .iter()
+            .any(|extension| extension.as_c_str() == name)
+    }
+
+    /// Whether the Vulkan implementation offers instance extension `name`, before any instance
+    /// has been created
+    pub fn is_extension_available(name: &CStr) -> bool {
+        Instance::get_available_extensions(&ash::Entry::linked())
+            .iter()
+            .any(|extension| extension.as_c_str() == name)
+    }
+
+    fn get_available_extensions(entry: &ash::Entry) -> Vec<CString> {
+        entry
+            .enumerate_instance_extension_properties(None)
+            .expect("Failed to enumerate instance extension properties")
+            .iter()
+            .map(|properties| {
+                unsafe { CStr::from_ptr(properties.extension_name.as_ptr()) }.to_owned()
+            })
+            .collect()
+    }
+
     pub fn check_validation_layer_support(entry: &ash::Entry, required_layers: &[String]) -> bool {
         let layer_properties = entry
             .enumerate_instance_layer_properties()
@@ -1,30 +1,50 @@
 pub mod Image;
+mod buffer;
 mod command_buffer;
 mod command_pool;
+mod debug_label;
 mod debug_messenger_struct;
 mod device;
+mod device_builder;
 mod fence;
+mod gpu_preference;
+mod gpu_profiler;
+mod immediate_submit;
 mod instance;
 mod pipeline;
+mod query_pool;
 mod queue;
+mod sampler;
 mod semaphore;
 mod shader;
 mod surface;
 mod swapchain;
+mod texture;
+mod timeline_semaphore;
 pub mod descriptors;
 pub mod traits;
 
 // Re-export everything
 pub use self::instance::*;
+pub use buffer::*;
 pub use command_buffer::*;
 pub use command_pool::*;
+pub use debug_label::*;
 pub use debug_messenger_struct::*;
 pub use device::*;
+pub use device_builder::*;
 pub use fence::*;
+pub use gpu_preference::*;
+pub use gpu_profiler::*;
+pub use immediate_submit::*;
 pub use pipeline::*;
+pub use query_pool::*;
 pub use queue::*;
+pub use sampler::*;
 pub use semaphore::*;
 pub use shader::*;
 pub use surface::*;
 pub use swapchain::*;
+pub use texture::*;
+pub use timeline_semaphore::*;
 pub use Image::*;
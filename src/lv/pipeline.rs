@@ -2,7 +2,7 @@ use crate::lv;
 use ash::vk;
 use ash::vk::TaggedStructure;
 use std::cmp::max_by;
-use std::ffi::{c_char, c_void};
+use std::ffi::c_void;
 use std::future::poll_fn;
 use std::ptr;
 use std::sync::Arc;
@@ -18,10 +18,13 @@ pub struct PipelineBuilder {
     viewports: Vec<vk::Viewport>,
     scissors: Vec<vk::Rect2D>,
     dynamic_states_vector: Vec<vk::DynamicState>,
+    layouts: Vec<vk::DescriptorSetLayout>,
+    push_constant_ranges: Vec<vk::PushConstantRange>,
 
     shader_stages: Vec<vk::PipelineShaderStageCreateInfo>,
     depth_formats: Vec<vk::Format>,
     color_formats: Vec<vk::Format>,
+    name: Option<String>,
 }
 
 impl PipelineBuilder {
@@ -54,13 +57,22 @@ impl PipelineBuilder {
             viewports: Vec::new(),
             scissors: Vec::new(),
             dynamic_states_vector: Vec::new(),
+            layouts: Vec::new(),
+            push_constant_ranges: Vec::new(),
 
             shader_stages: Vec::new(),
             color_formats: Vec::new(),
             depth_formats: Vec::new(),
+            name: None,
         }
     }
 
+    /// Debug name given to the pipeline and its layout
+    pub fn set_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
     /// Sets viewports and automatically deals with pointers and size
     pub fn set_viewports(
         mut self,
@@ -81,6 +93,16 @@ impl PipelineBuilder {
         self
     }
 
+    pub fn set_layouts(mut self, layouts: Vec<vk::DescriptorSetLayout>) -> Self {
+        self.layouts = layouts;
+        self
+    }
+
+    pub fn attach_push_constant(mut self, push_constant_range: vk::PushConstantRange) -> Self {
+        self.push_constant_ranges.push(push_constant_range);
+        self
+    }
+
     pub fn color_attachments(mut self, count: u32, formats: Vec<vk::Format>) -> Self {
         self.color_formats = formats;
         self
@@ -116,14 +138,43 @@ impl PipelineBuilder {
         self
     }
 
-    pub fn set_multisampling_none(mut self) -> Self {
+    /// Offset depth by `constant_factor` units plus `slope_factor` times the polygon's depth
+    /// slope, to keep surfaces from shadowing themselves
+    pub fn set_depth_bias(mut self, constant_factor: f32, slope_factor: f32) -> Self {
+        self.rasterizer.depth_bias_enable = vk::TRUE;
+        self.rasterizer.depth_bias_constant_factor = constant_factor;
+        self.rasterizer.depth_bias_slope_factor = slope_factor;
+        self.rasterizer.depth_bias_clamp = 0.0f32;
+        self
+    }
+
+    pub fn set_multisampling_none(self) -> Self {
+        self.set_multisampling(vk::SampleCountFlags::TYPE_1)
+    }
+
+    /// Rasterize `samples` samples per pixel, running the fragment shader once per pixel
+    pub fn set_multisampling(mut self, samples: vk::SampleCountFlags) -> Self {
         self.multisampling.sample_shading_enable = vk::FALSE;
-        self.multisampling.rasterization_samples = vk::SampleCountFlags::TYPE_1;
+        self.multisampling.rasterization_samples = samples;
         self.multisampling.min_sample_shading = 1.0f32;
         self.multisampling.p_sample_mask = ptr::null();
 
+        self.multisampling.alpha_to_coverage_enable = vk::FALSE;
         self.multisampling.alpha_to_one_enable = vk::FALSE;
-        self.multisampling.alpha_to_one_enable = vk::FALSE;
+        self
+    }
+
+    /// Run the fragment shader for at least `min_sample_shading` of each pixel's samples. Needs
+    /// the `SampleRateShading` device feature
+    pub fn enable_sample_shading(mut self, min_sample_shading: f32) -> Self {
+        self.multisampling.sample_shading_enable = vk::TRUE;
+        self.multisampling.min_sample_shading = min_sample_shading;
+        self
+    }
+
+    /// Derive which samples a fragment covers from the alpha it writes to the first attachment
+    pub fn enable_alpha_to_coverage(mut self) -> Self {
+        self.multisampling.alpha_to_coverage_enable = vk::TRUE;
         self
     }
 
@@ -136,11 +187,41 @@ impl PipelineBuilder {
         self
     }
 
+    /// Blend premultiplied colors over the attachment, `src + dst * (1 - src alpha)`
+    pub fn enable_blending_premultiplied(mut self) -> Self {
+        self.color_blend_attachment.color_write_mask = vk::ColorComponentFlags::R
+            | vk::ColorComponentFlags::G
+            | vk::ColorComponentFlags::B
+            | vk::ColorComponentFlags::A;
+        self.color_blend_attachment.blend_enable = vk::TRUE;
+        self.color_blend_attachment.src_color_blend_factor = vk::BlendFactor::ONE;
+        self.color_blend_attachment.dst_color_blend_factor = vk::BlendFactor::ONE_MINUS_SRC_ALPHA;
+        self.color_blend_attachment.color_blend_op = vk::BlendOp::ADD;
+        self.color_blend_attachment.src_alpha_blend_factor = vk::BlendFactor::ONE;
+        self.color_blend_attachment.dst_alpha_blend_factor = vk::BlendFactor::ONE_MINUS_SRC_ALPHA;
+        self.color_blend_attachment.alpha_blend_op = vk::BlendOp::ADD;
+        self
+    }
+
     pub fn set_depth_format(mut self, format: vk::Format) -> Self {
         self.depth_formats.push(format);
         self
     }
 
+    /// Test fragments against the depth attachment with `compare_op`, optionally writing depth
+    pub fn enable_depthtest(mut self, depth_write_enable: bool, compare_op: vk::CompareOp) -> Self {
+        self.depth_stencil.depth_test_enable = vk::TRUE;
+        self.depth_stencil.depth_write_enable = depth_write_enable.into();
+        self.depth_stencil.depth_compare_op = compare_op;
+        self.depth_stencil.depth_bounds_test_enable = vk::FALSE;
+        self.depth_stencil.stencil_test_enable = vk::FALSE;
+        self.depth_stencil.front = vk::StencilOpState::default();
+        self.depth_stencil.back = vk::StencilOpState::default();
+        self.depth_stencil.min_depth_bounds = 0.0f32;
+        self.depth_stencil.max_depth_bounds = 1.0f32;
+        self
+    }
+
     pub fn disable_depthtest(mut self) -> Self {
         self.depth_stencil.depth_test_enable = vk::FALSE;
         self.depth_stencil.depth_write_enable = vk::FALSE;
@@ -166,10 +247,10 @@ impl Pipeline {
         let layout_ci = vk::PipelineLayoutCreateInfo {
             s_type: vk::PipelineLayoutCreateInfo::STRUCTURE_TYPE,
             flags: vk::PipelineLayoutCreateFlags::empty(),
-            set_layout_count: 0,
-            p_set_layouts: ptr::null(),
-            push_constant_range_count: 0,
-            p_push_constant_ranges: ptr::null(),
+            set_layout_count: builder.layouts.len() as u32,
+            p_set_layouts: builder.layouts.as_ptr(),
+            push_constant_range_count: builder.push_constant_ranges.len() as u32,
+            p_push_constant_ranges: builder.push_constant_ranges.as_ptr(),
             ..Default::default()
         };
         builder.pipeline_layout = unsafe {
@@ -187,11 +268,14 @@ impl Pipeline {
             p_scissors: ptr::null(),
             ..Default::default()
         };
+        // Every color attachment blends the same way
+        let color_blend_attachments =
+            vec![builder.color_blend_attachment; builder.color_formats.len()];
         let color_blending = vk::PipelineColorBlendStateCreateInfo {
             s_type: vk::PipelineColorBlendStateCreateInfo::STRUCTURE_TYPE,
             logic_op: vk::LogicOp::COPY,
-            attachment_count: 1,
-            p_attachments: &builder.color_blend_attachment,
+            attachment_count: color_blend_attachments.len() as u32,
+            p_attachments: color_blend_attachments.as_ptr(),
             ..Default::default()
         };
         let vertex_info = vk::PipelineVertexInputStateCreateInfo {
@@ -205,8 +289,17 @@ impl Pipeline {
             p_dynamic_states: builder.dynamic_states_vector.as_ptr(),
             ..Default::default()
         };
+        // Attachment formats for dynamic rendering
+        builder.render_info.color_attachment_count = builder.color_formats.len() as u32;
+        builder.render_info.p_color_attachment_formats = builder.color_formats.as_ptr();
+        builder.render_info.depth_attachment_format = builder
+            .depth_formats
+            .last()
+            .copied()
+            .unwrap_or(vk::Format::UNDEFINED);
         let pipeline_ci = vk::GraphicsPipelineCreateInfo {
             s_type: vk::GraphicsPipelineCreateInfo::STRUCTURE_TYPE,
+            p_next: &builder.render_info as *const _ as *const c_void,
             flags: vk::PipelineCreateFlags::empty(),
             stage_count: builder.shader_stages.len() as u32,
             p_stages: builder.shader_stages.as_ptr(),
@@ -224,7 +317,6 @@ impl Pipeline {
             subpass: 0,
             base_pipeline_handle: vk::Pipeline::null(),
             base_pipeline_index: -1,
-            ..Default::default()
         };
         let handle = unsafe {
             device
@@ -234,6 +326,10 @@ impl Pipeline {
         }
         .pop()
         .unwrap();
+        if let Some(name) = builder.name.as_ref() {
+            device.set_object_name(handle, name);
+            device.set_object_name(builder.pipeline_layout, &format!("{} layout", name));
+        }
 
         Pipeline {
             handle,
@@ -245,6 +341,10 @@ impl Pipeline {
     pub fn get_handle(&self) -> vk::Pipeline {
         self.handle
     }
+
+    pub fn get_layout(&self) -> vk::PipelineLayout {
+        self.layout
+    }
 }
 
 impl Drop for Pipeline {
@@ -272,6 +372,7 @@ pub struct ComputePipelineBuilder {
     layouts: Vec<vk::DescriptorSetLayout>,
     shader_stage: vk::PipelineShaderStageCreateInfo,
     push_constant_range: Vec<vk::PushConstantRange>,
+    name: Option<String>,
 }
 
 impl ComputePipelineBuilder {
@@ -288,9 +389,16 @@ impl ComputePipelineBuilder {
             layouts: Vec::new(),
             shader_stage: vk::PipelineShaderStageCreateInfo::default(),
             push_constant_range: Vec::new(),
+            name: None,
         }
     }
 
+    /// Debug name given to the pipeline and its layout
+    pub fn set_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+
     pub fn set_layouts(mut self, layouts: Vec<vk::DescriptorSetLayout>) -> ComputePipelineBuilder {
         self.layouts = layouts;
         self.pipeline_layout.set_layout_count = self.layouts.len() as u32;
@@ -333,6 +441,10 @@ impl ComputePipeline {
         }
         .pop()
         .unwrap();
+        if let Some(name) = builder.name.as_ref() {
+            device.set_object_name(pipeline, name);
+            device.set_object_name(layout, &format!("{} layout", name));
+        }
         Self {
             handle: pipeline,
             layout,
@@ -0,0 +1,243 @@
+use crate::lv;
+use ash::vk;
+use std::sync::Arc;
+
+/// Counters read back from a pipeline statistics query.
+///
+/// Counters that were not enabled on the pool are left at zero.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PipelineStatistics {
+    pub input_assembly_vertices: u64,
+    pub input_assembly_primitives: u64,
+    pub vertex_shader_invocations: u64,
+    pub clipping_invocations: u64,
+    pub clipping_primitives: u64,
+    pub fragment_shader_invocations: u64,
+    pub compute_shader_invocations: u64,
+}
+
+impl PipelineStatistics {
+    /// Counters most useful for judging overdraw and culling
+    pub const DEFAULT_FLAGS: vk::QueryPipelineStatisticFlags =
+        vk::QueryPipelineStatisticFlags::from_raw(
+            vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES.as_raw()
+                | vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES.as_raw()
+                | vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS.as_raw()
+                | vk::QueryPipelineStatisticFlags::CLIPPING_INVOCATIONS.as_raw()
+                | vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES.as_raw()
+                | vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS.as_raw()
+                | vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS.as_raw(),
+        );
+
+    /// Decode the values of a single query, which are written in order of increasing flag bit
+    fn from_values(flags: vk::QueryPipelineStatisticFlags, values: &[u64]) -> Self {
+        let mut statistics = PipelineStatistics::default();
+        let mut values = values.iter();
+        for bit in 0..32 {
+            let flag = vk::QueryPipelineStatisticFlags::from_raw(1 << bit);
+            if !flags.contains(flag) {
+                continue;
+            }
+            let value = *values.next().unwrap();
+            match flag {
+                vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES => {
+                    statistics.input_assembly_vertices = value
+                }
+                vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES => {
+                    statistics.input_assembly_primitives = value
+                }
+                vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS => {
+                    statistics.vertex_shader_invocations = value
+                }
+                vk::QueryPipelineStatisticFlags::CLIPPING_INVOCATIONS => {
+                    statistics.clipping_invocations = value
+                }
+                vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES => {
+                    statistics.clipping_primitives = value
+                }
+                vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS => {
+                    statistics.fragment_shader_invocations = value
+                }
+                vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS => {
+                    statistics.compute_shader_invocations = value
+                }
+                _ => {}
+            }
+        }
+        statistics
+    }
+}
+
+pub struct QueryPool {
+    handle: vk::QueryPool,
+    query_type: vk::QueryType,
+    pipeline_statistics: vk::QueryPipelineStatisticFlags,
+
+    device: Arc<lv::Device>,
+}
+
+impl QueryPool {
+    pub fn new(
+        device: Arc<lv::Device>,
+        query_type: vk::QueryType,
+        query_count: u32,
+        name: Option<&str>,
+    ) -> Self {
+        QueryPool::create(
+            device,
+            query_type,
+            query_count,
+            vk::QueryPipelineStatisticFlags::empty(),
+            name,
+        )
+    }
+
+    /// Create a pool of pipeline statistics queries counting `statistics`.
+    ///
+    /// Requires the `PipelineStatisticsQuery` device feature.
+    pub fn new_pipeline_statistics(
+        device: Arc<lv::Device>,
+        statistics: vk::QueryPipelineStatisticFlags,
+        query_count: u32,
+        name: Option<&str>,
+    ) -> Self {
+        if !device.is_feature_enabled(lv::DeviceFeature::PipelineStatisticsQuery) {
+            panic!("Pipeline statistics queries require the PipelineStatisticsQuery feature");
+        }
+        QueryPool::create(
+            device,
+            vk::QueryType::PIPELINE_STATISTICS,
+            query_count,
+            statistics,
+            name,
+        )
+    }
+
+    fn create(
+        device: Arc<lv::Device>,
+        query_type: vk::QueryType,
+        query_count: u32,
+        pipeline_statistics: vk::QueryPipelineStatisticFlags,
+        name: Option<&str>,
+    ) -> Self {
+        let pool_ci = vk::QueryPoolCreateInfo {
+            s_type: vk::StructureType::QUERY_POOL_CREATE_INFO,
+            query_type,
+            query_count,
+            pipeline_statistics,
+            ..Default::default()
+        };
+        let handle = unsafe { device.handle.create_query_pool(&pool_ci, None).unwrap() };
+        if let Some(name) = name {
+            device.set_object_name(handle, name);
+        }
+
+        QueryPool {
+            handle,
+            query_type,
+            pipeline_statistics,
+            device,
+        }
+    }
+
+    pub fn get_handle(&self) -> vk::QueryPool {
+        self.handle
+    }
+
+    /// Record a reset of `count` queries starting at `first`. Queries must be reset before reuse
+    pub fn cmd_reset(&self, command_buffer: vk::CommandBuffer, first: u32, count: u32) {
+        unsafe {
+            self.device
+                .handle
+                .cmd_reset_query_pool(command_buffer, self.handle, first, count)
+        };
+    }
+
+    /// Start `query`, used for occlusion and pipeline statistics queries.
+    ///
+    /// `precise` requests exact sample counts from occlusion queries instead of just zero or
+    /// non-zero, which needs the `occlusionQueryPrecise` feature.
+    pub fn cmd_begin(&self, command_buffer: vk::CommandBuffer, query: u32, precise: bool) {
+        let flags = if precise {
+            vk::QueryControlFlags::PRECISE
+        } else {
+            vk::QueryControlFlags::empty()
+        };
+        unsafe {
+            self.device
+                .handle
+                .cmd_begin_query(command_buffer, self.handle, query, flags)
+        };
+    }
+
+    pub fn cmd_end(&self, command_buffer: vk::CommandBuffer, query: u32) {
+        unsafe {
+            self.device
+                .handle
+                .cmd_end_query(command_buffer, self.handle, query)
+        };
+    }
+
+    /// Read back `count` queries starting at `first` without waiting on the GPU.
+    ///
+    /// Each query yields `values_per_query` 64-bit values. Returns `None` if any of the queries
+    /// are not available yet.
+    pub fn get_results(&self, first: u32, count: u32, values_per_query: usize) -> Option<Vec<u64>> {
+        if count == 0 {
+            return Some(Vec::new());
+        }
+        let mut results: Vec<u64> = vec![0; count as usize * values_per_query];
+        let stride = std::mem::size_of::<u64>() * values_per_query;
+        // ash's wrapper assumes one value per query, so call the function pointer directly
+        let result = unsafe {
+            (self.device.handle.fp_v1_0().get_query_pool_results)(
+                self.device.handle.handle(),
+                self.handle,
+                first,
+                count,
+                stride * count as usize,
+                results.as_mut_ptr() as *mut std::ffi::c_void,
+                stride as vk::DeviceSize,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+        match result {
+            vk::Result::SUCCESS => Some(results),
+            vk::Result::NOT_READY => None,
+            err => panic!("Failed to read query pool results: {:?}", err),
+        }
+    }
+
+    /// Read back the number of samples that passed for `count` occlusion queries starting at
+    /// `first`, or `None` if they are not available yet
+    pub fn get_occlusion_results(&self, first: u32, count: u32) -> Option<Vec<u64>> {
+        assert_eq!(self.query_type, vk::QueryType::OCCLUSION);
+        self.get_results(first, count, 1)
+    }
+
+    /// Read back `count` pipeline statistics queries starting at `first`, or `None` if they are
+    /// not available yet
+    pub fn get_pipeline_statistics(
+        &self,
+        first: u32,
+        count: u32,
+    ) -> Option<Vec<PipelineStatistics>> {
+        assert_eq!(self.query_type, vk::QueryType::PIPELINE_STATISTICS);
+        let values_per_query = self.pipeline_statistics.as_raw().count_ones() as usize;
+        let values = self.get_results(first, count, values_per_query)?;
+        Some(
+            values
+                .chunks(values_per_query)
+                .map(|values| PipelineStatistics::from_values(self.pipeline_statistics, values))
+                .collect(),
+        )
+    }
+}
+
+impl Drop for QueryPool {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.handle.destroy_query_pool(self.handle, None);
+        };
+    }
+}
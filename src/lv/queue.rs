@@ -3,12 +3,13 @@ use ash::vk;
 #[derive(Clone)]
 pub struct Queue {
     pub handle: vk::Queue,
+    /// Index of the queue family this queue belongs to
     pub index: u32,
 }
 
 impl Queue {
-    pub fn new(queue_family_index: u32, device: &ash::Device) -> Queue {
-        let queue = unsafe { device.get_device_queue(queue_family_index, 0) };
+    pub fn new(queue_family_index: u32, queue_index: u32, device: &ash::Device) -> Queue {
+        let queue = unsafe { device.get_device_queue(queue_family_index, queue_index) };
         Queue {
             handle: queue,
             index: queue_family_index,
@@ -0,0 +1,36 @@
+use crate::lv;
+use ash::vk;
+use std::sync::Arc;
+
+pub struct Sampler {
+    handle: vk::Sampler,
+
+    device: Arc<lv::Device>,
+}
+
+impl Sampler {
+    pub fn new(
+        sampler_ci: &vk::SamplerCreateInfo,
+        device: Arc<lv::Device>,
+        name: Option<&str>,
+    ) -> Self {
+        let handle = unsafe { device.handle.create_sampler(sampler_ci, None).unwrap() };
+        if let Some(name) = name {
+            device.set_object_name(handle, name);
+        }
+
+        Sampler { handle, device }
+    }
+
+    pub fn get_handle(&self) -> vk::Sampler {
+        self.handle
+    }
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.handle.destroy_sampler(self.handle, None);
+        };
+    }
+}
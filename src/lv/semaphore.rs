@@ -8,13 +8,20 @@ pub struct Semaphore {
 }
 
 impl Semaphore {
-    pub fn new(device: Arc<lv::Device>, flags: Option<vk::SemaphoreCreateFlags>) -> Self {
+    pub fn new(
+        device: Arc<lv::Device>,
+        flags: Option<vk::SemaphoreCreateFlags>,
+        name: Option<&str>,
+    ) -> Self {
         let semaphore_ci = vk::SemaphoreCreateInfo {
             s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
             flags: flags.unwrap_or_default(),
             ..Default::default()
         };
         let handle = unsafe { device.handle.create_semaphore(&semaphore_ci, None).unwrap() };
+        if let Some(name) = name {
+            device.set_object_name(handle, name);
+        }
 
         Semaphore { device, handle }
     }
@@ -22,6 +29,16 @@ impl Semaphore {
     pub fn get_handle(&self) -> vk::Semaphore {
         self.handle
     }
+
+    /// Info used to wait on or signal this semaphore at `stage_mask` in a queue submission
+    pub fn submit_info(&self, stage_mask: vk::PipelineStageFlags2) -> vk::SemaphoreSubmitInfo {
+        vk::SemaphoreSubmitInfo {
+            s_type: vk::StructureType::SEMAPHORE_SUBMIT_INFO,
+            semaphore: self.handle,
+            stage_mask,
+            ..Default::default()
+        }
+    }
 }
 
 impl Drop for Semaphore {
@@ -19,7 +19,8 @@ pub struct Shader {
 }
 
 impl Shader {
-    pub fn new(path: &std::path::Path, device: Arc<lv::Device>) -> Shader {
+    /// Load a SPIR-V module from `path`. Without a `name` the module is named after the file
+    pub fn new(path: &std::path::Path, device: Arc<lv::Device>, name: Option<&str>) -> Shader {
         let shader_code = read_shader_code(path);
         let shader_ci = vk::ShaderModuleCreateInfo {
             s_type: vk::StructureType::SHADER_MODULE_CREATE_INFO,
@@ -33,6 +34,10 @@ impl Shader {
                 .create_shader_module(&shader_ci, None)
                 .unwrap()
         };
+        match name {
+            Some(name) => device.set_object_name(shader, name),
+            None => device.set_object_name(shader, &path.to_string_lossy()),
+        }
         Shader {
             handle: shader,
             device,
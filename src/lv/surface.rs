@@ -24,6 +24,25 @@ impl Surface {
             handle: surface,
         })
     }
+
+    /// Surface without a window, which needs `VK_EXT_headless_surface`. Presenting to it does
+    /// nothing, so frames can be rendered and captured without a display
+    pub fn headless(lv: &lv::Instance, loader: ash::extensions::khr::Surface) -> Arc<Surface> {
+        let headless_loader = ash::extensions::ext::HeadlessSurface::new(&lv.entry, &lv.instance);
+        let surface_ci = vk::HeadlessSurfaceCreateInfoEXT {
+            s_type: vk::StructureType::HEADLESS_SURFACE_CREATE_INFO_EXT,
+            ..Default::default()
+        };
+        let surface = unsafe {
+            headless_loader
+                .create_headless_surface(&surface_ci, None)
+                .unwrap()
+        };
+        Arc::new(Surface {
+            loader,
+            handle: surface,
+        })
+    }
 }
 
 impl Drop for Surface {
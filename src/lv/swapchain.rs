@@ -3,7 +3,6 @@ use ash::vk;
 use std::ptr;
 use std::str::from_boxed_utf8_unchecked;
 use std::sync::Arc;
-use winit::window;
 
 #[derive(Clone)]
 pub struct SwapchainSupportDetails {
@@ -13,16 +12,27 @@ pub struct SwapchainSupportDetails {
 }
 
 impl SwapchainSupportDetails {
-    pub fn choose_format(&self, preferred_formats: &[vk::Format]) -> vk::SurfaceFormatKHR {
+    /// The first of `preferred_formats` the surface supports, matching both the format and the
+    /// color space. Falls back to the first sRGB format available, then to any format
+    pub fn choose_format(
+        &self,
+        preferred_formats: &[vk::SurfaceFormatKHR],
+    ) -> vk::SurfaceFormatKHR {
         for preferred_format in preferred_formats.iter() {
             for available_format in self.formats.iter() {
-                if *preferred_format == available_format.format {
-                    return available_format.clone();
+                if preferred_format.format == available_format.format
+                    && preferred_format.color_space == available_format.color_space
+                {
+                    return *available_format;
                 }
             }
         }
 
-        self.formats.get(0).unwrap().clone()
+        *self
+            .formats
+            .iter()
+            .find(|format| format.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR)
+            .unwrap_or(self.formats.first().unwrap())
     }
 
     pub fn choose_presentation_mode(
@@ -40,16 +50,18 @@ impl SwapchainSupportDetails {
         vk::PresentModeKHR::FIFO
     }
 
-    pub fn choose_extent(&self, window: &window::Window) -> vk::Extent2D {
+    /// The surface's own extent, or `requested` clamped to the supported range if the surface
+    /// lets the swapchain decide, like headless surfaces and some window systems do
+    pub fn choose_extent(&self, requested: vk::Extent2D) -> vk::Extent2D {
         if self.capabilities.current_extent.width != u32::MAX {
             return self.capabilities.current_extent;
         }
         let extent: vk::Extent2D = vk::Extent2D {
-            width: window.inner_size().width.clamp(
+            width: requested.width.clamp(
                 self.capabilities.min_image_extent.width,
                 self.capabilities.max_image_extent.width,
             ),
-            height: window.inner_size().height.clamp(
+            height: requested.height.clamp(
                 self.capabilities.min_image_extent.height,
                 self.capabilities.max_image_extent.height,
             ),
@@ -66,6 +78,7 @@ pub struct Swapchain {
     pub image_views: Vec<vk::ImageView>,
     pub extent: vk::Extent2D,
     pub surface_format: vk::SurfaceFormatKHR,
+    pub present_mode: vk::PresentModeKHR,
     loader: ash::extensions::khr::Swapchain,
 
     // Reference-counting
@@ -74,27 +87,36 @@ pub struct Swapchain {
 }
 
 pub struct SwapchainPreferred<'a> {
-    pub preferred_format: &'a [vk::Format],
+    /// Format and color space pairs in order of preference
+    pub preferred_formats: &'a [vk::SurfaceFormatKHR],
     pub preferred_present_modes: &'a [vk::PresentModeKHR],
     pub swapchain_support_details: SwapchainSupportDetails,
 }
 
 impl Swapchain {
+    /// Create a swapchain for `surface`, sized `extent` unless the surface dictates its own size.
+    /// Passing the swapchain it replaces as `old_swapchain` retires it, it should be dropped once
+    /// the new one exists
     pub fn new(
         swapchain_loader: ash::extensions::khr::Swapchain,
         physical_device: &lv::PhysicalDevice,
         device: Arc<lv::Device>,
         surface: Arc<lv::Surface>,
         preferred: SwapchainPreferred,
-        window: &window::Window,
+        extent: vk::Extent2D,
+        old_swapchain: Option<&Swapchain>,
     ) -> Swapchain {
         let swapchain_support_details = preferred.swapchain_support_details;
-        let surface_format = swapchain_support_details.choose_format(preferred.preferred_format);
+        let surface_format = swapchain_support_details.choose_format(preferred.preferred_formats);
         let present_mode =
             swapchain_support_details.choose_presentation_mode(preferred.preferred_present_modes);
-        let extent = swapchain_support_details.choose_extent(window);
-        let image_count: u32 = (swapchain_support_details.capabilities.min_image_count + 3)
-            .min(swapchain_support_details.capabilities.max_image_count);
+        let extent = swapchain_support_details.choose_extent(extent);
+        let capabilities = &swapchain_support_details.capabilities;
+        let mut image_count: u32 = capabilities.min_image_count + 3;
+        // A maximum of 0 means there is no limit
+        if capabilities.max_image_count > 0 {
+            image_count = image_count.min(capabilities.max_image_count);
+        }
 
         let family_queues = physical_device.queue_families;
         let queue_indices =
@@ -114,18 +136,23 @@ impl Swapchain {
             image_color_space: surface_format.color_space,
             image_extent: extent,
             image_array_layers: 1,
+            // Not STORAGE, which HDR formats often do not support on swapchains
             image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT
-                | vk::ImageUsageFlags::STORAGE
                 | vk::ImageUsageFlags::TRANSFER_SRC
                 | vk::ImageUsageFlags::TRANSFER_DST,
-            image_sharing_mode: vk::SharingMode::CONCURRENT,
+            // Concurrent sharing is only valid across more than one queue family
+            image_sharing_mode: if queue_indices.len() > 1 {
+                vk::SharingMode::CONCURRENT
+            } else {
+                vk::SharingMode::EXCLUSIVE
+            },
             queue_family_index_count: queue_indices.len() as u32,
             p_queue_family_indices: queue_indices.as_ptr(),
             pre_transform: swapchain_support_details.capabilities.current_transform,
             composite_alpha: vk::CompositeAlphaFlagsKHR::OPAQUE,
             present_mode,
             clipped: vk::TRUE,
-            old_swapchain: vk::SwapchainKHR::null(),
+            old_swapchain: old_swapchain.map_or(vk::SwapchainKHR::null(), |old| old.handle),
             ..vk::SwapchainCreateInfoKHR::default()
         };
         let swapchain = unsafe {
@@ -136,6 +163,10 @@ impl Swapchain {
 
         // Retrieve swapchain images and views
         let images = unsafe { swapchain_loader.get_swapchain_images(swapchain).unwrap() };
+        device.set_object_name(swapchain, "Swapchain");
+        for (index, image) in images.iter().enumerate() {
+            device.set_object_name(*image, &format!("Swapchain image {}", index));
+        }
         let mut image_views = Vec::<vk::ImageView>::with_capacity(images.len());
         for (index, _) in images.iter().enumerate() {
             let image_view_ci = vk::ImageViewCreateInfo {
@@ -174,6 +205,7 @@ impl Swapchain {
             images,
             image_views,
             surface_format,
+            present_mode,
             extent,
             device,
             surface,
@@ -0,0 +1,123 @@
+use crate::lv::descriptors::DescriptorInfo;
+use crate::{lv, utility};
+use ash::vk;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+/// Sampled image that shaders read through the bindless resource table
+pub struct Texture {
+    image: lv::AllocatedImage,
+    sampler: Rc<lv::Sampler>,
+    /// Layout the image is in whenever shaders sample it
+    layout: vk::ImageLayout,
+}
+
+impl Texture {
+    /// Sample an image rendered on the GPU, which must be in `SHADER_READ_ONLY_OPTIMAL` whenever
+    /// shaders read it
+    pub fn from_image(image: lv::AllocatedImage, sampler: Rc<lv::Sampler>) -> Self {
+        Texture {
+            image,
+            sampler,
+            layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }
+    }
+
+    /// Sample an image that compute passes also write to through storage views, which must be in
+    /// `GENERAL` whenever shaders access it
+    pub fn from_storage_image(image: lv::AllocatedImage, sampler: Rc<lv::Sampler>) -> Self {
+        Texture {
+            image,
+            sampler,
+            layout: vk::ImageLayout::GENERAL,
+        }
+    }
+
+    /// Upload tightly packed `pixels` into a new `SHADER_READ_ONLY_OPTIMAL` image
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_pixels(
+        pixels: &[u8],
+        extent: vk::Extent2D,
+        format: vk::Format,
+        sampler: Rc<lv::Sampler>,
+        immediate: &lv::ImmediateSubmit,
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+        name: Option<&str>,
+    ) -> Self {
+        let mut staging = lv::AllocatedBuffer::new(
+            pixels.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+            device.clone(),
+            allocator.clone(),
+            Some("Texture staging"),
+        );
+        staging.write_slice(pixels);
+
+        let image_extent = vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        };
+        let image = lv::AllocatedImage::new(
+            utility::init::image_create_info(
+                format,
+                vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+                image_extent,
+            ),
+            vk::ImageAspectFlags::COLOR,
+            device.clone(),
+            allocator,
+            name,
+        );
+        immediate.upload_image(
+            image.get_handle(),
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            |command_buffer| {
+                let region = vk::BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_row_length: 0,
+                    buffer_image_height: 0,
+                    image_subresource: vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    },
+                    image_offset: vk::Offset3D::default(),
+                    image_extent,
+                };
+                unsafe {
+                    device.handle.cmd_copy_buffer_to_image(
+                        command_buffer,
+                        staging.get_handle(),
+                        image.get_handle(),
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        &[region],
+                    );
+                }
+            },
+        );
+
+        Texture {
+            image,
+            sampler,
+            layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }
+    }
+
+    pub fn get_image(&self) -> &lv::AllocatedImage {
+        &self.image
+    }
+}
+
+impl lv::traits::Resource for Texture {
+    fn get_descriptor(&self) -> DescriptorInfo {
+        DescriptorInfo::Image(vk::DescriptorImageInfo {
+            image_view: self.image.get_view(),
+            image_layout: self.layout,
+            sampler: self.sampler.get_handle(),
+        })
+    }
+}
@@ -0,0 +1,103 @@
+use crate::lv;
+use ash::vk;
+use ash::vk::TaggedStructure;
+use std::ffi::c_void;
+use std::sync::Arc;
+
+/// Semaphore with a monotonically increasing 64-bit payload that can be waited on and
+/// signaled from both the host and the GPU
+///
+/// ```ignore
+/// let timeline = lv::TimelineSemaphore::new(device, 0, Some("Example timeline"));
+/// timeline.signal(1);
+/// assert!(timeline.wait(1, 0));
+/// assert_eq!(timeline.get_value(), 1);
+/// ```
+pub struct TimelineSemaphore {
+    device: Arc<lv::Device>,
+    handle: vk::Semaphore,
+}
+
+impl TimelineSemaphore {
+    pub fn new(device: Arc<lv::Device>, initial_value: u64, name: Option<&str>) -> Self {
+        let type_ci = vk::SemaphoreTypeCreateInfo {
+            s_type: vk::SemaphoreTypeCreateInfo::STRUCTURE_TYPE,
+            semaphore_type: vk::SemaphoreType::TIMELINE,
+            initial_value,
+            ..Default::default()
+        };
+        let semaphore_ci = vk::SemaphoreCreateInfo {
+            s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
+            p_next: &type_ci as *const _ as *const c_void,
+            ..Default::default()
+        };
+        let handle = unsafe { device.handle.create_semaphore(&semaphore_ci, None).unwrap() };
+        if let Some(name) = name {
+            device.set_object_name(handle, name);
+        }
+
+        TimelineSemaphore { device, handle }
+    }
+
+    /// Current payload of the semaphore as seen by the host
+    pub fn get_value(&self) -> u64 {
+        unsafe {
+            self.device
+                .handle
+                .get_semaphore_counter_value(self.handle)
+                .unwrap()
+        }
+    }
+
+    /// Set the payload to `value` from the host. `value` must be greater than the current payload
+    pub fn signal(&self, value: u64) {
+        let signal_info = vk::SemaphoreSignalInfo {
+            s_type: vk::SemaphoreSignalInfo::STRUCTURE_TYPE,
+            semaphore: self.handle,
+            value,
+            ..Default::default()
+        };
+        unsafe { self.device.handle.signal_semaphore(&signal_info).unwrap() };
+    }
+
+    /// Block the host until the payload reaches at least `value`.
+    ///
+    /// Returns false if `timeout` (in nanoseconds) elapsed before that happened.
+    pub fn wait(&self, value: u64, timeout: u64) -> bool {
+        let wait_info = vk::SemaphoreWaitInfo {
+            s_type: vk::SemaphoreWaitInfo::STRUCTURE_TYPE,
+            semaphore_count: 1,
+            p_semaphores: &self.handle,
+            p_values: &value,
+            ..Default::default()
+        };
+        match unsafe { self.device.handle.wait_semaphores(&wait_info, timeout) } {
+            Ok(_) => true,
+            Err(vk::Result::TIMEOUT) => false,
+            Err(err) => panic!("Failed to wait on timeline semaphore: {:?}", err),
+        }
+    }
+
+    /// Info used to have a queue submission set the payload to `value` once `stage_mask` completes
+    pub fn signal_info(
+        &self,
+        value: u64,
+        stage_mask: vk::PipelineStageFlags2,
+    ) -> vk::SemaphoreSubmitInfo {
+        vk::SemaphoreSubmitInfo {
+            s_type: vk::SemaphoreSubmitInfo::STRUCTURE_TYPE,
+            semaphore: self.handle,
+            value,
+            stage_mask,
+            ..Default::default()
+        }
+    }
+}
+
+impl Drop for TimelineSemaphore {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.handle.destroy_semaphore(self.handle, None);
+        };
+    }
+}
@@ -1,14 +1,51 @@
-use crate::frame::FrameData;
+use crate::antialiasing::{AntiAliasing, AntiAliasingSettings};
+use crate::bloom::BloomSettings;
+use crate::camera::{Camera, CameraController, CameraMode, CameraUniforms, Projection};
+use crate::display::{DisplaySettings, OutputEncoding};
+use crate::frame::{frame_complete_value, FrameData};
+use crate::frame_pacing::{
+    FrameLimiter, FramePacingSettings, FrameTimeStats, FrameTimeSummary, PresentMode,
+};
+use crate::gradient::GradientSettings;
+use crate::input::{Action, Input, InputMap};
+use crate::light::{Light, LightData, LightingHeader};
+use crate::material::{AlphaMode, Material, MaterialData, MaterialLibrary};
+use crate::mesh::{Mesh, MeshData};
+use crate::msaa::MsaaSettings;
+use crate::overlay::{Overlay, OverlayDraw, OverlayVertex};
+use crate::render_scale::{RenderScaleSettings, UpscaleFilter};
+use crate::scene::{DrawList, InstanceData, MaterialId, MeshId, NodeId, Scene};
+use crate::screenshot::{AutoCapture, PendingScreenshot, ScreenshotRequest, ScreenshotSource};
+use crate::shadow::{ShadowData, ShadowSettings};
+use crate::tonemap::TonemapSettings;
 use ash::vk::TaggedStructure;
 use ash::{self, vk};
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
-use std::ffi::CString;
+use std::ffi::{c_void, CStr, CString};
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
 use winit::{self};
 
+mod antialiasing;
+mod bloom;
+mod camera;
+mod display;
 mod frame;
+mod frame_pacing;
+mod gradient;
+mod input;
+mod light;
 mod lv;
+mod material;
+mod mesh;
+mod msaa;
+mod overlay;
+mod render_scale;
+mod scene;
+mod screenshot;
+mod shadow;
+mod tonemap;
 mod utility;
 mod vk_descriptors;
 
@@ -17,104 +54,403 @@ const WINDOW_TITLE: &str = "Hello, Vulkan!";
 const WINDOW_WIDTH: u32 = 800;
 const WINDOW_HEIGHT: u32 = 600;
 const FRAME_OVERLAP: u32 = 2;
-
-#[derive()]
-struct ValidationInfo {
-    pub is_enabled: bool,
-    pub required_validation_layers: [&'static str; 1],
-}
+const GPU_PROFILER_MAX_SCOPES: u32 = 16;
+/// How often the averaged GPU pass timings are logged
+const GPU_PROFILER_LOG_INTERVAL: u64 = 1000;
+/// Directory screenshots taken with the screenshot actions are saved to
+const SCREENSHOT_DIRECTORY: &str = "screenshots";
+/// Optional file overriding the default input bindings, see `InputMap::apply_config`
+const BINDINGS_PATH: &str = "bindings.cfg";
+/// Frame rate the window is rendered at while it is in the background
+const BACKGROUND_FPS: f32 = 30.0;
+/// Number of instances each frame's instance buffer starts with room for
+const INITIAL_INSTANCE_CAPACITY: usize = 1024;
+/// Number of materials each frame's material buffer starts with room for
+const INITIAL_MATERIAL_CAPACITY: usize = 64;
+/// Number of lights each frame's light buffer starts with room for
+const INITIAL_LIGHT_CAPACITY: usize = 256;
+/// Number of overlay vertices each frame's overlay buffers start with room for
+const INITIAL_OVERLAY_VERTEX_CAPACITY: usize = 1024;
+/// Format of the HDR image passes render into
+const DRAW_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
+const DEPTH_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+/// Stops the exposure changes by per key press
+const EXPOSURE_STEP: f32 = 0.5;
+/// Format of the screen-space motion the geometry pass writes for TAA
+const VELOCITY_FORMAT: vk::Format = vk::Format::R16G16_SFLOAT;
+/// Animation time step used during automatic captures, so they render the same every run
+const CAPTURE_TIME_STEP: f32 = 1.0 / 60.0;
+/// Anisotropic filtering level of material textures when the device supports it
+const MAX_ANISOTROPY: f32 = 16.0;
 
 struct VulkanApp {
     handle: Arc<lv::Instance>,
-    debug_messenger: Arc<lv::DebugMessenger>,
+    debug_messenger: Option<Arc<lv::DebugMessenger>>,
     surface: Arc<lv::Surface>,
     physical_device: Arc<lv::PhysicalDevice>,
     logical_device: Arc<lv::Device>,
     allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
     swapchain: lv::Swapchain,
 
+    /// Size of the render targets, the swapchain extent scaled by the render scale
     draw_extent: vk::Extent2D,
-    draw_image_index: u32,
+    targets: RenderTargets,
+    render_scale_settings: RenderScaleSettings,
+    display_settings: DisplaySettings,
+    /// How tonemapping encodes for the swapchain's color space
+    output_encoding: OutputEncoding,
+    /// Storage image index of the sharpened image at the swapchain extent
+    sharpen_image_index: u32,
     frames: Vec<FrameData>,
     frame_count: u64,
+    frame_timeline: lv::TimelineSemaphore,
+    profiler: lv::GpuProfiler,
+    /// One pipeline statistics query per frame in flight, if the device supports them
+    pipeline_statistics: Option<lv::QueryPool>,
+    last_pipeline_statistics: Option<lv::PipelineStatistics>,
+    /// One occlusion query per frame in flight around the geometry pass
+    occlusion_queries: lv::QueryPool,
+    /// Samples of the geometry pass that passed the depth test, from the last resolved query
+    last_visible_samples: Option<u64>,
+    screenshot_request: Option<ScreenshotRequest>,
+    auto_capture: Option<AutoCapture>,
+    /// Validation messages reported during an automatic capture, which fails if any are errors
+    validation_capture: Option<lv::ValidationCapture>,
+    input: Input,
+    /// Where the cursor was when looking around started, to put it back afterwards
+    look_cursor_position: Option<glam::Vec2>,
+    passes: RenderPasses,
+    camera: Camera,
+    camera_controller: CameraController,
+    last_frame_time: std::time::Instant,
+    frame_pacing_settings: FramePacingSettings,
+    frame_limiter: FrameLimiter,
+    frame_time_stats: FrameTimeStats,
+    /// Seconds the current frame advances animation and exposure adaptation by
+    time_step: f32,
+    meshes: Vec<Mesh>,
+    scene: Scene,
+    /// Node spun around by the demo animation, if the scene is animated
+    scene_root: Option<NodeId>,
+    /// Children of `scene_root` taken out by [`Action::ToggleSatellites`], to add them back
+    removed_satellites: Vec<RemovedNode>,
+    scene_time: f32,
+    draw_list: DrawList,
+    materials: MaterialLibrary,
+    lights: Vec<Light>,
+    /// Used for uploads outside the frame loop
+    immediate_submit: lv::ImmediateSubmit,
+    /// Bilinear sampler clamping at the edges, shared by the render targets
+    clamp_sampler: Rc<lv::Sampler>,
+    msaa_settings: MsaaSettings,
+    shadow_settings: ShadowSettings,
+    /// Texture index of the shadow map, one layer per cascade
+    shadow_map_index: u32,
+    tonemap_settings: TonemapSettings,
+    gradient_settings: GradientSettings,
+    overlay: Overlay,
+    /// Draws of the overlay meshes uploaded for this frame
+    overlay_draws: Vec<OverlayDraw>,
+    /// Luminance histogram of the draw image, cleared again once averaged
+    histogram_buffer: lv::AllocatedBuffer,
+    /// Luminance auto-exposure has adapted to
+    exposure_buffer: lv::AllocatedBuffer,
+    bloom_settings: BloomSettings,
+    anti_aliasing_settings: AntiAliasingSettings,
+    /// Last frame TAA wrote its history in, to tell whether the previous history is usable
+    last_taa_frame: Option<u64>,
+    /// Unjittered view projection of the previous frame, for motion vectors
+    previous_view_projection: glam::Mat4,
 
     gpu_resource_table: lv::descriptors::ShaRT,
 
     gradient_pipeline: Rc<lv::ComputePipeline>,
-    triangle_pipeline: Rc<lv::Pipeline>,
+    light_cull_pipeline: Rc<lv::ComputePipeline>,
+    shadow_pipeline: Rc<lv::Pipeline>,
+    lit_pipeline: Rc<lv::Pipeline>,
+    msaa_load_pipeline: Rc<lv::Pipeline>,
+    histogram_pipeline: Rc<lv::ComputePipeline>,
+    exposure_pipeline: Rc<lv::ComputePipeline>,
+    tonemap_pipeline: Rc<lv::ComputePipeline>,
+    bloom_prefilter_pipeline: Rc<lv::ComputePipeline>,
+    bloom_downsample_pipeline: Rc<lv::ComputePipeline>,
+    bloom_upsample_pipeline: Rc<lv::ComputePipeline>,
+    bloom_composite_pipeline: Rc<lv::ComputePipeline>,
+    taa_pipeline: Rc<lv::ComputePipeline>,
+    fxaa_pipeline: Rc<lv::ComputePipeline>,
+    sharpen_pipeline: Rc<lv::ComputePipeline>,
+    overlay_pipeline: Rc<lv::Pipeline>,
 }
 
-const VALIDATION: ValidationInfo = ValidationInfo {
-    is_enabled: true,
-    required_validation_layers: ["VK_LAYER_KHRONOS_validation"],
-};
+/// Images sized by the draw extent, recreated when it changes
+struct RenderTargets {
+    /// Storage image index of the HDR image passes render into
+    draw_image_index: u32,
+    /// Multisampled when MSAA is enabled
+    depth_image: lv::AllocatedImage,
+    /// Color image the geometry pass renders into and resolves from, if MSAA is enabled
+    msaa_color_image: Option<lv::AllocatedImage>,
+    /// Velocity image the geometry pass renders into and resolves from, if MSAA is enabled
+    msaa_velocity_image: Option<lv::AllocatedImage>,
+    /// Texture index of the velocity the geometry pass writes
+    velocity_texture_index: u32,
+    /// Texture index of the tonemapped image, presented unless FXAA is on
+    ldr_texture_index: u32,
+    /// Storage view index tonemapping writes the LDR image through
+    ldr_view_index: u32,
+    /// Texture index of the FXAA output, presented when FXAA is on
+    fxaa_texture_index: u32,
+    /// Storage view index FXAA writes its output through
+    fxaa_view_index: u32,
+    /// Texture index of each frame in flight's TAA history
+    taa_history_indices: Vec<u32>,
+    /// Storage view index of each frame in flight's TAA history
+    taa_history_view_indices: Vec<u32>,
+    /// Texture index of the bloom mip chain
+    bloom_texture_index: u32,
+    /// Storage view index of each mip level of the bloom chain
+    bloom_view_indices: Vec<u32>,
+}
+
+impl RenderTargets {
+    /// Release the targets' resource table entries. The GPU must be done with them
+    fn free(self, gpu_resource_table: &mut lv::descriptors::ShaRT) {
+        // Views go before the images they view
+        let view_indices = [self.ldr_view_index, self.fxaa_view_index];
+        for index in view_indices
+            .into_iter()
+            .chain(self.taa_history_view_indices)
+            .chain(self.bloom_view_indices)
+        {
+            gpu_resource_table.free_storage_view(index);
+        }
+        let texture_indices = [
+            self.velocity_texture_index,
+            self.ldr_texture_index,
+            self.fxaa_texture_index,
+            self.bloom_texture_index,
+        ];
+        for index in texture_indices.into_iter().chain(self.taa_history_indices) {
+            gpu_resource_table.free_texture(index);
+        }
+        gpu_resource_table.free_storage_image(self.draw_image_index);
+    }
+}
+
+const VALIDATION: bool = true;
+/// Environment variable with a comma separated list of extra instance layers to enable, e.g.
+/// `VK_LAYER_LUNARG_api_dump`
+const LAYERS_ENV: &str = "LV_LAYERS";
+
+/// Environment variable with a comma separated list of passes to render, e.g. `gradient,geometry`
+const PASSES_ENV: &str = "LV_PASSES";
+
+/// Which passes get recorded each frame, so tests can render them in isolation
+#[derive(Clone, Copy, Debug)]
+struct RenderPasses {
+    gradient: bool,
+    geometry: bool,
+    bloom: bool,
+}
+
+impl RenderPasses {
+    fn from_env() -> RenderPasses {
+        let passes = match std::env::var(PASSES_ENV) {
+            Ok(passes) => passes,
+            Err(_) => {
+                return RenderPasses {
+                    gradient: true,
+                    geometry: true,
+                    bloom: true,
+                }
+            }
+        };
+        let mut enabled = RenderPasses {
+            gradient: false,
+            geometry: false,
+            bloom: false,
+        };
+        for pass in passes
+            .split(',')
+            .map(str::trim)
+            .filter(|pass| !pass.is_empty())
+        {
+            match pass {
+                "gradient" => enabled.gradient = true,
+                "geometry" => enabled.geometry = true,
+                "bloom" => enabled.bloom = true,
+                _ => log::warn!("Ignoring unknown pass {:?} in {}", pass, PASSES_ENV),
+            }
+        }
+        enabled
+    }
+}
+
+/// Environment variable selecting the scene, `demo` or `triangle`
+const SCENE_ENV: &str = "LV_SCENE";
+
+/// What is needed to add a node without children back to the scene after removing it
+struct RemovedNode {
+    name: String,
+    local: glam::Affine3A,
+    mesh: Option<MeshId>,
+    material: Option<MaterialId>,
+}
+
+/// Scene the geometry pass renders
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DemoScene {
+    /// Animated meshes and materials lit by the demo lights
+    Demo,
+    /// A single unlit triangle and no lights, so golden tests do not change with the lighting
+    Triangle,
+}
+
+impl DemoScene {
+    fn from_env() -> DemoScene {
+        match std::env::var(SCENE_ENV).as_deref() {
+            Err(_) | Ok("demo") => DemoScene::Demo,
+            Ok("triangle") => DemoScene::Triangle,
+            Ok(scene) => {
+                log::warn!("Ignoring unknown scene {:?} in {}", scene, SCENE_ENV);
+                DemoScene::Demo
+            }
+        }
+    }
+}
 
+/// Push constants of the geometry pipelines
 #[repr(C)]
-struct ComputePushConstants {
-    data1: [f32; 4],
-    data2: [f32; 4],
-    data3: [f32; 4],
-    data4: [f32; 4],
+struct GeometryPushConstants {
+    camera: vk::DeviceAddress,
+    vertices: vk::DeviceAddress,
+    instances: vk::DeviceAddress,
+    materials: vk::DeviceAddress,
+    lights: vk::DeviceAddress,
+    clusters: vk::DeviceAddress,
+    shadows: vk::DeviceAddress,
+    material_index: u32,
+}
+
+/// Where frames are presented
+#[derive(Clone, Copy)]
+enum PresentTarget<'a> {
+    Window(&'a winit::window::Window),
+    /// A `VK_EXT_headless_surface` of the given size, so automatic captures run without a display
+    Headless(vk::Extent2D),
+}
+
+impl PresentTarget<'_> {
+    fn get_window(&self) -> Option<&winit::window::Window> {
+        match self {
+            PresentTarget::Window(window) => Some(window),
+            PresentTarget::Headless(_) => None,
+        }
+    }
+
+    /// Size the swapchain should have, unless the surface decides
+    fn get_extent(&self) -> vk::Extent2D {
+        match self {
+            PresentTarget::Window(window) => vk::Extent2D {
+                width: window.inner_size().width,
+                height: window.inner_size().height,
+            },
+            PresentTarget::Headless(extent) => *extent,
+        }
+    }
+
+    fn get_required_extensions(&self) -> Vec<&'static CStr> {
+        match self {
+            PresentTarget::Window(window) => {
+                ash_window::enumerate_required_extensions(window.raw_display_handle())
+                    .unwrap()
+                    .iter()
+                    // SAFETY: ash_window hands out pointers to the extension name constants in ash
+                    .map(|name| unsafe { CStr::from_ptr(*name) })
+                    .collect()
+            }
+            PresentTarget::Headless(_) => vec![
+                ash::extensions::khr::Surface::name(),
+                ash::extensions::ext::HeadlessSurface::name(),
+            ],
+        }
+    }
 }
 
 impl VulkanApp {
-    pub fn new(window: &winit::window::Window) -> VulkanApp {
+    /// Set up rendering to `target`. With `auto_capture` the given frame is saved, and any
+    /// validation error reported until then fails the run
+    fn new(target: PresentTarget, auto_capture: Option<AutoCapture>) -> VulkanApp {
         // Init vulkan stuff
-        let instance = Arc::new(lv::Instance::new(
-            VulkanApp::get_required_extensions(window),
-            true,
-        ));
+        let instance_builder = lv::InstanceBuilder::new()
+            .app_name(WINDOW_TITLE)
+            .app_version(0, 1, 0)
+            .api_version(vk::API_VERSION_1_3)
+            .extensions(&target.get_required_extensions())
+            // HDR color spaces for the swapchain
+            .optional_extension(vk::ExtSwapchainColorspaceFn::name())
+            .validation(VALIDATION)
+            .validation_features(lv::ValidationFeatures {
+                synchronization: true,
+                ..Default::default()
+            })
+            // Keep object names and pass labels for capture tools when validation is off
+            .debug_utils(true);
+        let instance_builder = std::env::var(LAYERS_ENV)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|layer| !layer.is_empty())
+            .fold(instance_builder, |builder, layer| builder.layer(layer));
+        let instance = Arc::new(lv::Instance::from_builder(instance_builder));
 
-        let required_extensions = vec![
-            ash::extensions::khr::Swapchain::name()
-                .to_string_lossy()
-                .into_owned(),
-            ash::extensions::khr::DynamicRendering::name()
-                .to_string_lossy()
-                .into_owned(),
-            ash::extensions::khr::Synchronization2::name()
-                .to_string_lossy()
-                .into_owned(),
-            // BDAs
-            ash::extensions::khr::BufferDeviceAddress::name()
-                .to_string_lossy()
-                .into_owned(),
-            // RT extensions
-            /*
-            ash::extensions::khr::DeferredHostOperations::name()
-                .to_string_lossy()
-                .into_owned(),
-            ash::extensions::khr::AccelerationStructure::name()
-                .to_string_lossy()
-                .into_owned(),
-            ash::extensions::khr::RayTracingPipeline::name()
-                .to_string_lossy()
-                .into_owned(),
-
-             */
-        ];
+        let debug_messenger = if instance.validation_enabled {
+            Some(lv::DebugMessenger::new(instance.clone()))
+        } else {
+            None
+        };
+        // Collect validation messages from here on, when the messenger reports them
+        let validation_capture = debug_messenger
+            .as_deref()
+            .filter(|_| auto_capture.is_some())
+            .map(lv::ValidationCapture::install);
+        let device_builder = VulkanApp::device_requirements();
 
         let surface_loader =
             ash::extensions::khr::Surface::new(&instance.entry, &instance.instance);
-        let surface = lv::Surface::new(
-            &instance,
-            surface_loader.clone(),
-            window.raw_display_handle(),
-            window.raw_window_handle(),
-        );
+        let surface = match target {
+            PresentTarget::Window(window) => lv::Surface::new(
+                &instance,
+                surface_loader.clone(),
+                window.raw_display_handle(),
+                window.raw_window_handle(),
+            ),
+            PresentTarget::Headless(_) => lv::Surface::headless(&instance, surface_loader.clone()),
+        };
         let physical_device = VulkanApp::pick_physical_devices(
             instance.clone(),
             &surface_loader,
             surface.handle,
-            required_extensions.clone(),
+            &device_builder,
+            lv::GpuPreference::from_env(),
         )
-        .unwrap();
-        let logical_device = lv::Device::new(
-            physical_device.clone(),
-            Some(required_extensions),
-            instance.clone(),
-        );
-        let debug_messenger = lv::DebugMessenger::new(instance.clone());
+        .expect("Could not find a suitable GPU");
+        let logical_device =
+            lv::Device::from_builder(device_builder, physical_device.clone(), instance.clone())
+                .expect("Failed to create logical device");
+        for (heap, (budget, usage)) in logical_device
+            .get_memory_budgets()
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+        {
+            log::info!(
+                "Memory heap {}: {} MiB of {} MiB budget in use",
+                heap,
+                usage >> 20,
+                budget >> 20
+            );
+        }
 
         let swapchain_loader =
             ash::extensions::khr::Swapchain::new(&instance.instance, &logical_device.handle);
@@ -132,6 +468,11 @@ impl VulkanApp {
             .unwrap(),
         ));
 
+        let display_settings = DisplaySettings::from_env();
+        let frame_pacing_settings = FramePacingSettings::from_env();
+        let preferred_formats = display_settings.get_preferred_surface_formats(
+            instance.is_extension_enabled(vk::ExtSwapchainColorspaceFn::name()),
+        );
         let swapchain = lv::Swapchain::new(
             swapchain_loader,
             &physical_device,
@@ -139,69 +480,323 @@ impl VulkanApp {
             surface.clone(),
             lv::SwapchainPreferred {
                 swapchain_support_details: swapchain_support.clone(),
-                preferred_format: &[vk::Format::R8G8B8_SRGB],
-                preferred_present_modes: &[vk::PresentModeKHR::MAILBOX],
+                preferred_formats: &preferred_formats,
+                preferred_present_modes: &[frame_pacing_settings.present_mode.get_vk()],
             },
-            window,
+            target.get_extent(),
+            None,
         );
-        // create image that is rendered to
-        let draw_extent = swapchain.extent;
-        let draw_image_extent = vk::Extent3D {
-            height: swapchain.extent.height,
-            width: swapchain.extent.width,
-            depth: 1,
-        };
-        let draw_image = lv::AllocatedImage::new(
-            utility::init::image_create_info(
-                vk::Format::R16G16B16A16_SFLOAT,
-                vk::ImageUsageFlags::TRANSFER_SRC
-                    | vk::ImageUsageFlags::TRANSFER_DST
-                    | vk::ImageUsageFlags::STORAGE
-                    | vk::ImageUsageFlags::COLOR_ATTACHMENT,
-                draw_image_extent,
-            ),
-            vk::ImageAspectFlags::COLOR,
+        VulkanApp::log_present_mode(frame_pacing_settings.present_mode, &swapchain);
+        let output_encoding = OutputEncoding::from_surface_format(swapchain.surface_format);
+        log::info!(
+            "Swapchain format: {:?} in {:?}, output encoding: {}",
+            swapchain.surface_format.format,
+            swapchain.surface_format.color_space,
+            output_encoding.name()
+        );
+        let msaa_settings = MsaaSettings::from_env(
+            &physical_device.properties.properties.limits,
+            logical_device.is_feature_enabled(lv::DeviceFeature::SampleRateShading),
+        );
+        let render_scale_settings = RenderScaleSettings::from_env();
+        let draw_extent = render_scale_settings.scaled_extent(swapchain.extent);
+        let sharpen_image = VulkanApp::create_sharpen_image(
+            swapchain.extent,
+            output_encoding.get_image_format(),
             logical_device.clone(),
             allocator.clone(),
         );
 
-        let triangle_pipeline = VulkanApp::create_triangle_pipeline(
-            logical_device.clone(),
-            &swapchain_support,
-            draw_image.get_format(),
-        );
         let mut frames: Vec<FrameData> = Vec::with_capacity(FRAME_OVERLAP as usize);
-        for _ in 0..FRAME_OVERLAP {
+        for frame_index in 0..FRAME_OVERLAP {
             let pool = lv::CommandPool::new(
                 vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
-                logical_device
-                    .queues
-                    .get(&physical_device.queue_families.graphics_family.unwrap())
-                    .unwrap(),
+                logical_device.graphics_queue(),
+                logical_device.clone(),
+            );
+            let main_command_buffer = lv::CommandBuffer::new(
+                &pool,
+                vk::CommandBufferLevel::PRIMARY,
+                &logical_device,
+                Some(&format!("Frame {} command buffer", frame_index)),
+            );
+            let render_semaphore = lv::Semaphore::new(
+                logical_device.clone(),
+                None,
+                Some(&format!("Frame {} render semaphore", frame_index)),
+            );
+            let swapchain_semaphore = lv::Semaphore::new(
+                logical_device.clone(),
+                None,
+                Some(&format!("Frame {} swapchain semaphore", frame_index)),
+            );
+
+            let camera_buffer = lv::AllocatedBuffer::new(
+                std::mem::size_of::<CameraUniforms>() as vk::DeviceSize,
+                vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                gpu_allocator::MemoryLocation::CpuToGpu,
+                logical_device.clone(),
+                allocator.clone(),
+                Some(&format!("Frame {} camera", frame_index)),
+            );
+            let instance_buffer = VulkanApp::create_storage_buffer(
+                logical_device.clone(),
+                allocator.clone(),
+                (INITIAL_INSTANCE_CAPACITY * std::mem::size_of::<InstanceData>()) as vk::DeviceSize,
+                &format!("Frame {} instances", frame_index),
+            );
+            let material_buffer = VulkanApp::create_storage_buffer(
+                logical_device.clone(),
+                allocator.clone(),
+                (INITIAL_MATERIAL_CAPACITY * std::mem::size_of::<MaterialData>()) as vk::DeviceSize,
+                &format!("Frame {} materials", frame_index),
+            );
+            let light_buffer = VulkanApp::create_storage_buffer(
+                logical_device.clone(),
+                allocator.clone(),
+                (std::mem::size_of::<LightingHeader>()
+                    + INITIAL_LIGHT_CAPACITY * std::mem::size_of::<LightData>())
+                    as vk::DeviceSize,
+                &format!("Frame {} lights", frame_index),
+            );
+            let cluster_buffer = lv::AllocatedBuffer::new(
+                light::cluster_buffer_size(),
+                vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+                gpu_allocator::MemoryLocation::GpuOnly,
+                logical_device.clone(),
+                allocator.clone(),
+                Some(&format!("Frame {} light clusters", frame_index)),
+            );
+            let shadow_buffer = VulkanApp::create_storage_buffer(
+                logical_device.clone(),
+                allocator.clone(),
+                std::mem::size_of::<ShadowData>() as vk::DeviceSize,
+                &format!("Frame {} shadows", frame_index),
+            );
+            let overlay_vertex_buffer = VulkanApp::create_storage_buffer(
+                logical_device.clone(),
+                allocator.clone(),
+                (INITIAL_OVERLAY_VERTEX_CAPACITY * std::mem::size_of::<OverlayVertex>())
+                    as vk::DeviceSize,
+                &format!("Frame {} overlay vertices", frame_index),
+            );
+            // Quads take six indices for four vertices
+            let overlay_index_buffer = VulkanApp::create_storage_buffer(
                 logical_device.clone(),
+                allocator.clone(),
+                (INITIAL_OVERLAY_VERTEX_CAPACITY * 6 / 4 * std::mem::size_of::<u32>())
+                    as vk::DeviceSize,
+                &format!("Frame {} overlay indices", frame_index),
             );
-            let main_command_buffer =
-                lv::CommandBuffer::new(&pool, vk::CommandBufferLevel::PRIMARY, &logical_device);
-            let render_semaphore = lv::Semaphore::new(logical_device.clone(), None);
-            let swapchain_semaphore = lv::Semaphore::new(logical_device.clone(), None);
-            let render_fence =
-                lv::Fence::new(logical_device.clone(), Some(vk::FenceCreateFlags::SIGNALED));
 
             frames.push(FrameData {
                 pool,
                 main_command_buffer,
                 render_semaphore,
-                render_fence,
                 swapchain_semaphore,
+                camera_buffer,
+                instance_buffer,
+                material_buffer,
+                light_buffer,
+                cluster_buffer,
+                shadow_buffer,
+                overlay_vertex_buffer,
+                overlay_index_buffer,
+                screenshot: None,
             })
         }
-        let (gpu_resource_table, draw_image_index) =
-            VulkanApp::init_descriptors(logical_device.clone(), draw_image);
+        let frame_timeline =
+            lv::TimelineSemaphore::new(logical_device.clone(), 0, Some("Frame timeline"));
+        let profiler = lv::GpuProfiler::new(
+            logical_device.clone(),
+            &physical_device,
+            FRAME_OVERLAP,
+            GPU_PROFILER_MAX_SCOPES,
+        );
+        let pipeline_statistics =
+            if logical_device.is_feature_enabled(lv::DeviceFeature::PipelineStatisticsQuery) {
+                Some(lv::QueryPool::new_pipeline_statistics(
+                    logical_device.clone(),
+                    lv::PipelineStatistics::DEFAULT_FLAGS,
+                    FRAME_OVERLAP,
+                    Some("Pipeline statistics"),
+                ))
+            } else {
+                None
+            };
+        let occlusion_queries = lv::QueryPool::new(
+            logical_device.clone(),
+            vk::QueryType::OCCLUSION,
+            FRAME_OVERLAP,
+            Some("Geometry occlusion"),
+        );
+        let mut gpu_resource_table = lv::descriptors::ShaRT::new(logical_device.clone());
+        let lit_pipeline = VulkanApp::create_lit_pipeline(
+            logical_device.clone(),
+            &swapchain_support,
+            *gpu_resource_table.get_layout(),
+            DRAW_FORMAT,
+            &msaa_settings,
+        );
+        let overlay_pipeline = VulkanApp::create_overlay_pipeline(
+            logical_device.clone(),
+            *gpu_resource_table.get_layout(),
+            swapchain.surface_format.format,
+        );
+        let msaa_load_pipeline = VulkanApp::create_msaa_load_pipeline(
+            logical_device.clone(),
+            *gpu_resource_table.get_layout(),
+            DRAW_FORMAT,
+            msaa_settings.samples,
+        );
+        let shadow_settings = ShadowSettings::from_env();
+        let shadow_pipeline =
+            VulkanApp::create_shadow_pipeline(logical_device.clone(), &shadow_settings);
         let gradient_pipeline = VulkanApp::init_background_pipelines(
             logical_device.clone(),
             *gpu_resource_table.get_layout(),
         );
         let gradient_pipeline = Rc::new(gradient_pipeline);
+        let light_cull_pipeline = Rc::new(VulkanApp::create_light_cull_pipeline(
+            logical_device.clone(),
+        ));
+        let (histogram_pipeline, exposure_pipeline, tonemap_pipeline) =
+            VulkanApp::create_tonemap_pipelines(
+                logical_device.clone(),
+                *gpu_resource_table.get_layout(),
+            );
+        let (
+            bloom_prefilter_pipeline,
+            bloom_downsample_pipeline,
+            bloom_upsample_pipeline,
+            bloom_composite_pipeline,
+        ) = VulkanApp::create_bloom_pipelines(
+            logical_device.clone(),
+            *gpu_resource_table.get_layout(),
+        );
+        let (taa_pipeline, fxaa_pipeline) = VulkanApp::create_antialiasing_pipelines(
+            logical_device.clone(),
+            *gpu_resource_table.get_layout(),
+        );
+        let sharpen_pipeline = VulkanApp::create_sharpen_pipeline(
+            logical_device.clone(),
+            *gpu_resource_table.get_layout(),
+        );
+        let meshes = vec![
+            Mesh::new(
+                &MeshData::triangle(),
+                logical_device.clone(),
+                allocator.clone(),
+                "Triangle",
+            ),
+            Mesh::new(
+                &MeshData::quad(),
+                logical_device.clone(),
+                allocator.clone(),
+                "Quad",
+            ),
+        ];
+        let immediate_submit = lv::ImmediateSubmit::new(
+            logical_device.clone(),
+            logical_device.graphics_queue(),
+            logical_device.transfer_queue(),
+            Some("Immediate submit"),
+        );
+        let (histogram_buffer, exposure_buffer) = VulkanApp::create_exposure_buffers(
+            &immediate_submit,
+            logical_device.clone(),
+            allocator.clone(),
+        );
+        let anisotropy_enable =
+            logical_device.is_feature_enabled(lv::DeviceFeature::SamplerAnisotropy);
+        // Trilinear and repeating, the material textures keep it alive
+        let default_sampler = Rc::new(lv::Sampler::new(
+            &vk::SamplerCreateInfo {
+                s_type: vk::StructureType::SAMPLER_CREATE_INFO,
+                mag_filter: vk::Filter::LINEAR,
+                min_filter: vk::Filter::LINEAR,
+                mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+                address_mode_u: vk::SamplerAddressMode::REPEAT,
+                address_mode_v: vk::SamplerAddressMode::REPEAT,
+                address_mode_w: vk::SamplerAddressMode::REPEAT,
+                anisotropy_enable: anisotropy_enable as vk::Bool32,
+                max_anisotropy: physical_device
+                    .properties
+                    .properties
+                    .limits
+                    .max_sampler_anisotropy
+                    .min(MAX_ANISOTROPY),
+                max_lod: vk::LOD_CLAMP_NONE,
+                ..Default::default()
+            },
+            logical_device.clone(),
+            Some("Default sampler"),
+        ));
+        // Post-processing samples explicit mip levels, and its filters rely on clamping at the
+        // edges
+        let clamp_sampler = Rc::new(lv::Sampler::new(
+            &vk::SamplerCreateInfo {
+                s_type: vk::StructureType::SAMPLER_CREATE_INFO,
+                mag_filter: vk::Filter::LINEAR,
+                min_filter: vk::Filter::LINEAR,
+                mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+                address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                max_lod: vk::LOD_CLAMP_NONE,
+                ..Default::default()
+            },
+            logical_device.clone(),
+            Some("Clamp sampler"),
+        ));
+        let checker_texture =
+            gpu_resource_table.allocate_texture(VulkanApp::create_checker_texture(
+                default_sampler,
+                &immediate_submit,
+                logical_device.clone(),
+                allocator.clone(),
+            ));
+        let shadow_map_index = gpu_resource_table.allocate_texture(VulkanApp::create_shadow_map(
+            &shadow_settings,
+            logical_device.clone(),
+            allocator.clone(),
+        ));
+        let targets = VulkanApp::create_render_targets(
+            draw_extent,
+            output_encoding.get_image_format(),
+            &msaa_settings,
+            clamp_sampler.clone(),
+            &mut gpu_resource_table,
+            logical_device.clone(),
+            allocator.clone(),
+        );
+        let sharpen_image_index = gpu_resource_table.allocate_storage_image(sharpen_image);
+        gpu_resource_table.update();
+        let mut materials = MaterialLibrary::new();
+        let demo_scene = DemoScene::from_env();
+        let (scene, scene_root) = match demo_scene {
+            DemoScene::Demo => {
+                let (scene, root) = VulkanApp::create_demo_scene(
+                    MeshId(0),
+                    MeshId(1),
+                    &mut materials,
+                    checker_texture,
+                );
+                (scene, Some(root))
+            }
+            DemoScene::Triangle => (
+                VulkanApp::create_triangle_scene(MeshId(0), &mut materials),
+                None,
+            ),
+        };
+        let overlay = Overlay::new(
+            target.get_window(),
+            physical_device
+                .properties
+                .properties
+                .limits
+                .max_image_dimension2_d as usize,
+        );
 
         VulkanApp {
             handle: instance,
@@ -212,59 +807,598 @@ impl VulkanApp {
             surface,
             swapchain,
             frames,
-            draw_image_index,
             draw_extent,
+            targets,
+            render_scale_settings,
+            display_settings,
+            output_encoding,
+            sharpen_image_index,
             frame_count: 0,
+            frame_timeline,
+            profiler,
+            pipeline_statistics,
+            last_pipeline_statistics: None,
+            occlusion_queries,
+            last_visible_samples: None,
+            screenshot_request: None,
+            auto_capture,
+            validation_capture,
+            input: Input::new(InputMap::load(std::path::Path::new(BINDINGS_PATH))),
+            look_cursor_position: None,
+            passes: RenderPasses::from_env(),
+            camera: Camera::new(
+                glam::Vec3::new(0.0, 0.0, 3.0),
+                Projection::Perspective {
+                    fov_y: 70.0f32.to_radians(),
+                    near: 0.1,
+                    far: 1000.0,
+                },
+                draw_extent.width as f32 / draw_extent.height as f32,
+            ),
+            camera_controller: CameraController::new(CameraMode::Fly),
+            last_frame_time: std::time::Instant::now(),
+            frame_pacing_settings,
+            frame_limiter: FrameLimiter::default(),
+            frame_time_stats: FrameTimeStats::default(),
+            time_step: 0.0,
+            meshes,
+            scene,
+            scene_root,
+            removed_satellites: Vec::new(),
+            scene_time: 0.0,
+            draw_list: DrawList::default(),
+            materials,
+            lights: match demo_scene {
+                DemoScene::Demo => VulkanApp::create_demo_lights(),
+                DemoScene::Triangle => Vec::new(),
+            },
+            immediate_submit,
+            clamp_sampler,
+            msaa_settings,
+            shadow_settings,
+            shadow_map_index,
+            tonemap_settings: TonemapSettings::from_env(),
+            gradient_settings: GradientSettings::default(),
+            overlay,
+            overlay_draws: Vec::new(),
+            histogram_buffer,
+            exposure_buffer,
+            bloom_settings: BloomSettings::from_env(),
+            anti_aliasing_settings: AntiAliasingSettings::from_env(),
+            last_taa_frame: None,
+            // Only read by TAA, which ignores the first frame's history anyway
+            previous_view_projection: glam::Mat4::IDENTITY,
+
+            gpu_resource_table,
+
+            gradient_pipeline,
+            light_cull_pipeline,
+            shadow_pipeline,
+            lit_pipeline,
+            msaa_load_pipeline,
+            histogram_pipeline,
+            exposure_pipeline,
+            tonemap_pipeline,
+            bloom_prefilter_pipeline,
+            bloom_downsample_pipeline,
+            bloom_upsample_pipeline,
+            bloom_composite_pipeline,
+            taa_pipeline,
+            fxaa_pipeline,
+            sharpen_pipeline,
+            overlay_pipeline,
+        }
+    }
+
+    /// Every image sized by `extent`, added to the resource table
+    fn create_render_targets(
+        extent: vk::Extent2D,
+        output_format: vk::Format,
+        msaa: &MsaaSettings,
+        sampler: Rc<lv::Sampler>,
+        gpu_resource_table: &mut lv::descriptors::ShaRT,
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+    ) -> RenderTargets {
+        let image_extent = vk::Extent3D {
+            width: extent.width,
+            height: extent.height,
+            depth: 1,
+        };
+        let draw_image = lv::AllocatedImage::new(
+            utility::init::image_create_info(
+                DRAW_FORMAT,
+                vk::ImageUsageFlags::TRANSFER_SRC
+                    | vk::ImageUsageFlags::TRANSFER_DST
+                    | vk::ImageUsageFlags::STORAGE
+                    | vk::ImageUsageFlags::COLOR_ATTACHMENT,
+                image_extent,
+            ),
+            vk::ImageAspectFlags::COLOR,
+            device.clone(),
+            allocator.clone(),
+            Some("Draw image"),
+        );
+        let mut depth_image_ci = utility::init::image_create_info(
+            DEPTH_FORMAT,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            image_extent,
+        );
+        depth_image_ci.samples = msaa.samples;
+        let depth_image = lv::AllocatedImage::new(
+            depth_image_ci,
+            vk::ImageAspectFlags::DEPTH,
+            device.clone(),
+            allocator.clone(),
+            Some("Depth image"),
+        );
+        // Transient attachments the geometry pass resolves into the draw and velocity images
+        let msaa_image = |format: vk::Format, name: &str| {
+            let mut image_ci = utility::init::image_create_info(
+                format,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+                image_extent,
+            );
+            image_ci.samples = msaa.samples;
+            lv::AllocatedImage::new(
+                image_ci,
+                vk::ImageAspectFlags::COLOR,
+                device.clone(),
+                allocator.clone(),
+                Some(name),
+            )
+        };
+        let msaa_color_image = msaa
+            .is_enabled()
+            .then(|| msaa_image(DRAW_FORMAT, "MSAA color image"));
+        let msaa_velocity_image = msaa
+            .is_enabled()
+            .then(|| msaa_image(VELOCITY_FORMAT, "MSAA velocity image"));
+        let velocity_image = lv::AllocatedImage::new(
+            utility::init::image_create_info(
+                VELOCITY_FORMAT,
+                vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+                image_extent,
+            ),
+            vk::ImageAspectFlags::COLOR,
+            device.clone(),
+            allocator.clone(),
+            Some("Velocity image"),
+        );
+        // Written through a storage view and sampled by the passes after
+        let ldr_target = |name: &str| {
+            let image = lv::AllocatedImage::new(
+                utility::init::image_create_info(
+                    output_format,
+                    vk::ImageUsageFlags::STORAGE
+                        | vk::ImageUsageFlags::SAMPLED
+                        | vk::ImageUsageFlags::TRANSFER_SRC,
+                    image_extent,
+                ),
+                vk::ImageAspectFlags::COLOR,
+                device.clone(),
+                allocator.clone(),
+                Some(name),
+            );
+            let view = lv::ImageView::mip(
+                &image,
+                0,
+                vk::ImageAspectFlags::COLOR,
+                Some(&format!("{} view", name)),
+            );
+            (
+                lv::Texture::from_storage_image(image, sampler.clone()),
+                view,
+            )
+        };
+        let (ldr_texture, ldr_view) = ldr_target("LDR image");
+        let (fxaa_texture, fxaa_view) = ldr_target("FXAA image");
+        let (taa_history_indices, taa_history_view_indices) = VulkanApp::create_taa_history(
+            image_extent,
+            DRAW_FORMAT,
+            sampler.clone(),
+            device.clone(),
+            allocator.clone(),
+        )
+        .into_iter()
+        .map(|(texture, view)| {
+            (
+                gpu_resource_table.allocate_texture(texture),
+                gpu_resource_table.allocate_storage_view(view),
+            )
+        })
+        .unzip();
+        let (bloom_chain, bloom_views) =
+            VulkanApp::create_bloom_chain(extent, sampler.clone(), device, allocator);
+        RenderTargets {
+            draw_image_index: gpu_resource_table.allocate_storage_image(draw_image),
+            depth_image,
+            msaa_color_image,
+            msaa_velocity_image,
+            velocity_texture_index: gpu_resource_table
+                .allocate_texture(lv::Texture::from_image(velocity_image, sampler)),
+            ldr_view_index: gpu_resource_table.allocate_storage_view(ldr_view),
+            ldr_texture_index: gpu_resource_table.allocate_texture(ldr_texture),
+            fxaa_view_index: gpu_resource_table.allocate_storage_view(fxaa_view),
+            fxaa_texture_index: gpu_resource_table.allocate_texture(fxaa_texture),
+            taa_history_indices,
+            taa_history_view_indices,
+            bloom_view_indices: bloom_views
+                .into_iter()
+                .map(|view| gpu_resource_table.allocate_storage_view(view))
+                .collect(),
+            bloom_texture_index: gpu_resource_table.allocate_texture(bloom_chain),
+        }
+    }
+
+    /// Recreate the swapchain with the current present mode, along with the images sized by it
+    /// if its extent changed
+    fn recreate_swapchain(&mut self, extent: vk::Extent2D) {
+        unsafe {
+            self.logical_device.handle.device_wait_idle().unwrap();
+        }
+        let swapchain = lv::Swapchain::new(
+            self.swapchain.get_loader().clone(),
+            &self.physical_device,
+            self.logical_device.clone(),
+            self.surface.clone(),
+            lv::SwapchainPreferred {
+                swapchain_support_details: self
+                    .physical_device
+                    .get_swapchain_support(&self.surface.loader, self.surface.handle),
+                // The output images and encoding were chosen for the current format
+                preferred_formats: &[self.swapchain.surface_format],
+                preferred_present_modes: &[self.frame_pacing_settings.present_mode.get_vk()],
+            },
+            extent,
+            Some(&self.swapchain),
+        );
+        VulkanApp::log_present_mode(self.frame_pacing_settings.present_mode, &swapchain);
+        let extent_changed = swapchain.extent != self.swapchain.extent;
+        self.swapchain = swapchain;
+        if extent_changed {
+            self.gpu_resource_table
+                .free_storage_image(self.sharpen_image_index);
+            self.sharpen_image_index =
+                self.gpu_resource_table
+                    .allocate_storage_image(VulkanApp::create_sharpen_image(
+                        self.swapchain.extent,
+                        self.output_encoding.get_image_format(),
+                        self.logical_device.clone(),
+                        self.allocator.clone(),
+                    ));
+            self.recreate_render_targets();
+        }
+    }
+
+    fn log_present_mode(requested: PresentMode, swapchain: &lv::Swapchain) {
+        match PresentMode::from_vk(swapchain.present_mode) {
+            Some(mode) if mode == requested => log::info!("Present mode: {}", mode.name()),
+            _ => log::warn!(
+                "Present mode {} is not supported, using {:?}",
+                requested.name(),
+                swapchain.present_mode
+            ),
+        }
+    }
+
+    /// Recreate the render targets at the current render scale, after waiting for the GPU to be
+    /// done with the old ones
+    fn recreate_render_targets(&mut self) {
+        unsafe {
+            self.logical_device.handle.device_wait_idle().unwrap();
+        }
+        self.draw_extent = self
+            .render_scale_settings
+            .scaled_extent(self.swapchain.extent);
+        let targets = VulkanApp::create_render_targets(
+            self.draw_extent,
+            self.output_encoding.get_image_format(),
+            &self.msaa_settings,
+            self.clamp_sampler.clone(),
+            &mut self.gpu_resource_table,
+            self.logical_device.clone(),
+            self.allocator.clone(),
+        );
+        std::mem::replace(&mut self.targets, targets).free(&mut self.gpu_resource_table);
+        self.gpu_resource_table.update();
+        // The history was accumulated at the old size
+        self.last_taa_frame = None;
+    }
+
+    /// Grey checkerboard used as the floor's base color, with alpha cutting it to a disc
+    fn create_checker_texture(
+        sampler: Rc<lv::Sampler>,
+        immediate_submit: &lv::ImmediateSubmit,
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+    ) -> lv::Texture {
+        const SIZE: u32 = 64;
+        const SQUARE: u32 = 8;
+        let mut pixels = Vec::with_capacity((SIZE * SIZE * 4) as usize);
+        for y in 0..SIZE {
+            for x in 0..SIZE {
+                let value = if (x / SQUARE + y / SQUARE).is_multiple_of(2) {
+                    200
+                } else {
+                    90
+                };
+                let center = glam::Vec2::new(x as f32 + 0.5, y as f32 + 0.5) - SIZE as f32 / 2.0;
+                let alpha = if center.length() <= SIZE as f32 / 2.0 {
+                    255
+                } else {
+                    0
+                };
+                pixels.extend_from_slice(&[value, value, value, alpha]);
+            }
+        }
+        lv::Texture::from_pixels(
+            &pixels,
+            vk::Extent2D {
+                width: SIZE,
+                height: SIZE,
+            },
+            vk::Format::R8G8B8A8_SRGB,
+            sampler,
+            immediate_submit,
+            device,
+            allocator,
+            Some("Checker texture"),
+        )
+    }
+
+    /// A large triangle with smaller metal triangles orbiting it as children, above a round floor
+    fn create_demo_scene(
+        triangle: MeshId,
+        quad: MeshId,
+        materials: &mut MaterialLibrary,
+        checker_texture: u32,
+    ) -> (Scene, NodeId) {
+        let vertex_color = materials.add(Material {
+            name: "Vertex color".to_string(),
+            roughness_factor: 0.5,
+            ..Default::default()
+        });
+        let gold = materials.add(Material {
+            name: "Gold".to_string(),
+            base_color_factor: [1.0, 0.766, 0.336, 1.0],
+            metallic_factor: 1.0,
+            roughness_factor: 0.3,
+            ..Default::default()
+        });
+        let checker = materials.add(Material {
+            name: "Checker".to_string(),
+            base_color_texture: Some(checker_texture),
+            roughness_factor: 0.8,
+            alpha_mode: AlphaMode::Mask { cutoff: 0.5 },
+            ..Default::default()
+        });
+
+        let mut scene = Scene::new();
+        let root = scene.add_mesh_node(
+            "Root",
+            glam::Affine3A::IDENTITY,
+            None,
+            triangle,
+            Some(vertex_color),
+        );
+        for (index, offset) in [
+            glam::Vec3::new(2.0, 0.0, 0.0),
+            glam::Vec3::new(-2.0, 0.0, 0.0),
+            glam::Vec3::new(0.0, 0.0, 2.0),
+            glam::Vec3::new(0.0, 0.0, -2.0),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            scene.add_mesh_node(
+                &format!("Satellite {}", index),
+                glam::Affine3A::from_scale_rotation_translation(
+                    glam::Vec3::splat(0.4),
+                    glam::Quat::IDENTITY,
+                    offset,
+                ),
+                Some(root),
+                triangle,
+                Some(gold),
+            );
+        }
+        scene.add_mesh_node(
+            "Floor",
+            glam::Affine3A::from_scale_rotation_translation(
+                glam::Vec3::splat(4.0),
+                glam::Quat::from_rotation_x(-std::f32::consts::FRAC_PI_2),
+                glam::Vec3::new(0.0, -1.5, 0.0),
+            ),
+            None,
+            quad,
+            Some(checker),
+        );
+        (scene, root)
+    }
+
+    /// Remove the children of the animated root from the scene, or add back the ones removed before
+    fn toggle_satellites(&mut self) {
+        let Some(root) = self.scene_root else {
+            return;
+        };
+        if self.removed_satellites.is_empty() {
+            for satellite in self.scene.get_children(root).to_vec() {
+                let node = self.scene.get_node(satellite).unwrap();
+                log::info!("Removing {}", node.name);
+                self.removed_satellites.push(RemovedNode {
+                    name: node.name.clone(),
+                    local: node.local,
+                    mesh: node.mesh,
+                    material: node.material,
+                });
+                self.scene.remove_node(satellite);
+            }
+        } else {
+            for removed in self.removed_satellites.drain(..) {
+                log::info!("Adding {}", removed.name);
+                let satellite = self
+                    .scene
+                    .add_node(&removed.name, removed.local, Some(root));
+                let node = self.scene.get_node_mut(satellite).unwrap();
+                node.mesh = removed.mesh;
+                node.material = removed.material;
+            }
+        }
+    }
+
+    /// A single static, unlit triangle with vertex colors
+    fn create_triangle_scene(triangle: MeshId, materials: &mut MaterialLibrary) -> Scene {
+        let unlit = materials.add(Material {
+            name: "Unlit vertex color".to_string(),
+            unlit: true,
+            ..Default::default()
+        });
+        let mut scene = Scene::new();
+        scene.add_mesh_node(
+            "Triangle",
+            glam::Affine3A::IDENTITY,
+            None,
+            triangle,
+            Some(unlit),
+        );
+        scene
+    }
+
+    /// Host-visible storage buffer read by shaders through its device address
+    fn create_storage_buffer(
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+        size: vk::DeviceSize,
+        name: &str,
+    ) -> lv::AllocatedBuffer {
+        lv::AllocatedBuffer::new(
+            size,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+            device,
+            allocator,
+            Some(name),
+        )
+    }
+
+    /// Replace a buffer from [`VulkanApp::create_storage_buffer`] with one twice as large as
+    /// needed if it is smaller than `size`. The GPU must be done with the buffer.
+    fn reserve_storage_buffer(
+        buffer: &mut lv::AllocatedBuffer,
+        size: vk::DeviceSize,
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+        name: &str,
+    ) {
+        if size > buffer.get_size() {
+            *buffer = VulkanApp::create_storage_buffer(device, allocator, size * 2, name);
+        }
+    }
 
-            gpu_resource_table,
+    /// Write `data` to a buffer from [`VulkanApp::create_storage_buffer`], growing it if it does
+    /// not fit. The GPU must be done with the buffer.
+    fn write_storage_buffer<T: Copy>(
+        buffer: &mut lv::AllocatedBuffer,
+        data: &[T],
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+        name: &str,
+    ) {
+        let size = std::mem::size_of_val(data) as vk::DeviceSize;
+        VulkanApp::reserve_storage_buffer(buffer, size, device, allocator, name);
+        buffer.write_slice(data);
+    }
 
-            gradient_pipeline,
-            triangle_pipeline,
+    /// Animate the scene, flatten it into this frame's draw list and upload the instances and
+    /// materials
+    fn update_scene(&mut self, frame_slot: usize, delta_time: f32) {
+        self.scene_time += delta_time;
+        if let Some(root) = self
+            .scene_root
+            .and_then(|root| self.scene.get_node_mut(root))
+        {
+            root.local = glam::Affine3A::from_rotation_y(self.scene_time);
+        }
+        self.scene.update_transforms();
+        self.draw_list = self.scene.build_draw_list();
+        // Turn the point light rings slowly
+        let light_rotation = glam::Quat::from_rotation_y(delta_time * 0.3);
+        for light in self.lights.iter_mut() {
+            if light.kind == light::LightKind::Point {
+                light.position = light_rotation * light.position;
+            }
         }
+
+        let frame = &mut self.frames[frame_slot];
+        VulkanApp::write_storage_buffer(
+            &mut frame.instance_buffer,
+            &self.draw_list.instances,
+            self.logical_device.clone(),
+            self.allocator.clone(),
+            &format!("Frame {} instances", frame_slot),
+        );
+        VulkanApp::write_storage_buffer(
+            &mut frame.material_buffer,
+            &self.materials.get_data(),
+            self.logical_device.clone(),
+            self.allocator.clone(),
+            &format!("Frame {} materials", frame_slot),
+        );
+
+        let (near, far) = self.camera.projection.get_depth_range();
+        let (header, light_data) = light::get_lighting_data(
+            &self.lights,
+            [
+                self.draw_extent.width as f32,
+                self.draw_extent.height as f32,
+            ],
+            near,
+            far,
+        );
+        let lights_offset = std::mem::size_of::<LightingHeader>();
+        let frame = &mut self.frames[frame_slot];
+        VulkanApp::reserve_storage_buffer(
+            &mut frame.light_buffer,
+            (lights_offset + std::mem::size_of_val(light_data.as_slice())) as vk::DeviceSize,
+            self.logical_device.clone(),
+            self.allocator.clone(),
+            &format!("Frame {} lights", frame_slot),
+        );
+        frame.light_buffer.write(&header);
+        frame
+            .light_buffer
+            .write_slice_at(lights_offset, &light_data);
     }
 
-    fn init_background_pipelines(
+    /// Compute pipeline for the shader at `path` taking push constants of `push_constant_size`
+    fn create_compute_pipeline(
         device: Arc<lv::Device>,
-        descriptor_set_layout: vk::DescriptorSetLayout,
+        path: &std::path::Path,
+        name: &str,
+        descriptor_set_layouts: Vec<vk::DescriptorSetLayout>,
+        push_constant_size: u32,
     ) -> lv::ComputePipeline {
-        let draw_shader = lv::Shader::new(
-            std::path::Path::new("./shaders/gradient.comp.spv"),
-            device.clone(),
-        );
+        let shader = lv::Shader::new(path, device.clone(), None);
         let shader_entry_point = CString::new("main").unwrap();
         let shader_stage_ci = vk::PipelineShaderStageCreateInfo {
             s_type: vk::PipelineShaderStageCreateInfo::STRUCTURE_TYPE,
             stage: vk::ShaderStageFlags::COMPUTE,
-            module: draw_shader.handle,
+            module: shader.handle,
             p_name: shader_entry_point.as_ptr(),
             ..Default::default()
         };
         let push_constant = vk::PushConstantRange {
             stage_flags: vk::ShaderStageFlags::COMPUTE,
             offset: 0,
-            size: std::mem::size_of::<ComputePushConstants>() as u32,
+            size: push_constant_size,
         };
-        println!(
-            "Size: {:?}",
-            std::mem::size_of::<ComputePushConstants>() as u32
-        );
         let pipeline_builder = lv::ComputePipelineBuilder::new()
+            .set_name(name)
             .attach_stages(shader_stage_ci)
-            .set_layouts(vec![descriptor_set_layout])
+            .set_layouts(descriptor_set_layouts)
             .attach_push_constant(push_constant);
-        let pipeline = lv::ComputePipeline::from_builder(pipeline_builder, device.clone());
-        pipeline
-    }
-
-    fn init_descriptors(
-        device: Arc<lv::Device>,
-        image: lv::AllocatedImage,
-    ) -> (lv::descriptors::ShaRT, u32) {
-        let mut gpu_resource_table = lv::descriptors::ShaRT::new(device.clone());
-        let id = gpu_resource_table.allocate_storage_image(image);
-        gpu_resource_table.update();
-
-        (gpu_resource_table, id)
+        lv::ComputePipeline::from_builder(pipeline_builder, device)
     }
 
     fn get_current_frame(&self) -> &FrameData {
@@ -275,13 +1409,65 @@ impl VulkanApp {
 
     fn draw_geometry(&self) {
         let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
+        let _label = lv::DebugLabel::new(&self.logical_device, command_buffer, "geometry");
+        let _scope = self.profiler.scope(command_buffer, "geometry");
         let draw_image = self
             .gpu_resource_table
-            .get_storage_image(self.draw_image_index as usize)
+            .get_storage_image(self.targets.draw_image_index as usize)
             .as_ref()
             .unwrap();
-        let draw_image_attachment =
-            utility::init::attachment_info(draw_image.get_view(), None, vk::ImageLayout::GENERAL);
+        let velocity_image = self
+            .gpu_resource_table
+            .get_texture(self.targets.velocity_texture_index as usize)
+            .as_ref()
+            .unwrap()
+            .get_image();
+        // Cleared to no motion
+        let clear_velocity = Some(vk::ClearValue::default());
+        let color_attachments = [
+            match self.targets.msaa_color_image.as_ref() {
+                // Samples are averaged into the draw image when rendering ends
+                Some(msaa_color_image) => vk::RenderingAttachmentInfo {
+                    store_op: vk::AttachmentStoreOp::DONT_CARE,
+                    resolve_mode: vk::ResolveModeFlags::AVERAGE,
+                    resolve_image_view: draw_image.get_view(),
+                    resolve_image_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    ..utility::init::attachment_info(
+                        msaa_color_image.get_view(),
+                        None,
+                        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    )
+                },
+                None => utility::init::attachment_info(
+                    draw_image.get_view(),
+                    None,
+                    vk::ImageLayout::GENERAL,
+                ),
+            },
+            match self.targets.msaa_velocity_image.as_ref() {
+                // Averaging motion across an edge would match neither side, so take one sample
+                Some(msaa_velocity_image) => vk::RenderingAttachmentInfo {
+                    store_op: vk::AttachmentStoreOp::DONT_CARE,
+                    resolve_mode: vk::ResolveModeFlags::SAMPLE_ZERO,
+                    resolve_image_view: velocity_image.get_view(),
+                    resolve_image_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    ..utility::init::attachment_info(
+                        msaa_velocity_image.get_view(),
+                        clear_velocity,
+                        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    )
+                },
+                None => utility::init::attachment_info(
+                    velocity_image.get_view(),
+                    clear_velocity,
+                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                ),
+            },
+        ];
+        let depth_attachment = utility::init::depth_attachment_info(
+            self.targets.depth_image.get_view(),
+            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+        );
         let rendering_info = vk::RenderingInfo {
             s_type: vk::RenderingInfo::STRUCTURE_TYPE,
             flags: Default::default(),
@@ -294,8 +1480,9 @@ impl VulkanApp {
             },
             layer_count: 1,
             view_mask: 0,
-            color_attachment_count: 1,
-            p_color_attachments: [draw_image_attachment].as_ptr(),
+            color_attachment_count: color_attachments.len() as u32,
+            p_color_attachments: color_attachments.as_ptr(),
+            p_depth_attachment: &depth_attachment,
             ..Default::default()
         };
         unsafe {
@@ -305,9 +1492,26 @@ impl VulkanApp {
             self.logical_device.handle.cmd_bind_pipeline(
                 command_buffer,
                 vk::PipelineBindPoint::GRAPHICS,
-                self.triangle_pipeline.get_handle(),
-            )
+                self.lit_pipeline.get_handle(),
+            );
+            // Material textures are read through the resource table
+            self.logical_device.handle.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.lit_pipeline.get_layout(),
+                0,
+                &[*self.gpu_resource_table.get_descriptor()],
+                &[],
+            );
         }
+        // Counts the samples of the scene that pass the depth test
+        let frame_slot = (self.frame_count % self.frames.len() as u64) as u32;
+        self.occlusion_queries.cmd_begin(
+            command_buffer,
+            frame_slot,
+            self.logical_device
+                .is_feature_enabled(lv::DeviceFeature::OcclusionQueryPrecise),
+        );
         let viewport = vk::Viewport {
             x: 0.0,
             y: 0.0,
@@ -329,58 +1533,119 @@ impl VulkanApp {
             self.logical_device
                 .handle
                 .cmd_set_scissor(command_buffer, 0, &[scissor]);
+        }
 
-            self.logical_device
-                .handle
-                .cmd_draw(command_buffer, 3, 1, 0, 0);
+        let frame = self.get_current_frame();
+        for batch in self.draw_list.batches.iter() {
+            let mesh = &self.meshes[batch.mesh.0 as usize];
+            let push_constants = GeometryPushConstants {
+                camera: frame.camera_buffer.get_device_address(),
+                vertices: mesh.get_vertex_address(),
+                instances: frame.instance_buffer.get_device_address(),
+                materials: frame.material_buffer.get_device_address(),
+                lights: frame.light_buffer.get_device_address(),
+                clusters: frame.cluster_buffer.get_device_address(),
+                shadows: frame.shadow_buffer.get_device_address(),
+                material_index: batch.material.0,
+            };
+            unsafe {
+                self.logical_device.handle.cmd_push_constants(
+                    command_buffer,
+                    self.lit_pipeline.get_layout(),
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    std::slice::from_raw_parts(
+                        &push_constants as *const _ as *const u8,
+                        std::mem::size_of::<GeometryPushConstants>(),
+                    ),
+                );
+                self.logical_device.handle.cmd_bind_index_buffer(
+                    command_buffer,
+                    mesh.get_index_buffer(),
+                    0,
+                    vk::IndexType::UINT32,
+                );
+                self.logical_device.handle.cmd_draw_indexed(
+                    command_buffer,
+                    mesh.get_index_count(),
+                    batch.instance_count,
+                    0,
+                    0,
+                    batch.first_instance,
+                );
+            }
+        }
 
+        self.occlusion_queries.cmd_end(command_buffer, frame_slot);
+        unsafe {
             self.logical_device.handle.cmd_end_rendering(command_buffer);
         }
     }
-    fn draw_background(&self) {
+
+    /// Bind `pipeline` with `push_constants` and dispatch `group_counts` workgroups. The resource
+    /// table is bound too if the pipeline reads images from it
+    fn dispatch_compute<T>(
+        &self,
+        pipeline: &lv::ComputePipeline,
+        push_constants: &T,
+        group_counts: [u32; 3],
+        uses_resource_table: bool,
+    ) {
         let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
         unsafe {
             self.logical_device.handle.cmd_bind_pipeline(
                 command_buffer,
                 vk::PipelineBindPoint::COMPUTE,
-                self.gradient_pipeline.get_handle(),
-            );
-            self.logical_device.handle.cmd_bind_descriptor_sets(
-                command_buffer,
-                vk::PipelineBindPoint::COMPUTE,
-                self.gradient_pipeline.get_layout(),
-                0,
-                &[*self.gpu_resource_table.get_descriptor()],
-                &[],
+                pipeline.get_handle(),
             );
-            let pc = ComputePushConstants {
-                data1: glam::Vec4::new(1.0, 0.0, 0.0, 1.0).to_array(),
-                data2: glam::Vec4::new(0.0, 0.0, 1.0, 1.0).to_array(),
-                data3: glam::Vec4::ZERO.to_array(),
-                data4: glam::Vec4::ZERO.to_array(),
-            };
+            if uses_resource_table {
+                self.logical_device.handle.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    pipeline.get_layout(),
+                    0,
+                    &[*self.gpu_resource_table.get_descriptor()],
+                    &[],
+                );
+            }
             self.logical_device.handle.cmd_push_constants(
                 command_buffer,
-                self.gradient_pipeline.get_layout(),
+                pipeline.get_layout(),
                 vk::ShaderStageFlags::COMPUTE,
                 0,
                 std::slice::from_raw_parts(
-                    &pc as *const _ as *const u8,
-                    std::mem::size_of::<ComputePushConstants>(),
+                    push_constants as *const T as *const u8,
+                    std::mem::size_of::<T>(),
                 ),
             );
             self.logical_device.handle.cmd_dispatch(
                 command_buffer,
-                (self.swapchain.extent.width as f32 / 16.0f32).ceil() as u32,
-                (self.swapchain.extent.height as f32 / 16.0f32).ceil() as u32,
-                1,
+                group_counts[0],
+                group_counts[1],
+                group_counts[2],
             );
         }
     }
 
+    /// Make one compute pass's storage writes visible to the next
+    fn compute_barrier(&self) {
+        let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
+        utility::memory_barrier(
+            &self.logical_device.handle,
+            command_buffer,
+            vk::PipelineStageFlags2::COMPUTE_SHADER,
+            vk::AccessFlags2::SHADER_STORAGE_WRITE,
+            vk::PipelineStageFlags2::COMPUTE_SHADER,
+            vk::AccessFlags2::SHADER_STORAGE_READ
+                | vk::AccessFlags2::SHADER_STORAGE_WRITE
+                | vk::AccessFlags2::SHADER_SAMPLED_READ,
+        );
+    }
+
     fn record_commands(&mut self, index: usize) {
         let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
-        self.draw_extent = self.swapchain.extent;
+        let screenshot_request = self.screenshot_request.take();
+        let mut screenshot = None;
 
         unsafe {
             let command_buffer_bi = vk::CommandBufferBeginInfo {
@@ -393,10 +1658,32 @@ impl VulkanApp {
                 .begin_command_buffer(command_buffer, &command_buffer_bi)
                 .unwrap();
         }
+        // The frame timeline wait in draw_frame guarantees this slot's previous queries are done
+        let frame_slot = (self.frame_count % self.frames.len() as u64) as u32;
+        self.profiler
+            .begin_frame(command_buffer, frame_slot as usize);
+        if let Some(pool) = self.pipeline_statistics.as_ref() {
+            // Only read back slots that have been used, unused queries were never reset
+            if self.frame_count >= self.frames.len() as u64 {
+                if let Some(statistics) = pool.get_pipeline_statistics(frame_slot, 1) {
+                    self.last_pipeline_statistics = statistics.first().copied();
+                }
+            }
+            pool.cmd_reset(command_buffer, frame_slot, 1);
+            pool.cmd_begin(command_buffer, frame_slot, false);
+        }
+        // Queries must be reset outside of rendering, the geometry pass begins and ends it
+        if self.frame_count >= self.frames.len() as u64 {
+            if let Some(samples) = self.occlusion_queries.get_occlusion_results(frame_slot, 1) {
+                self.last_visible_samples = samples.first().copied();
+            }
+        }
+        self.occlusion_queries
+            .cmd_reset(command_buffer, frame_slot, 1);
 
         let draw_image = self
             .gpu_resource_table
-            .get_storage_image(self.draw_image_index as usize)
+            .get_storage_image(self.targets.draw_image_index as usize)
             .as_ref()
             .unwrap();
 
@@ -410,7 +1697,26 @@ impl VulkanApp {
             vk::QUEUE_FAMILY_IGNORED,
             vk::QUEUE_FAMILY_IGNORED,
         );
-        self.draw_background();
+        if self.passes.gradient {
+            self.draw_background();
+        } else {
+            unsafe {
+                self.logical_device.handle.cmd_clear_color_image(
+                    command_buffer,
+                    draw_image.get_handle(),
+                    vk::ImageLayout::GENERAL,
+                    &vk::ClearColorValue {
+                        float32: [0.0, 0.0, 0.0, 1.0],
+                    },
+                    &[utility::init::image_subresource_range(
+                        vk::ImageAspectFlags::COLOR,
+                    )],
+                )
+            };
+        }
+        if self.passes.geometry {
+            self.load_msaa_color();
+        }
 
         utility::transition_image(
             &self.logical_device.handle,
@@ -421,14 +1727,106 @@ impl VulkanApp {
             vk::QUEUE_FAMILY_IGNORED,
             vk::QUEUE_FAMILY_IGNORED,
         );
-        self.draw_geometry();
+        utility::transition_image(
+            &self.logical_device.handle,
+            command_buffer,
+            self.targets.depth_image.get_handle(),
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            vk::QUEUE_FAMILY_IGNORED,
+            vk::QUEUE_FAMILY_IGNORED,
+        );
+        // Velocity is cleared by the geometry pass
+        let velocity_image = self
+            .gpu_resource_table
+            .get_texture(self.targets.velocity_texture_index as usize)
+            .as_ref()
+            .unwrap()
+            .get_image();
+        for image in
+            std::iter::once(velocity_image).chain(self.targets.msaa_velocity_image.as_ref())
+        {
+            utility::transition_image(
+                &self.logical_device.handle,
+                command_buffer,
+                image.get_handle(),
+                vk::ImageLayout::UNDEFINED,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::QUEUE_FAMILY_IGNORED,
+                vk::QUEUE_FAMILY_IGNORED,
+            );
+        }
+        if self.passes.geometry {
+            self.draw_shadows();
+            self.cull_lights();
+            self.draw_geometry();
+        }
+        if let Some(pool) = self.pipeline_statistics.as_ref() {
+            pool.cmd_end(command_buffer, frame_slot);
+        }
+
+        utility::transition_image(
+            &self.logical_device.handle,
+            command_buffer,
+            draw_image.get_handle(),
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::GENERAL,
+            vk::QUEUE_FAMILY_IGNORED,
+            vk::QUEUE_FAMILY_IGNORED,
+        );
+        let anti_aliasing = self.anti_aliasing_settings.mode;
+        // Without the geometry pass there is no velocity to reproject the history with
+        if anti_aliasing == AntiAliasing::Taa && self.passes.geometry {
+            self.resolve_taa();
+            self.last_taa_frame = Some(self.frame_count);
+        }
+        if self.passes.bloom {
+            self.bloom();
+        }
+        self.tonemap();
+        if anti_aliasing == AntiAliasing::Fxaa {
+            self.fxaa();
+        }
+
+        let final_texture_index = if anti_aliasing == AntiAliasing::Fxaa {
+            self.targets.fxaa_texture_index
+        } else {
+            self.targets.ldr_texture_index
+        };
+        let upscale_filter = self.render_scale_settings.filter;
+        let (present_image, present_extent) = if upscale_filter == UpscaleFilter::Sharpen {
+            self.sharpen(final_texture_index);
+            let sharpen_image = self
+                .gpu_resource_table
+                .get_storage_image(self.sharpen_image_index as usize)
+                .as_ref()
+                .unwrap();
+            (sharpen_image, self.swapchain.extent)
+        } else {
+            let final_image = self
+                .gpu_resource_table
+                .get_texture(final_texture_index as usize)
+                .as_ref()
+                .unwrap()
+                .get_image();
+            (final_image, self.draw_extent)
+        };
 
         // transition image into their connect transfer layout
         utility::transition_image(
             &self.logical_device.handle,
             command_buffer,
             draw_image.get_handle(),
-            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::GENERAL,
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::QUEUE_FAMILY_IGNORED,
+            vk::QUEUE_FAMILY_IGNORED,
+        );
+        utility::transition_image(
+            &self.logical_device.handle,
+            command_buffer,
+            present_image.get_handle(),
+            vk::ImageLayout::GENERAL,
             vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
             vk::QUEUE_FAMILY_IGNORED,
             vk::QUEUE_FAMILY_IGNORED,
@@ -443,22 +1841,79 @@ impl VulkanApp {
             vk::QUEUE_FAMILY_IGNORED,
         );
 
-        // execute a copy from drawn image to present image
+        // execute a copy from the tonemapped image to present image
         utility::copy_image_to_image(
             command_buffer,
             &self.logical_device,
-            draw_image.get_handle(),
+            present_image.get_handle(),
             *self.swapchain.images.get(index).unwrap(),
-            self.draw_extent,
+            present_extent,
             self.swapchain.extent,
+            match upscale_filter {
+                UpscaleFilter::Nearest => vk::Filter::NEAREST,
+                _ => vk::Filter::LINEAR,
+            },
         );
 
+        let mut swapchain_layout = vk::ImageLayout::TRANSFER_DST_OPTIMAL;
+        if !self.overlay_draws.is_empty() {
+            utility::transition_image(
+                &self.logical_device.handle,
+                command_buffer,
+                *self.swapchain.images.get(index).unwrap(),
+                swapchain_layout,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                vk::QUEUE_FAMILY_IGNORED,
+                vk::QUEUE_FAMILY_IGNORED,
+            );
+            swapchain_layout = vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL;
+            self.draw_overlay(index);
+        }
+        if let Some(request) = screenshot_request {
+            let (image, extent, format) = match request.source {
+                ScreenshotSource::DrawImage => (
+                    draw_image.get_handle(),
+                    self.draw_extent,
+                    draw_image.get_format(),
+                ),
+                ScreenshotSource::Swapchain => {
+                    utility::transition_image(
+                        &self.logical_device.handle,
+                        command_buffer,
+                        *self.swapchain.images.get(index).unwrap(),
+                        swapchain_layout,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        vk::QUEUE_FAMILY_IGNORED,
+                        vk::QUEUE_FAMILY_IGNORED,
+                    );
+                    swapchain_layout = vk::ImageLayout::TRANSFER_SRC_OPTIMAL;
+                    (
+                        *self.swapchain.images.get(index).unwrap(),
+                        self.swapchain.extent,
+                        self.swapchain.surface_format.format,
+                    )
+                }
+            };
+            match PendingScreenshot::record(
+                self.logical_device.clone(),
+                self.allocator.clone(),
+                command_buffer,
+                image,
+                extent,
+                format,
+                request.path,
+            ) {
+                Ok(pending) => screenshot = Some(pending),
+                Err(err) => log::error!("Failed to take screenshot: {}", err),
+            }
+        }
+
         // set swapchain image layout to Present so we can show it on the screen
         utility::transition_image(
             &self.logical_device.handle,
             command_buffer,
             *self.swapchain.images.get(index).unwrap(),
-            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            swapchain_layout,
             vk::ImageLayout::PRESENT_SRC_KHR,
             vk::QUEUE_FAMILY_IGNORED,
             vk::QUEUE_FAMILY_IGNORED,
@@ -470,15 +1925,35 @@ impl VulkanApp {
                 .end_command_buffer(command_buffer)
                 .unwrap()
         }
+        self.frames[frame_slot as usize].screenshot = screenshot;
+    }
+
+    /// Save `source` as it is at the end of the next frame to `path`.
+    ///
+    /// The screenshot is written as EXR if `path` ends in `.exr` and as PNG otherwise.
+    pub fn request_screenshot(&mut self, source: ScreenshotSource, path: PathBuf) {
+        self.screenshot_request = Some(ScreenshotRequest { source, path });
+    }
+
+    fn save_screenshot(pending: PendingScreenshot) {
+        let path = pending.get_path().to_path_buf();
+        match pending.save() {
+            Ok(()) => log::info!("Saved screenshot to {}", path.display()),
+            Err(err) => log::error!("Failed to save screenshot to {}: {}", path.display(), err),
+        }
     }
-    fn create_triangle_pipeline(
+    /// PBR pipeline drawing meshes with their materials, see `shaders/lit.frag`
+    fn create_lit_pipeline(
         device: Arc<lv::Device>,
         swapchain_support: &lv::SwapchainSupportDetails,
+        descriptor_set_layout: vk::DescriptorSetLayout,
         color_format: vk::Format,
+        msaa: &MsaaSettings,
     ) -> Rc<lv::Pipeline> {
         let vertex_shader = lv::Shader::new(
-            std::path::Path::new("./shaders/colored_triangle.vert.spv"),
+            std::path::Path::new("./shaders/lit.vert.spv"),
             device.clone(),
+            None,
         );
         let shader_entry_point = CString::new("main").unwrap();
         let vert_shader_stage_info = vk::PipelineShaderStageCreateInfo {
@@ -489,14 +1964,30 @@ impl VulkanApp {
             ..Default::default()
         };
         let fragment_shader = lv::Shader::new(
-            std::path::Path::new("./shaders/colored_triangle.frag.spv"),
+            std::path::Path::new("./shaders/lit.frag.spv"),
             device.clone(),
+            None,
         );
+        // ALPHA_TO_COVERAGE in shaders/lit.frag
+        let alpha_to_coverage = msaa.is_enabled() && msaa.alpha_to_coverage;
+        let specialization_data: vk::Bool32 = alpha_to_coverage.into();
+        let specialization_entry = vk::SpecializationMapEntry {
+            constant_id: 0,
+            offset: 0,
+            size: std::mem::size_of::<vk::Bool32>(),
+        };
+        let specialization_info = vk::SpecializationInfo {
+            map_entry_count: 1,
+            p_map_entries: &specialization_entry,
+            data_size: std::mem::size_of::<vk::Bool32>(),
+            p_data: &specialization_data as *const _ as *const c_void,
+        };
         let fragment_shader_stage_info = vk::PipelineShaderStageCreateInfo {
             s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
             stage: vk::ShaderStageFlags::FRAGMENT,
             module: fragment_shader.handle,
             p_name: shader_entry_point.as_ptr(),
+            p_specialization_info: &specialization_info,
             ..Default::default()
         };
         let shader_stages = vec![vert_shader_stage_info, fragment_shader_stage_info];
@@ -507,17 +1998,33 @@ impl VulkanApp {
             .map(|format| format.format)
             .collect();
          */
-        let formats = vec![color_format];
-        let builder = lv::PipelineBuilder::new()
+        // Color and the velocity TAA reprojects with
+        let formats = vec![color_format, VELOCITY_FORMAT];
+        let push_constant = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<GeometryPushConstants>() as u32,
+        };
+        let mut builder = lv::PipelineBuilder::new()
+            .set_name("lit")
+            .set_layouts(vec![descriptor_set_layout])
+            .attach_push_constant(push_constant)
             .dynamic_states(vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR])
             .attach_shaders_stages(shader_stages)
             .color_attachments(formats.len() as u32, formats)
             .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
             .set_polygon_mode(vk::PolygonMode::FILL)
             .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
-            .set_multisampling_none()
+            .set_multisampling(msaa.samples)
             .disable_blending()
-            .disable_depthtest();
+            .set_depth_format(DEPTH_FORMAT)
+            .enable_depthtest(true, vk::CompareOp::LESS);
+        if msaa.is_enabled() && msaa.min_sample_shading > 0.0 {
+            builder = builder.enable_sample_shading(msaa.min_sample_shading);
+        }
+        if alpha_to_coverage {
+            builder = builder.enable_alpha_to_coverage();
+        }
         let pipeline = Rc::new(lv::Pipeline::from_builder(builder, device.clone()));
         pipeline
     }
@@ -530,78 +2037,198 @@ impl VulkanApp {
             .expect("Failed to create window")
     }
 
+    /// Features and extensions the renderer needs from the logical device
+    fn device_requirements() -> lv::DeviceBuilder {
+        lv::DeviceBuilder::new()
+            .require_extension(ash::extensions::khr::Swapchain::name())
+            // Reports how much memory each heap has left at startup
+            .optional_extension(vk::ExtMemoryBudgetFn::name())
+            .require_features(&[
+                // Dynamic rendering is a feature expected at the very minimum
+                lv::DeviceFeature::DynamicRendering,
+                // Same with sync2
+                lv::DeviceFeature::Synchronization2,
+                // Frame pacing is driven by a timeline semaphore
+                lv::DeviceFeature::TimelineSemaphore,
+                // BDAs
+                lv::DeviceFeature::BufferDeviceAddress,
+                // Bindless resource table
+                lv::DeviceFeature::DescriptorIndexing,
+                lv::DeviceFeature::ShaderSampledImageArrayNonUniformIndexing,
+                lv::DeviceFeature::ShaderStorageImageArrayNonUniformIndexing,
+                lv::DeviceFeature::DescriptorBindingSampledImageUpdateAfterBind,
+                lv::DeviceFeature::DescriptorBindingStorageImageUpdateAfterBind,
+                lv::DeviceFeature::DescriptorBindingUpdateUnusedWhilePending,
+                lv::DeviceFeature::DescriptorBindingPartiallyBound,
+                lv::DeviceFeature::RuntimeDescriptorArray,
+                // Output images are written without a format, it depends on the swapchain
+                lv::DeviceFeature::ShaderStorageImageWriteWithoutFormat,
+            ])
+            .optional_features(&[
+                lv::DeviceFeature::SamplerAnisotropy,
+                lv::DeviceFeature::PipelineStatisticsQuery,
+                lv::DeviceFeature::SampleRateShading,
+                // Exact sample counts from the geometry occlusion query
+                lv::DeviceFeature::OcclusionQueryPrecise,
+            ])
+    }
+
+    /// Check whether the device can run the renderer at all, returning why if it cannot
     fn is_device_suitable(
         physical_device: &mut lv::PhysicalDevice,
         surface_loader: &ash::extensions::khr::Surface,
         surface: vk::SurfaceKHR,
-        required_extensions: &[String],
-    ) -> bool {
-        if physical_device.properties.properties.device_type == vk::PhysicalDeviceType::DISCRETE_GPU
-            && physical_device.features_1_3.synchronization2 == vk::TRUE
-            && physical_device.features_1_3.dynamic_rendering == vk::TRUE
-            && physical_device.features_1_2.buffer_device_address == vk::TRUE
-            && physical_device.features_1_2.descriptor_indexing == vk::TRUE
-            && physical_device.features_1_2.runtime_descriptor_array == vk::TRUE
-            && physical_device.features.features.geometry_shader == vk::TRUE
-            && physical_device.has_extensions(required_extensions)
-        {
-            // check surface support now
-            physical_device.find_queue_families(surface_loader, surface);
-            let swapchain_support = physical_device.get_swapchain_support(surface_loader, surface);
-            if !swapchain_support.formats.is_empty()
-                && !swapchain_support.present_modes.is_empty()
-                && physical_device.queue_families.graphics_family.is_some()
-                && physical_device.queue_families.present_family.is_some()
-            {
-                return true;
-            }
+        device_builder: &lv::DeviceBuilder,
+    ) -> Result<(), String> {
+        device_builder.check_support(physical_device)?;
+
+        // check surface support now
+        physical_device.find_queue_families(surface_loader, surface);
+        if physical_device.queue_families.graphics_family.is_none() {
+            return Err("no graphics queue family".to_string());
+        }
+        if physical_device.queue_families.present_family.is_none() {
+            return Err("no queue family can present to the surface".to_string());
         }
-        false
+        let swapchain_support = physical_device.get_swapchain_support(surface_loader, surface);
+        if swapchain_support.formats.is_empty() || swapchain_support.present_modes.is_empty() {
+            return Err("surface reports no formats or present modes".to_string());
+        }
+        Ok(())
     }
 
+    /// Pick the highest scoring suitable device, unless `preference` names a suitable one
     fn pick_physical_devices(
         instance: Arc<lv::Instance>,
         surface_loader: &ash::extensions::khr::Surface,
         surface: vk::SurfaceKHR,
-        required_extensions: Vec<String>,
+        device_builder: &lv::DeviceBuilder,
+        preference: Option<lv::GpuPreference>,
     ) -> Option<Arc<lv::PhysicalDevice>> {
         let physical_devices = unsafe { instance.instance.enumerate_physical_devices().unwrap() };
-        for physical_device in physical_devices {
+        let mut best: Option<(u64, lv::PhysicalDevice)> = None;
+        let mut preferred: Option<lv::PhysicalDevice> = None;
+        for (index, physical_device) in physical_devices.into_iter().enumerate() {
             let mut lv_device = lv::PhysicalDevice::new(physical_device, instance.clone());
-            if VulkanApp::is_device_suitable(
+            let is_preferred = preference
+                .as_ref()
+                .is_some_and(|preference| preference.matches(index, &lv_device));
+            if let Err(reason) = VulkanApp::is_device_suitable(
                 &mut lv_device,
                 surface_loader,
                 surface,
-                &required_extensions,
+                device_builder,
             ) {
-                return Some(Arc::new(lv_device));
+                if is_preferred {
+                    log::warn!(
+                        "Preferred device [{}] {} is unsuitable: {}",
+                        index,
+                        lv_device.get_name(),
+                        reason
+                    );
+                } else {
+                    log::info!(
+                        "Rejected device [{}] {}: {}",
+                        index,
+                        lv_device.get_name(),
+                        reason
+                    );
+                }
+                continue;
             }
-        }
-        None
-    }
 
-    fn get_required_extensions(window: &winit::window::Window) -> Vec<*const i8> {
-        // Extensions
-        let mut extensions_names =
-            ash_window::enumerate_required_extensions(window.raw_display_handle())
-                .unwrap()
-                .to_vec();
+            let score = lv_device.score();
+            log::info!(
+                "Found device [{}] {} ({:?}), score {}",
+                index,
+                lv_device.get_name(),
+                lv_device.properties.properties.device_type,
+                score
+            );
+            let is_best = match &best {
+                Some((best_score, _)) => score > *best_score,
+                None => true,
+            };
+            if is_preferred && preferred.is_none() {
+                preferred = Some(lv_device);
+            } else if is_best {
+                best = Some((score, lv_device));
+            }
+        }
 
-        if VALIDATION.is_enabled {
-            extensions_names.push(ash::extensions::ext::DebugUtils::name().as_ptr());
+        if let (Some(preference), None) = (&preference, &preferred) {
+            log::warn!(
+                "No suitable device matched {:?}, falling back to the highest score",
+                preference
+            );
         }
+        let selected = preferred.or(best.map(|(_, device)| device))?;
+        log::info!("Selected device {}", selected.get_name());
+        Some(Arc::new(selected))
+    }
 
-        extensions_names
+    /// Block until the GPU has finished executing frame `frame`
+    fn wait_for_frame(&self, frame: u64) {
+        self.frame_timeline
+            .wait(frame_complete_value(frame), u64::MAX);
     }
 
-    fn draw_frame(&mut self) {
-        let render_fences = [self.get_current_frame().render_fence.get_handle()];
-        unsafe {
-            self.logical_device
-                .handle
-                .wait_for_fences(&render_fences, true, u64::MAX)
-                .unwrap();
+    fn draw_frame(&mut self, target: PresentTarget) {
+        if !self.input.is_focused() {
+            self.frame_limiter.wait(BACKGROUND_FPS);
+        } else if self.frame_pacing_settings.limit_frame_rate {
+            self.frame_limiter
+                .wait(self.frame_pacing_settings.target_fps);
+        }
+        // Wait until the GPU is done with the last frame that used this frame's resources
+        if self.frame_count >= self.frames.len() as u64 {
+            self.wait_for_frame(self.frame_count - self.frames.len() as u64);
+        }
+        let frame_slot = (self.frame_count % self.frames.len() as u64) as usize;
+        if let Some(pending) = self.frames[frame_slot].screenshot.take() {
+            VulkanApp::save_screenshot(pending);
+        }
+        // The GPU is done with this frame's camera and instance buffers, so they can be updated
+        let now = std::time::Instant::now();
+        let delta_time = (now - self.last_frame_time).as_secs_f32();
+        self.last_frame_time = now;
+        self.frame_time_stats.record(delta_time);
+        let animation_time_step = if self.auto_capture.is_some() {
+            CAPTURE_TIME_STEP
+        } else {
+            delta_time
         };
+        self.time_step = animation_time_step;
+        self.update_scene(frame_slot, animation_time_step);
+        self.update_overlay(frame_slot, target);
+        self.camera_controller
+            .update(&mut self.camera, &self.input, delta_time);
+        self.camera.aspect_ratio = self.draw_extent.width as f32 / self.draw_extent.height as f32;
+        let jitter = if self.anti_aliasing_settings.mode == AntiAliasing::Taa {
+            // Normalized device coordinates span 2 across the image
+            let draw_size = glam::Vec2::new(
+                self.draw_extent.width as f32,
+                self.draw_extent.height as f32,
+            );
+            antialiasing::jitter_offset(self.frame_count) * 2.0 / draw_size
+        } else {
+            glam::Vec2::ZERO
+        };
+        let camera_uniforms = self
+            .camera
+            .get_uniforms(jitter, self.previous_view_projection);
+        self.previous_view_projection =
+            glam::Mat4::from_cols_array_2d(&camera_uniforms.unjittered_view_projection);
+        self.frames[frame_slot]
+            .camera_buffer
+            .write(&camera_uniforms);
+        self.update_shadows(frame_slot);
+
+        if let Some(capture) = self.auto_capture.as_ref() {
+            if capture.frame == self.frame_count {
+                self.request_screenshot(capture.source, capture.path.clone());
+            }
+        }
 
         let (index, _) = unsafe {
             self.swapchain
@@ -615,9 +2242,6 @@ impl VulkanApp {
                 .unwrap()
         };
         let index = index as usize;
-        let wait_semaphores = [self.get_current_frame().swapchain_semaphore.get_handle()];
-        let signal_semaphore = [self.get_current_frame().render_semaphore.get_handle()];
-
         // reset command buffer to be recorded into
         unsafe {
             self.logical_device
@@ -631,46 +2255,53 @@ impl VulkanApp {
         self.record_commands(index);
 
         // submitting the commands
-        let submit_info = vk::SubmitInfo {
-            s_type: vk::StructureType::SUBMIT_INFO,
-            wait_semaphore_count: wait_semaphores.len() as u32,
-            p_wait_semaphores: wait_semaphores.as_ptr(), // wait for this semaphore before
-            // executing
-            p_wait_dst_stage_mask: [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT].as_ptr(), // wait stage to wait
-            // at. i.e. continue up until color attachment and wait for the semaphore to be signaled
-            command_buffer_count: 1,
-            p_command_buffers: [self.get_current_frame().main_command_buffer.get_handle()].as_ptr(),
-
-            // Indicate which semaphore is to be signaled after the queue is finished
-            signal_semaphore_count: signal_semaphore.len() as u32,
-            p_signal_semaphores: signal_semaphore.as_ptr(),
+        // wait for the swapchain image to be acquired before writing into it
+        let wait_semaphores = [self
+            .get_current_frame()
+            .swapchain_semaphore
+            .submit_info(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)];
+        // signal the binary semaphore for presentation and advance the frame timeline
+        let signal_semaphores = [
+            self.get_current_frame()
+                .render_semaphore
+                .submit_info(vk::PipelineStageFlags2::ALL_COMMANDS),
+            self.frame_timeline.signal_info(
+                frame_complete_value(self.frame_count),
+                vk::PipelineStageFlags2::ALL_COMMANDS,
+            ),
+        ];
+        let command_buffer_infos = [vk::CommandBufferSubmitInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_SUBMIT_INFO,
+            command_buffer: self.get_current_frame().main_command_buffer.get_handle(),
+            ..Default::default()
+        }];
+        let submit_info = vk::SubmitInfo2 {
+            s_type: vk::StructureType::SUBMIT_INFO_2,
+            wait_semaphore_info_count: wait_semaphores.len() as u32,
+            p_wait_semaphore_infos: wait_semaphores.as_ptr(),
+            command_buffer_info_count: command_buffer_infos.len() as u32,
+            p_command_buffer_infos: command_buffer_infos.as_ptr(),
+            signal_semaphore_info_count: signal_semaphores.len() as u32,
+            p_signal_semaphore_infos: signal_semaphores.as_ptr(),
             ..Default::default()
         };
         unsafe {
             self.logical_device
                 .handle
-                .reset_fences(&render_fences)
-                .unwrap();
-
-            self.logical_device
-                .handle
-                .queue_submit(
-                    self.logical_device
-                        .queues
-                        .get(&self.physical_device.queue_families.graphics_family.unwrap())
-                        .unwrap()
-                        .handle,
+                .queue_submit2(
+                    self.logical_device.graphics_queue().handle,
                     &[submit_info],
-                    *render_fences.get(0).unwrap(),
+                    vk::Fence::null(),
                 )
                 .unwrap();
         };
 
+        let present_semaphores = [self.get_current_frame().render_semaphore.get_handle()];
         let swapchains = [self.swapchain.handle];
         let present_info = vk::PresentInfoKHR {
             s_type: vk::StructureType::PRESENT_INFO_KHR,
             wait_semaphore_count: 1,
-            p_wait_semaphores: signal_semaphore.as_ptr(),
+            p_wait_semaphores: present_semaphores.as_ptr(),
             swapchain_count: 1,
             p_swapchains: swapchains.as_ptr(),
             p_image_indices: &index as *const _ as *const u32,
@@ -680,19 +2311,244 @@ impl VulkanApp {
         unsafe {
             self.swapchain
                 .get_loader()
-                .queue_present(
-                    self.logical_device
-                        .queues
-                        .get(&self.physical_device.queue_families.present_family.unwrap())
-                        .unwrap()
-                        .handle,
-                    &present_info,
-                )
+                .queue_present(self.logical_device.present_queue().handle, &present_info)
                 .unwrap();
         }
+        if self.frame_count.is_multiple_of(GPU_PROFILER_LOG_INTERVAL) {
+            for (name, milliseconds) in self.profiler.get_averages() {
+                log::info!("GPU {}: {:.3} ms", name, milliseconds);
+            }
+            if let Some(statistics) = self.last_pipeline_statistics {
+                log::info!("GPU pipeline statistics: {:?}", statistics);
+            }
+            if let Some(samples) = self.last_visible_samples {
+                log::info!("GPU geometry samples passing the depth test: {}", samples);
+            }
+        }
         self.frame_count += 1;
     }
 
+    /// Window title with frame time statistics, the GPU time of the last resolved frame and how
+    /// frames are paced
+    fn get_title(&self, summary: FrameTimeSummary) -> String {
+        let settings = &self.frame_pacing_settings;
+        let limit = if settings.limit_frame_rate {
+            format!(", limited to {} fps", settings.target_fps)
+        } else {
+            String::new()
+        };
+        // Passes are timed back to back, so their sum is the GPU time of the frame
+        let gpu_time: f64 = self
+            .profiler
+            .get_latest()
+            .iter()
+            .map(|(_, milliseconds)| milliseconds)
+            .sum();
+        format!(
+            "{} - {:.2} ms avg, {:.2} ms min, {:.2} ms p99, {:.2} ms GPU - {:?}{}",
+            WINDOW_TITLE,
+            summary.average,
+            summary.min,
+            summary.p99,
+            gpu_time,
+            self.swapchain.present_mode,
+            limit
+        )
+    }
+
+    /// Hide and hold the cursor in place while looking around with the mouse
+    fn update_cursor_grab(&mut self, window: &winit::window::Window) {
+        use winit::window::CursorGrabMode;
+        if self.input.is_pressed(Action::Look) {
+            self.look_cursor_position = self.input.cursor_position();
+            let grabbed = window
+                .set_cursor_grab(CursorGrabMode::Locked)
+                .or_else(|_| window.set_cursor_grab(CursorGrabMode::Confined));
+            if let Err(err) = grabbed {
+                log::warn!("Failed to grab the cursor: {}", err);
+            }
+            window.set_cursor_visible(false);
+        } else if self.input.is_released(Action::Look) {
+            if let Err(err) = window.set_cursor_grab(CursorGrabMode::None) {
+                log::warn!("Failed to release the cursor: {}", err);
+            }
+            window.set_cursor_visible(true);
+            // A confined cursor still moves while it is hidden
+            if let Some(position) = self.look_cursor_position.take() {
+                let position = winit::dpi::PhysicalPosition::new(position.x, position.y);
+                if let Err(err) = window.set_cursor_position(position) {
+                    log::debug!("Failed to move the cursor back: {}", err);
+                }
+            }
+        }
+    }
+
+    /// React to actions that are not handled elsewhere, like the camera controls
+    fn handle_actions(&mut self, target: PresentTarget) {
+        if self.input.is_pressed(Action::ToggleSatellites) {
+            self.toggle_satellites();
+        }
+        if self.input.is_pressed(Action::ReloadBindings) {
+            *self.input.get_map_mut() = InputMap::load(std::path::Path::new(BINDINGS_PATH));
+            let unbound: Vec<&str> = Action::ALL
+                .iter()
+                .filter(|action| self.input.get_map().get_bindings(**action).is_empty())
+                .map(|action| action.name())
+                .collect();
+            if unbound.is_empty() {
+                log::info!("Reloaded bindings from {}", BINDINGS_PATH);
+            } else {
+                log::info!(
+                    "Reloaded bindings from {}, unbound: {}",
+                    BINDINGS_PATH,
+                    unbound.join(", ")
+                );
+            }
+        }
+
+        let screenshot = if self.input.is_pressed(Action::ScreenshotExr) {
+            Some((ScreenshotSource::DrawImage, "exr"))
+        } else if self.input.is_pressed(Action::Screenshot) {
+            Some((ScreenshotSource::DrawImage, "png"))
+        } else if self.input.is_pressed(Action::ScreenshotSwapchain) {
+            Some((ScreenshotSource::Swapchain, "png"))
+        } else {
+            None
+        };
+        if let Some((source, extension)) = screenshot {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            let path = PathBuf::from(SCREENSHOT_DIRECTORY).join(format!(
+                "screenshot_{}_{}_{}.{}",
+                source.name(),
+                timestamp,
+                self.frame_count,
+                extension
+            ));
+            self.request_screenshot(source, path);
+        }
+
+        if self.input.is_pressed(Action::CycleAntiAliasing) {
+            let settings = &mut self.anti_aliasing_settings;
+            settings.mode = settings.mode.next();
+            log::info!("Antialiasing: {}", settings.mode.name());
+        }
+        let scale_steps = if self.input.is_pressed(Action::IncreaseRenderScale) {
+            1.0
+        } else if self.input.is_pressed(Action::DecreaseRenderScale) {
+            -1.0
+        } else {
+            0.0
+        };
+        if scale_steps != 0.0 && self.render_scale_settings.step_scale(scale_steps) {
+            self.recreate_render_targets();
+            log::info!(
+                "Render scale: {}x, {}x{}",
+                self.render_scale_settings.scale,
+                self.draw_extent.width,
+                self.draw_extent.height
+            );
+        }
+        if self.input.is_pressed(Action::CyclePresentMode) {
+            let settings = &mut self.frame_pacing_settings;
+            settings.present_mode = settings.present_mode.next();
+            log::info!(
+                "Vsync: {}",
+                if settings.present_mode.is_vsync() {
+                    "on"
+                } else {
+                    "off"
+                }
+            );
+            self.recreate_swapchain(target.get_extent());
+        }
+        if self.input.is_pressed(Action::ToggleFrameLimiter) {
+            let settings = &mut self.frame_pacing_settings;
+            settings.limit_frame_rate = !settings.limit_frame_rate;
+            log::info!(
+                "Frame limiter: {}, {} fps",
+                settings.limit_frame_rate,
+                settings.target_fps
+            );
+        }
+        if self.input.is_pressed(Action::CycleUpscaleFilter) {
+            let settings = &mut self.render_scale_settings;
+            settings.filter = settings.filter.next();
+            log::info!("Upscale filter: {}", settings.filter.name());
+        }
+        if self.input.is_pressed(Action::ToggleOverlay) {
+            self.overlay.visible = !self.overlay.visible;
+            log::info!("Overlay: {}", self.overlay.visible);
+        }
+        if self.input.is_pressed(Action::ToggleBloom) {
+            self.passes.bloom = !self.passes.bloom;
+            log::info!("Bloom: {}", self.passes.bloom);
+        }
+        let tonemap = &mut self.tonemap_settings;
+        if self.input.is_pressed(Action::CycleTonemapOperator) {
+            tonemap.operator = tonemap.operator.next();
+            log::info!("Tonemap operator: {}", tonemap.operator.name());
+        }
+        if self.input.is_pressed(Action::ToggleAutoExposure) {
+            tonemap.auto_exposure = !tonemap.auto_exposure;
+            log::info!("Auto-exposure: {}", tonemap.auto_exposure);
+        }
+        let exposure_change = if self.input.is_pressed(Action::IncreaseExposure) {
+            EXPOSURE_STEP
+        } else if self.input.is_pressed(Action::DecreaseExposure) {
+            -EXPOSURE_STEP
+        } else {
+            0.0
+        };
+        if exposure_change != 0.0 {
+            tonemap.exposure += exposure_change;
+            log::info!("Exposure compensation: {:+.1} EV", tonemap.exposure);
+        }
+    }
+
+    /// Wait for the GPU to go idle, retire the frame timeline and save any screenshots still in
+    /// flight
+    fn shutdown(&mut self) {
+        unsafe {
+            self.logical_device.handle.device_wait_idle().unwrap();
+        };
+        // Complete the frame that will never be submitted from the host, so waiting on it returns.
+        // Every submitted frame has finished by now, frame n - 1 completing at n
+        let next_frame_value = frame_complete_value(self.frame_count);
+        if self.frame_timeline.get_value() < next_frame_value {
+            self.frame_timeline.signal(next_frame_value);
+        }
+        self.wait_for_frame(self.frame_count);
+        for frame in self.frames.iter_mut() {
+            if let Some(pending) = frame.screenshot.take() {
+                VulkanApp::save_screenshot(pending);
+            }
+        }
+    }
+
+    /// Render frames without a window until the automatic capture has been saved
+    fn run_headless(&mut self) {
+        while self
+            .auto_capture
+            .as_ref()
+            .is_some_and(|capture| self.frame_count <= capture.frame)
+        {
+            self.draw_frame(PresentTarget::Headless(self.swapchain.extent));
+            self.input.end_frame();
+        }
+        self.finish_capture();
+    }
+
+    /// Save the automatic capture and fail if validation reported any errors while rendering it
+    fn finish_capture(&mut self) {
+        self.shutdown();
+        if let Some(validation_capture) = &self.validation_capture {
+            validation_capture.assert_no_errors();
+        }
+    }
+
     pub fn main_loop(
         &mut self,
         event_loop: winit::event_loop::EventLoop<()>,
@@ -701,19 +2557,56 @@ impl VulkanApp {
         event_loop.set_control_flow(winit::event_loop::ControlFlow::Poll);
         event_loop
             .run(move |event, elwt| match event {
-                winit::event::Event::WindowEvent { window_id, event } => match event {
-                    winit::event::WindowEvent::CloseRequested => {
-                        println!("Exiting application!");
-                        unsafe {
-                            self.logical_device.handle.device_wait_idle().unwrap();
-                        };
-                        elwt.exit();
+                winit::event::Event::WindowEvent { window_id, event } => {
+                    // Presses egui takes are not meant for the camera, releases still are so no
+                    // button gets stuck
+                    let taken_by_overlay = self.overlay.handle_window_event(&window, &event)
+                        && !matches!(
+                            event,
+                            winit::event::WindowEvent::MouseInput {
+                                state: winit::event::ElementState::Released,
+                                ..
+                            } | winit::event::WindowEvent::KeyboardInput {
+                                event: winit::event::KeyEvent {
+                                    state: winit::event::ElementState::Released,
+                                    ..
+                                },
+                                ..
+                            }
+                        );
+                    if !taken_by_overlay {
+                        self.input.handle_window_event(&event);
                     }
-                    winit::event::WindowEvent::RedrawRequested => {
-                        self.draw_frame();
+                    match event {
+                        winit::event::WindowEvent::CloseRequested => {
+                            println!("Exiting application!");
+                            self.shutdown();
+                            elwt.exit();
+                        }
+                        winit::event::WindowEvent::RedrawRequested if !elwt.exiting() => {
+                            self.update_cursor_grab(&window);
+                            self.handle_actions(PresentTarget::Window(&window));
+                            self.draw_frame(PresentTarget::Window(&window));
+                            self.input.end_frame();
+                            if let Some(summary) = self.frame_time_stats.take_summary() {
+                                window.set_title(&self.get_title(summary));
+                            }
+                            // Exit once the automatic capture has been recorded
+                            if self
+                                .auto_capture
+                                .as_ref()
+                                .is_some_and(|capture| self.frame_count > capture.frame)
+                            {
+                                self.finish_capture();
+                                elwt.exit();
+                            }
+                        }
+                        _ => (),
                     }
-                    _ => (),
-                },
+                }
+                winit::event::Event::DeviceEvent { event, .. } => {
+                    self.input.handle_device_event(&event);
+                }
                 winit::event::Event::AboutToWait => {
                     window.request_redraw();
                 }
@@ -724,8 +2617,21 @@ impl VulkanApp {
 }
 
 fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let auto_capture = AutoCapture::from_env();
+    // Captures do not need to be shown, so they skip the display when the driver allows it
+    if auto_capture.is_some()
+        && lv::Instance::is_extension_available(ash::extensions::ext::HeadlessSurface::name())
+    {
+        let extent = vk::Extent2D {
+            width: WINDOW_WIDTH,
+            height: WINDOW_HEIGHT,
+        };
+        VulkanApp::new(PresentTarget::Headless(extent), auto_capture).run_headless();
+        return;
+    }
     let event_loop = winit::event_loop::EventLoop::new().expect("Failed to make event loop");
     let window = VulkanApp::init_window(&event_loop);
-    let mut vulkan_app = VulkanApp::new(&window);
+    let mut vulkan_app = VulkanApp::new(PresentTarget::Window(&window), auto_capture);
     vulkan_app.main_loop(event_loop, window);
 }
@@ -0,0 +1,137 @@
+use crate::scene::MaterialId;
+
+/// Texture index telling shaders a material has no texture in that slot
+pub const NO_TEXTURE: u32 = u32::MAX;
+
+/// How the alpha of the base color is used, following glTF. Blending is not supported yet
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlphaMode {
+    /// Alpha is ignored
+    Opaque,
+    /// Fragments with an alpha below the cutoff are discarded
+    Mask { cutoff: f32 },
+}
+
+/// glTF metallic-roughness material. Textures are indices into the resource table's textures
+#[derive(Clone, Debug)]
+pub struct Material {
+    pub name: String,
+    /// Linear base color, multiplied with the base color texture and the vertex color
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<u32>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// Roughness in the green channel and metalness in the blue channel
+    pub metallic_roughness_texture: Option<u32>,
+    /// Tangent space normal map, the tangents are derived in the fragment shader
+    pub normal_texture: Option<u32>,
+    pub normal_scale: f32,
+    /// Ambient occlusion in the red channel
+    pub occlusion_texture: Option<u32>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
+    pub emissive_texture: Option<u32>,
+    pub alpha_mode: AlphaMode,
+    /// Shade with the base color only and ignore lights, like glTF's `KHR_materials_unlit`
+    pub unlit: bool,
+}
+
+impl Default for Material {
+    /// The glTF default material: white, fully rough dielectric
+    fn default() -> Self {
+        Material {
+            name: "Default".to_string(),
+            base_color_factor: [1.0; 4],
+            base_color_texture: None,
+            metallic_factor: 0.0,
+            roughness_factor: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive_factor: [0.0; 3],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            unlit: false,
+        }
+    }
+}
+
+impl Material {
+    pub fn get_data(&self) -> MaterialData {
+        MaterialData {
+            base_color_factor: self.base_color_factor,
+            emissive_factor: self.emissive_factor,
+            metallic_factor: self.metallic_factor,
+            roughness_factor: self.roughness_factor,
+            normal_scale: self.normal_scale,
+            occlusion_strength: self.occlusion_strength,
+            alpha_cutoff: match self.alpha_mode {
+                AlphaMode::Opaque => 0.0,
+                AlphaMode::Mask { cutoff } => cutoff,
+            },
+            base_color_texture: self.base_color_texture.unwrap_or(NO_TEXTURE),
+            metallic_roughness_texture: self.metallic_roughness_texture.unwrap_or(NO_TEXTURE),
+            normal_texture: self.normal_texture.unwrap_or(NO_TEXTURE),
+            occlusion_texture: self.occlusion_texture.unwrap_or(NO_TEXTURE),
+            emissive_texture: self.emissive_texture.unwrap_or(NO_TEXTURE),
+            unlit: self.unlit as u32,
+            _padding: [0; 2],
+        }
+    }
+}
+
+/// Material as laid out for shaders, see `shaders/material.inc.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct MaterialData {
+    pub base_color_factor: [f32; 4],
+    pub emissive_factor: [f32; 3],
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    pub normal_scale: f32,
+    pub occlusion_strength: f32,
+    /// 0 for opaque materials
+    pub alpha_cutoff: f32,
+    pub base_color_texture: u32,
+    pub metallic_roughness_texture: u32,
+    pub normal_texture: u32,
+    pub occlusion_texture: u32,
+    pub emissive_texture: u32,
+    pub unlit: u32,
+    _padding: [u32; 2],
+}
+
+/// All materials of the renderer, uploaded to a material buffer shaders index by [`MaterialId`].
+///
+/// Slot 0 always holds the default material used by nodes without one.
+pub struct MaterialLibrary {
+    materials: Vec<Material>,
+}
+
+impl Default for MaterialLibrary {
+    fn default() -> Self {
+        MaterialLibrary {
+            materials: vec![Material::default()],
+        }
+    }
+}
+
+impl MaterialLibrary {
+    pub fn new() -> Self {
+        MaterialLibrary::default()
+    }
+
+    pub fn add(&mut self, material: Material) -> MaterialId {
+        let id = MaterialId(self.materials.len() as u32);
+        log::debug!("Added material {:?} as {:?}", material.name, id);
+        self.materials.push(material);
+        id
+    }
+
+    /// Contents of the material buffer
+    pub fn get_data(&self) -> Vec<MaterialData> {
+        self.materials.iter().map(Material::get_data).collect()
+    }
+}
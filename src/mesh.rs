@@ -0,0 +1,119 @@
+use crate::lv;
+use ash::vk;
+use std::sync::{Arc, Mutex};
+
+/// Vertex layout shared with `shaders/mesh.vert`. The UV is split to pack the struct tightly
+/// under std430 rules.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub uv_x: f32,
+    pub normal: [f32; 3],
+    pub uv_y: f32,
+    pub color: [f32; 4],
+}
+
+impl Vertex {
+    pub fn new(position: [f32; 3], normal: [f32; 3], uv: [f32; 2], color: [f32; 4]) -> Self {
+        Vertex {
+            position,
+            uv_x: uv[0],
+            normal,
+            uv_y: uv[1],
+            color,
+        }
+    }
+}
+
+/// Mesh geometry on the CPU, before it is uploaded
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    /// The red, green and blue triangle, one unit across, facing +Z
+    pub fn triangle() -> Self {
+        let normal = [0.0, 0.0, 1.0];
+        MeshData {
+            vertices: vec![
+                Vertex::new([1.0, -1.0, 0.0], normal, [1.0, 1.0], [1.0, 0.0, 0.0, 1.0]),
+                Vertex::new([-1.0, -1.0, 0.0], normal, [0.0, 1.0], [0.0, 1.0, 0.0, 1.0]),
+                Vertex::new([0.0, 1.0, 0.0], normal, [0.5, 0.0], [0.0, 0.0, 1.0, 1.0]),
+            ],
+            indices: vec![0, 1, 2],
+        }
+    }
+
+    /// White square in the XY plane from -1 to 1, facing +Z
+    pub fn quad() -> Self {
+        let normal = [0.0, 0.0, 1.0];
+        let white = [1.0; 4];
+        MeshData {
+            vertices: vec![
+                Vertex::new([-1.0, -1.0, 0.0], normal, [0.0, 1.0], white),
+                Vertex::new([1.0, -1.0, 0.0], normal, [1.0, 1.0], white),
+                Vertex::new([1.0, 1.0, 0.0], normal, [1.0, 0.0], white),
+                Vertex::new([-1.0, 1.0, 0.0], normal, [0.0, 0.0], white),
+            ],
+            indices: vec![0, 1, 2, 2, 3, 0],
+        }
+    }
+}
+
+/// Geometry uploaded to the GPU. Shaders read the vertices through the vertex buffer's device
+/// address instead of vertex input bindings.
+pub struct Mesh {
+    vertex_buffer: lv::AllocatedBuffer,
+    index_buffer: lv::AllocatedBuffer,
+    index_count: u32,
+}
+
+impl Mesh {
+    /// Upload `data` into host-visible memory the GPU reads directly
+    pub fn new(
+        data: &MeshData,
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+        name: &str,
+    ) -> Self {
+        let mut vertex_buffer = lv::AllocatedBuffer::new(
+            std::mem::size_of_val(data.vertices.as_slice()) as vk::DeviceSize,
+            vk::BufferUsageFlags::STORAGE_BUFFER | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+            device.clone(),
+            allocator.clone(),
+            Some(&format!("{} vertices", name)),
+        );
+        vertex_buffer.write_slice(&data.vertices);
+        let mut index_buffer = lv::AllocatedBuffer::new(
+            std::mem::size_of_val(data.indices.as_slice()) as vk::DeviceSize,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            gpu_allocator::MemoryLocation::CpuToGpu,
+            device,
+            allocator,
+            Some(&format!("{} indices", name)),
+        );
+        index_buffer.write_slice(&data.indices);
+
+        Mesh {
+            vertex_buffer,
+            index_buffer,
+            index_count: data.indices.len() as u32,
+        }
+    }
+
+    pub fn get_vertex_address(&self) -> vk::DeviceAddress {
+        self.vertex_buffer.get_device_address()
+    }
+
+    pub fn get_index_buffer(&self) -> vk::Buffer {
+        self.index_buffer.get_handle()
+    }
+
+    pub fn get_index_count(&self) -> u32 {
+        self.index_count
+    }
+}
@@ -0,0 +1,283 @@
+use std::ffi::CString;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use ash::vk::{self, TaggedStructure};
+
+use crate::utility::parse_env;
+use crate::{lv, utility, VulkanApp};
+
+/// Environment variable selecting the samples per pixel, e.g. `4`
+const SAMPLES_ENV: &str = "LV_MSAA";
+/// Environment variable setting the minimum fraction of samples shaded per pixel
+const SAMPLE_SHADING_ENV: &str = "LV_SAMPLE_SHADING";
+/// Environment variable disabling alpha-to-coverage when set to `0`
+const ALPHA_TO_COVERAGE_ENV: &str = "LV_ALPHA_TO_COVERAGE";
+
+/// Multisampling configuration of the geometry pass
+#[derive(Clone, Copy, Debug)]
+pub struct MsaaSettings {
+    /// Samples per pixel, `TYPE_1` disables multisampling
+    pub samples: vk::SampleCountFlags,
+    /// Minimum fraction of samples the fragment shader runs for, 0 shades once per pixel
+    pub min_sample_shading: f32,
+    /// Turn the alpha of alpha tested materials into sample coverage instead of discarding
+    pub alpha_to_coverage: bool,
+}
+
+impl Default for MsaaSettings {
+    fn default() -> Self {
+        MsaaSettings {
+            samples: vk::SampleCountFlags::TYPE_1,
+            min_sample_shading: 0.0,
+            alpha_to_coverage: true,
+        }
+    }
+}
+
+impl MsaaSettings {
+    /// Settings from the environment, limited to what the device supports
+    pub fn from_env(limits: &vk::PhysicalDeviceLimits, sample_rate_shading: bool) -> MsaaSettings {
+        let mut settings = MsaaSettings::default();
+        if let Some(samples) = parse_env::<u32>(SAMPLES_ENV) {
+            let supported =
+                limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+            settings.samples = highest_sample_count(samples, supported);
+            if settings.samples.as_raw() != samples {
+                log::warn!(
+                    "{} samples per pixel are not supported, using {}",
+                    samples,
+                    settings.samples.as_raw()
+                );
+            }
+        }
+        if let Some(fraction) = parse_env::<f32>(SAMPLE_SHADING_ENV) {
+            if sample_rate_shading {
+                settings.min_sample_shading = fraction.clamp(0.0, 1.0);
+            } else {
+                log::warn!(
+                    "Ignoring {}, the device has no sample rate shading",
+                    SAMPLE_SHADING_ENV
+                );
+            }
+        }
+        if let Ok(value) = std::env::var(ALPHA_TO_COVERAGE_ENV) {
+            settings.alpha_to_coverage = value.trim() != "0";
+        }
+        settings
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.samples != vk::SampleCountFlags::TYPE_1
+    }
+}
+
+/// The highest sample count in `supported` that is at most `requested`
+pub fn highest_sample_count(
+    requested: u32,
+    supported: vk::SampleCountFlags,
+) -> vk::SampleCountFlags {
+    [
+        vk::SampleCountFlags::TYPE_64,
+        vk::SampleCountFlags::TYPE_32,
+        vk::SampleCountFlags::TYPE_16,
+        vk::SampleCountFlags::TYPE_8,
+        vk::SampleCountFlags::TYPE_4,
+        vk::SampleCountFlags::TYPE_2,
+    ]
+    .into_iter()
+    .find(|count| count.as_raw() <= requested && supported.contains(*count))
+    .unwrap_or(vk::SampleCountFlags::TYPE_1)
+}
+
+/// Push constants of the pass copying the draw image into the multisampled color image
+#[repr(C)]
+struct MsaaLoadPushConstants {
+    image: u32,
+}
+
+impl VulkanApp {
+    /// Fill the multisampled color image with the draw image, which must be in `GENERAL`, so the
+    /// geometry pass loads the background like it does without MSAA
+    pub fn load_msaa_color(&self) {
+        let Some(msaa_color_image) = self.targets.msaa_color_image.as_ref() else {
+            return;
+        };
+        let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
+        let _label = lv::DebugLabel::new(&self.logical_device, command_buffer, "msaa load");
+        utility::memory_barrier(
+            &self.logical_device.handle,
+            command_buffer,
+            vk::PipelineStageFlags2::ALL_COMMANDS,
+            vk::AccessFlags2::MEMORY_WRITE,
+            vk::PipelineStageFlags2::FRAGMENT_SHADER,
+            vk::AccessFlags2::SHADER_STORAGE_READ,
+        );
+        utility::transition_image(
+            &self.logical_device.handle,
+            command_buffer,
+            msaa_color_image.get_handle(),
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            vk::QUEUE_FAMILY_IGNORED,
+            vk::QUEUE_FAMILY_IGNORED,
+        );
+        let color_attachments = [vk::RenderingAttachmentInfo {
+            load_op: vk::AttachmentLoadOp::DONT_CARE,
+            ..utility::init::attachment_info(
+                msaa_color_image.get_view(),
+                None,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            )
+        }];
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: self.draw_extent,
+        };
+        let rendering_info = vk::RenderingInfo {
+            s_type: vk::RenderingInfo::STRUCTURE_TYPE,
+            render_area,
+            layer_count: 1,
+            color_attachment_count: color_attachments.len() as u32,
+            p_color_attachments: color_attachments.as_ptr(),
+            ..Default::default()
+        };
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: self.draw_extent.width as f32,
+            height: self.draw_extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        let push_constants = MsaaLoadPushConstants {
+            image: self.targets.draw_image_index,
+        };
+        unsafe {
+            let device = &self.logical_device.handle;
+            device.cmd_begin_rendering(command_buffer, &rendering_info);
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.msaa_load_pipeline.get_handle(),
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.msaa_load_pipeline.get_layout(),
+                0,
+                &[*self.gpu_resource_table.get_descriptor()],
+                &[],
+            );
+            device.cmd_push_constants(
+                command_buffer,
+                self.msaa_load_pipeline.get_layout(),
+                vk::ShaderStageFlags::FRAGMENT,
+                0,
+                std::slice::from_raw_parts(
+                    &push_constants as *const _ as *const u8,
+                    std::mem::size_of::<MsaaLoadPushConstants>(),
+                ),
+            );
+            device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+            device.cmd_set_scissor(command_buffer, 0, &[render_area]);
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            device.cmd_end_rendering(command_buffer);
+        }
+    }
+
+    /// Fullscreen pipeline copying the draw image into every sample of the multisampled color
+    /// image, see `shaders/msaa_load.frag`
+    pub fn create_msaa_load_pipeline(
+        device: Arc<lv::Device>,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        color_format: vk::Format,
+        samples: vk::SampleCountFlags,
+    ) -> Rc<lv::Pipeline> {
+        let vertex_shader = lv::Shader::new(
+            std::path::Path::new("./shaders/fullscreen.vert.spv"),
+            device.clone(),
+            None,
+        );
+        let fragment_shader = lv::Shader::new(
+            std::path::Path::new("./shaders/msaa_load.frag.spv"),
+            device.clone(),
+            None,
+        );
+        let shader_entry_point = CString::new("main").unwrap();
+        let shader_stages = vec![
+            vk::PipelineShaderStageCreateInfo {
+                s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
+                stage: vk::ShaderStageFlags::VERTEX,
+                module: vertex_shader.handle,
+                p_name: shader_entry_point.as_ptr(),
+                ..Default::default()
+            },
+            vk::PipelineShaderStageCreateInfo {
+                s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
+                stage: vk::ShaderStageFlags::FRAGMENT,
+                module: fragment_shader.handle,
+                p_name: shader_entry_point.as_ptr(),
+                ..Default::default()
+            },
+        ];
+        let push_constant = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<MsaaLoadPushConstants>() as u32,
+        };
+        let builder = lv::PipelineBuilder::new()
+            .set_name("msaa load")
+            .set_layouts(vec![descriptor_set_layout])
+            .attach_push_constant(push_constant)
+            .dynamic_states(vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR])
+            .attach_shaders_stages(shader_stages)
+            .color_attachments(1, vec![color_format])
+            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .set_polygon_mode(vk::PolygonMode::FILL)
+            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
+            .set_multisampling(samples)
+            .disable_blending()
+            .disable_depthtest();
+        Rc::new(lv::Pipeline::from_builder(builder, device))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_the_requested_count_when_supported() {
+        let supported = vk::SampleCountFlags::TYPE_1
+            | vk::SampleCountFlags::TYPE_2
+            | vk::SampleCountFlags::TYPE_4
+            | vk::SampleCountFlags::TYPE_8;
+        assert_eq!(
+            highest_sample_count(4, supported),
+            vk::SampleCountFlags::TYPE_4
+        );
+        assert_eq!(
+            highest_sample_count(1, supported),
+            vk::SampleCountFlags::TYPE_1
+        );
+    }
+
+    #[test]
+    fn clamps_to_the_supported_mask() {
+        let supported = vk::SampleCountFlags::TYPE_1 | vk::SampleCountFlags::TYPE_4;
+        assert_eq!(
+            highest_sample_count(16, supported),
+            vk::SampleCountFlags::TYPE_4
+        );
+        // Counts that are not a power of two round down
+        assert_eq!(
+            highest_sample_count(3, supported),
+            vk::SampleCountFlags::TYPE_1
+        );
+        assert_eq!(
+            highest_sample_count(8, vk::SampleCountFlags::TYPE_1),
+            vk::SampleCountFlags::TYPE_1
+        );
+    }
+}
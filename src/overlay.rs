@@ -0,0 +1,573 @@
+use crate::gradient::GradientSettings;
+use crate::{lv, utility, PresentTarget, VulkanApp};
+use ash::vk::{self, TaggedStructure};
+use glam::Vec2;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+/// Where the panel starts out, in points from the top left of the window
+const PANEL_POSITION: [f32; 2] = [16.0, 16.0];
+
+/// Axis aligned rectangle in pixels from the top left of the window
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rect {
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+/// Vertex of an overlay mesh, egui's `epaint::Vertex` with the position scaled to pixels
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct OverlayVertex {
+    /// Pixels from the top left of the window
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    /// Premultiplied sRGB color
+    pub color: [u8; 4],
+}
+
+/// Triangles sharing a texture and clip rectangle, from one of egui's `ClippedPrimitive`s
+pub struct OverlayMesh {
+    pub vertices: Vec<OverlayVertex>,
+    pub indices: Vec<u32>,
+    /// Index of the texture in the resource table
+    pub texture: u32,
+    /// Nothing outside of it is drawn
+    pub clip_rect: Rect,
+}
+
+/// Range of the batched indices drawn with one texture and scissor
+#[derive(Clone, Copy, Debug)]
+pub struct OverlayDraw {
+    pub first_index: u32,
+    pub index_count: u32,
+    pub texture: u32,
+    pub clip_rect: Rect,
+}
+
+/// Concatenate `meshes` into one vertex and one index list, offsetting the indices so they keep
+/// pointing at their mesh's vertices, with a draw per mesh
+pub fn batch_meshes(meshes: &[OverlayMesh]) -> (Vec<OverlayVertex>, Vec<u32>, Vec<OverlayDraw>) {
+    let mut vertices = Vec::new();
+    let mut indices = Vec::new();
+    let mut draws = Vec::with_capacity(meshes.len());
+    for mesh in meshes.iter().filter(|mesh| !mesh.indices.is_empty()) {
+        let base = vertices.len() as u32;
+        draws.push(OverlayDraw {
+            first_index: indices.len() as u32,
+            index_count: mesh.indices.len() as u32,
+            texture: mesh.texture,
+            clip_rect: mesh.clip_rect,
+        });
+        vertices.extend_from_slice(&mesh.vertices);
+        indices.extend(mesh.indices.iter().map(|index| base + index));
+    }
+    (vertices, indices, draws)
+}
+
+/// Convert one of egui's meshes, in points, to an overlay mesh in pixels
+fn to_overlay_mesh(
+    mesh: &egui::Mesh,
+    clip_rect: egui::Rect,
+    texture: u32,
+    pixels_per_point: f32,
+) -> OverlayMesh {
+    let to_pixels = |point: egui::Pos2| Vec2::new(point.x, point.y) * pixels_per_point;
+    OverlayMesh {
+        vertices: mesh
+            .vertices
+            .iter()
+            .map(|vertex| OverlayVertex {
+                position: to_pixels(vertex.pos).to_array(),
+                uv: [vertex.uv.x, vertex.uv.y],
+                color: vertex.color.to_array(),
+            })
+            .collect(),
+        indices: mesh.indices.clone(),
+        texture,
+        clip_rect: Rect {
+            min: to_pixels(clip_rect.min),
+            max: to_pixels(clip_rect.max),
+        },
+    }
+}
+
+/// Pixels of an egui texture as last uploaded, kept to apply partial updates to
+struct OverlayTexture {
+    /// Index of the texture in the resource table
+    index: u32,
+    size: [usize; 2],
+    pixels: Vec<egui::Color32>,
+}
+
+/// A frame of the overlay as laid out by [`Overlay::run`]
+pub struct OverlayFrame {
+    pub textures_delta: egui::TexturesDelta,
+    pub primitives: Vec<egui::ClippedPrimitive>,
+    pub pixels_per_point: f32,
+}
+
+/// egui panel over the final image, tweaking the background gradient
+pub struct Overlay {
+    pub visible: bool,
+    context: egui::Context,
+    /// Turns window events into egui input, `None` when rendering without a window
+    state: Option<egui_winit::State>,
+    textures: HashMap<egui::TextureId, OverlayTexture>,
+    /// Textures egui no longer uses, freed once the frames that drew them are done
+    textures_to_free: Vec<egui::TextureId>,
+}
+
+impl Overlay {
+    /// An overlay taking input from `window`, if there is one. Textures egui asks for are at
+    /// most `max_texture_side` texels wide and high
+    pub fn new(window: Option<&Window>, max_texture_side: usize) -> Self {
+        let context = egui::Context::default();
+        let state = window.map(|window| {
+            egui_winit::State::new(
+                context.clone(),
+                egui::ViewportId::ROOT,
+                window,
+                Some(window.scale_factor() as f32),
+                Some(max_texture_side),
+            )
+        });
+        Overlay {
+            visible: false,
+            context,
+            state,
+            textures: HashMap::new(),
+            textures_to_free: Vec::new(),
+        }
+    }
+
+    /// Pass `event` on to egui, returning whether the panel took it so the rest of the
+    /// application should ignore it
+    pub fn handle_window_event(&mut self, window: &Window, event: &WindowEvent) -> bool {
+        match &mut self.state {
+            Some(state) => state.on_window_event(window, event).consumed && self.visible,
+            None => false,
+        }
+    }
+
+    /// Lay out the panel for `window`, or an `extent` in pixels without one, applying its
+    /// changes to `gradient`. `None` while the overlay is hidden
+    pub fn run(
+        &mut self,
+        window: Option<&Window>,
+        extent: vk::Extent2D,
+        gradient: &mut GradientSettings,
+    ) -> Option<OverlayFrame> {
+        // Input is taken even while hidden, so it does not pile up
+        let raw_input = match (&mut self.state, window) {
+            (Some(state), Some(window)) => state.take_egui_input(window),
+            _ => egui::RawInput {
+                screen_rect: Some(egui::Rect::from_min_size(
+                    egui::Pos2::ZERO,
+                    egui::vec2(extent.width as f32, extent.height as f32),
+                )),
+                ..Default::default()
+            },
+        };
+        if !self.visible {
+            return None;
+        }
+        let output = self
+            .context
+            .run(raw_input, |context| Overlay::panel(context, gradient));
+        if let (Some(state), Some(window)) = (&mut self.state, window) {
+            state.handle_platform_output(window, output.platform_output);
+        }
+        Some(OverlayFrame {
+            primitives: self
+                .context
+                .tessellate(output.shapes, output.pixels_per_point),
+            textures_delta: output.textures_delta,
+            pixels_per_point: output.pixels_per_point,
+        })
+    }
+
+    fn panel(context: &egui::Context, gradient: &mut GradientSettings) {
+        egui::Window::new("Background")
+            .default_pos(PANEL_POSITION)
+            .resizable(false)
+            .show(context, |ui| {
+                egui::Grid::new("gradient").show(ui, |ui| {
+                    for (label, color) in
+                        [("Top", &mut gradient.top), ("Bottom", &mut gradient.bottom)]
+                    {
+                        ui.label(label);
+                        let mut rgb = color.truncate().to_array();
+                        if ui.color_edit_button_rgb(&mut rgb).changed() {
+                            *color = glam::Vec3::from_array(rgb).extend(color.w);
+                        }
+                        ui.end_row();
+                    }
+                });
+            });
+    }
+
+    /// Upload the textures egui added or changed in `delta` to `resource_table`, and free the
+    /// ones it dropped the frame before. Changing or freeing a texture waits for the GPU to be
+    /// done with it
+    #[allow(clippy::too_many_arguments)]
+    pub fn update_textures(
+        &mut self,
+        delta: &egui::TexturesDelta,
+        resource_table: &mut lv::descriptors::ShaRT,
+        sampler: Rc<lv::Sampler>,
+        immediate: &lv::ImmediateSubmit,
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+    ) {
+        let replaces = delta
+            .set
+            .iter()
+            .any(|(id, _)| self.textures.contains_key(id));
+        if replaces || !self.textures_to_free.is_empty() {
+            unsafe {
+                device.handle.device_wait_idle().unwrap();
+            }
+        }
+        for id in self.textures_to_free.drain(..) {
+            if let Some(texture) = self.textures.remove(&id) {
+                resource_table.free_texture(texture.index);
+            }
+        }
+
+        for (id, image_delta) in delta.set.iter() {
+            let size = image_delta.image.size();
+            let pixels: Vec<egui::Color32> = match &image_delta.image {
+                egui::ImageData::Color(image) => image.pixels.clone(),
+                egui::ImageData::Font(image) => image.srgba_pixels(None).collect(),
+            };
+            let old = self.textures.remove(id);
+            if let Some(old) = &old {
+                resource_table.free_texture(old.index);
+            }
+            // Partial updates are patched into the kept pixels, which are then uploaded whole
+            let (size, pixels) = match (image_delta.pos, old) {
+                (None, _) => (size, pixels),
+                (Some(pos), Some(mut texture)) => {
+                    for (row, region_row) in pixels.chunks_exact(size[0]).enumerate() {
+                        let start = (pos[1] + row) * texture.size[0] + pos[0];
+                        texture.pixels[start..start + size[0]].copy_from_slice(region_row);
+                    }
+                    (texture.size, texture.pixels)
+                }
+                (Some(_), None) => {
+                    log::warn!("Ignoring an update of unknown overlay texture {:?}", id);
+                    continue;
+                }
+            };
+            let bytes: Vec<u8> = pixels.iter().flat_map(|pixel| pixel.to_array()).collect();
+            let texture = lv::Texture::from_pixels(
+                &bytes,
+                vk::Extent2D {
+                    width: size[0] as u32,
+                    height: size[1] as u32,
+                },
+                // Kept in sRGB, the overlay shader blends in that space like egui
+                vk::Format::R8G8B8A8_UNORM,
+                sampler.clone(),
+                immediate,
+                device.clone(),
+                allocator.clone(),
+                Some(&format!("Overlay texture {:?}", id)),
+            );
+            let index = resource_table.allocate_texture(texture);
+            self.textures.insert(
+                *id,
+                OverlayTexture {
+                    index,
+                    size,
+                    pixels,
+                },
+            );
+        }
+        if !delta.set.is_empty() {
+            resource_table.update();
+        }
+        self.textures_to_free.extend_from_slice(&delta.free);
+    }
+
+    /// Meshes drawing `frame` with the textures from [`Overlay::update_textures`]
+    pub fn get_meshes(&self, frame: &OverlayFrame) -> Vec<OverlayMesh> {
+        frame
+            .primitives
+            .iter()
+            .filter_map(|primitive| match &primitive.primitive {
+                egui::epaint::Primitive::Mesh(mesh) => {
+                    let texture = self.textures.get(&mesh.texture_id)?;
+                    Some(to_overlay_mesh(
+                        mesh,
+                        primitive.clip_rect,
+                        texture.index,
+                        frame.pixels_per_point,
+                    ))
+                }
+                // The panel does not paint with callbacks
+                egui::epaint::Primitive::Callback(_) => None,
+            })
+            .collect()
+    }
+}
+
+/// Push constants of the overlay pipeline, see `shaders/overlay.vert`
+#[repr(C)]
+struct OverlayPushConstants {
+    vertices: vk::DeviceAddress,
+    indices: vk::DeviceAddress,
+    screen_size: [f32; 2],
+    texture: u32,
+    output_encoding: u32,
+    paper_white: f32,
+}
+
+impl VulkanApp {
+    /// Run the overlay, applying its changes to the settings it tweaks, and upload its textures
+    /// and meshes
+    pub fn update_overlay(&mut self, frame_slot: usize, target: PresentTarget) {
+        let Some(overlay_frame) = self.overlay.run(
+            target.get_window(),
+            self.swapchain.extent,
+            &mut self.gradient_settings,
+        ) else {
+            self.overlay_draws.clear();
+            return;
+        };
+        self.overlay.update_textures(
+            &overlay_frame.textures_delta,
+            &mut self.gpu_resource_table,
+            self.clamp_sampler.clone(),
+            &self.immediate_submit,
+            self.logical_device.clone(),
+            self.allocator.clone(),
+        );
+        let meshes = self.overlay.get_meshes(&overlay_frame);
+        let (vertices, indices, draws) = batch_meshes(&meshes);
+        self.overlay_draws = draws;
+        if self.overlay_draws.is_empty() {
+            return;
+        }
+        let frame = &mut self.frames[frame_slot];
+        VulkanApp::write_storage_buffer(
+            &mut frame.overlay_vertex_buffer,
+            &vertices,
+            self.logical_device.clone(),
+            self.allocator.clone(),
+            &format!("Frame {} overlay vertices", frame_slot),
+        );
+        VulkanApp::write_storage_buffer(
+            &mut frame.overlay_index_buffer,
+            &indices,
+            self.logical_device.clone(),
+            self.allocator.clone(),
+            &format!("Frame {} overlay indices", frame_slot),
+        );
+    }
+
+    /// Draw this frame's overlay meshes over swapchain image `index`, which must be in
+    /// `COLOR_ATTACHMENT_OPTIMAL`
+    pub fn draw_overlay(&self, index: usize) {
+        let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
+        let _label = lv::DebugLabel::new(&self.logical_device, command_buffer, "overlay");
+        let _scope = self.profiler.scope(command_buffer, "overlay");
+        let color_attachments = [utility::init::attachment_info(
+            self.swapchain.image_views[index],
+            None,
+            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        )];
+        let extent = self.swapchain.extent;
+        let rendering_info = vk::RenderingInfo {
+            s_type: vk::RenderingInfo::STRUCTURE_TYPE,
+            render_area: vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            },
+            layer_count: 1,
+            color_attachment_count: color_attachments.len() as u32,
+            p_color_attachments: color_attachments.as_ptr(),
+            ..Default::default()
+        };
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        let frame = self.get_current_frame();
+        unsafe {
+            let device = &self.logical_device.handle;
+            device.cmd_begin_rendering(command_buffer, &rendering_info);
+            device.cmd_bind_pipeline(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.overlay_pipeline.get_handle(),
+            );
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.overlay_pipeline.get_layout(),
+                0,
+                &[*self.gpu_resource_table.get_descriptor()],
+                &[],
+            );
+            device.cmd_set_viewport(command_buffer, 0, &[viewport]);
+            for draw in self.overlay_draws.iter() {
+                // Clip rectangles are in pixels, clamp them to the image
+                let min = draw.clip_rect.min.max(glam::Vec2::ZERO);
+                let max = draw
+                    .clip_rect
+                    .max
+                    .min(glam::Vec2::new(extent.width as f32, extent.height as f32));
+                if max.x <= min.x || max.y <= min.y {
+                    continue;
+                }
+                let scissor = vk::Rect2D {
+                    offset: vk::Offset2D {
+                        x: min.x as i32,
+                        y: min.y as i32,
+                    },
+                    extent: vk::Extent2D {
+                        width: (max.x - min.x).ceil() as u32,
+                        height: (max.y - min.y).ceil() as u32,
+                    },
+                };
+                let push_constants = OverlayPushConstants {
+                    vertices: frame.overlay_vertex_buffer.get_device_address(),
+                    indices: frame.overlay_index_buffer.get_device_address(),
+                    screen_size: [extent.width as f32, extent.height as f32],
+                    texture: draw.texture,
+                    output_encoding: self.output_encoding.get_index(),
+                    paper_white: self.display_settings.paper_white,
+                };
+                device.cmd_push_constants(
+                    command_buffer,
+                    self.overlay_pipeline.get_layout(),
+                    vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+                    0,
+                    std::slice::from_raw_parts(
+                        &push_constants as *const _ as *const u8,
+                        std::mem::size_of::<OverlayPushConstants>(),
+                    ),
+                );
+                device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+                // The vertex shader pulls indices from first_index on by gl_VertexIndex
+                device.cmd_draw(command_buffer, draw.index_count, 1, draw.first_index, 0);
+            }
+            device.cmd_end_rendering(command_buffer);
+        }
+    }
+
+    /// Pipeline blending overlay meshes over the swapchain image, see `shaders/overlay.frag`
+    pub fn create_overlay_pipeline(
+        device: Arc<lv::Device>,
+        descriptor_set_layout: vk::DescriptorSetLayout,
+        color_format: vk::Format,
+    ) -> Rc<lv::Pipeline> {
+        let vertex_shader = lv::Shader::new(
+            std::path::Path::new("./shaders/overlay.vert.spv"),
+            device.clone(),
+            None,
+        );
+        let fragment_shader = lv::Shader::new(
+            std::path::Path::new("./shaders/overlay.frag.spv"),
+            device.clone(),
+            None,
+        );
+        let shader_entry_point = CString::new("main").unwrap();
+        let shader_stages = vec![
+            vk::PipelineShaderStageCreateInfo {
+                s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
+                stage: vk::ShaderStageFlags::VERTEX,
+                module: vertex_shader.handle,
+                p_name: shader_entry_point.as_ptr(),
+                ..Default::default()
+            },
+            vk::PipelineShaderStageCreateInfo {
+                s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
+                stage: vk::ShaderStageFlags::FRAGMENT,
+                module: fragment_shader.handle,
+                p_name: shader_entry_point.as_ptr(),
+                ..Default::default()
+            },
+        ];
+        let push_constant = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<OverlayPushConstants>() as u32,
+        };
+        let builder = lv::PipelineBuilder::new()
+            .set_name("overlay")
+            .set_layouts(vec![descriptor_set_layout])
+            .attach_push_constant(push_constant)
+            .dynamic_states(vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR])
+            .attach_shaders_stages(shader_stages)
+            .color_attachments(1, vec![color_format])
+            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .set_polygon_mode(vk::PolygonMode::FILL)
+            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
+            .set_multisampling_none()
+            .enable_blending_premultiplied()
+            .disable_depthtest();
+        Rc::new(lv::Pipeline::from_builder(builder, device))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn triangle(texture: u32) -> OverlayMesh {
+        let vertex = OverlayVertex {
+            position: [0.0; 2],
+            uv: [0.0; 2],
+            color: [255; 4],
+        };
+        OverlayMesh {
+            vertices: vec![vertex; 3],
+            indices: vec![0, 1, 2],
+            texture,
+            clip_rect: Rect {
+                min: Vec2::ZERO,
+                max: Vec2::ONE,
+            },
+        }
+    }
+
+    #[test]
+    fn batching_offsets_indices_and_skips_empty_meshes() {
+        let empty = OverlayMesh {
+            indices: Vec::new(),
+            ..triangle(0)
+        };
+        let (vertices, indices, draws) = batch_meshes(&[triangle(1), empty, triangle(2)]);
+        assert_eq!(vertices.len(), 6);
+        assert_eq!(indices, [0, 1, 2, 3, 4, 5]);
+        assert_eq!(draws.len(), 2);
+        assert_eq!((draws[1].first_index, draws[1].index_count), (3, 3));
+        assert_eq!(draws[1].texture, 2);
+    }
+
+    #[test]
+    fn egui_meshes_are_scaled_to_pixels() {
+        let mut mesh = egui::Mesh::default();
+        mesh.colored_vertex(egui::pos2(10.0, 20.0), egui::Color32::RED);
+        mesh.add_triangle(0, 0, 0);
+        let clip_rect = egui::Rect::from_min_max(egui::pos2(1.0, 2.0), egui::pos2(3.0, 4.0));
+        let converted = to_overlay_mesh(&mesh, clip_rect, 5, 2.0);
+        assert_eq!(converted.vertices[0].position, [20.0, 40.0]);
+        assert_eq!(converted.vertices[0].color, [255, 0, 0, 255]);
+        assert_eq!(converted.indices, [0, 0, 0]);
+        assert_eq!(converted.texture, 5);
+        assert_eq!(converted.clip_rect.min, Vec2::new(2.0, 4.0));
+        assert_eq!(converted.clip_rect.max, Vec2::new(6.0, 8.0));
+    }
+}
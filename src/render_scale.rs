@@ -0,0 +1,241 @@
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use ash::vk;
+
+use crate::utility::parse_env;
+use crate::{lv, utility, VulkanApp};
+
+/// Environment variable setting the draw extent relative to the swapchain, e.g. `0.5`
+const SCALE_ENV: &str = "LV_RENDER_SCALE";
+/// Environment variable selecting the upscale filter by name, e.g. `sharpen`
+const FILTER_ENV: &str = "LV_UPSCALE_FILTER";
+pub const MIN_SCALE: f32 = 0.5;
+pub const MAX_SCALE: f32 = 2.0;
+/// Amount the render scale changes by per key press
+pub const SCALE_STEP: f32 = 0.25;
+
+/// How the draw image is resampled to the size of the swapchain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UpscaleFilter {
+    /// Blocky, shows the render resolution as is
+    Nearest,
+    Linear,
+    /// Bilinear upscale followed by contrast adaptive sharpening, see `shaders/sharpen.comp`
+    Sharpen,
+}
+
+impl UpscaleFilter {
+    pub const ALL: [UpscaleFilter; 3] = [
+        UpscaleFilter::Nearest,
+        UpscaleFilter::Linear,
+        UpscaleFilter::Sharpen,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UpscaleFilter::Nearest => "nearest",
+            UpscaleFilter::Linear => "linear",
+            UpscaleFilter::Sharpen => "sharpen",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<UpscaleFilter> {
+        UpscaleFilter::ALL
+            .iter()
+            .copied()
+            .find(|filter| filter.name() == name)
+    }
+
+    /// The filter after this one, wrapping around
+    pub fn next(&self) -> UpscaleFilter {
+        let index = UpscaleFilter::ALL
+            .iter()
+            .position(|filter| filter == self)
+            .unwrap();
+        UpscaleFilter::ALL[(index + 1) % UpscaleFilter::ALL.len()]
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct RenderScaleSettings {
+    /// Draw extent relative to the swapchain extent, between [`MIN_SCALE`] and [`MAX_SCALE`]
+    pub scale: f32,
+    pub filter: UpscaleFilter,
+    /// How strongly [`UpscaleFilter::Sharpen`] sharpens, from 0 to 1
+    pub sharpness: f32,
+}
+
+impl Default for RenderScaleSettings {
+    fn default() -> Self {
+        RenderScaleSettings {
+            scale: 1.0,
+            filter: UpscaleFilter::Linear,
+            sharpness: 0.5,
+        }
+    }
+}
+
+impl RenderScaleSettings {
+    /// Default settings with the scale and filter overridden from the environment
+    pub fn from_env() -> RenderScaleSettings {
+        let mut settings = RenderScaleSettings::default();
+        if let Some(scale) = parse_env::<f32>(SCALE_ENV) {
+            settings.scale = scale.clamp(MIN_SCALE, MAX_SCALE);
+        }
+        if let Ok(name) = std::env::var(FILTER_ENV) {
+            match UpscaleFilter::from_name(name.trim()) {
+                Some(filter) => settings.filter = filter,
+                None => log::warn!("Ignoring unknown {}={:?}", FILTER_ENV, name),
+            }
+        }
+        settings
+    }
+
+    /// Change the scale by `steps` times [`SCALE_STEP`], returning whether it changed
+    pub fn step_scale(&mut self, steps: f32) -> bool {
+        let scale = (self.scale + steps * SCALE_STEP).clamp(MIN_SCALE, MAX_SCALE);
+        let changed = scale != self.scale;
+        self.scale = scale;
+        changed
+    }
+
+    /// Size of the draw image for a swapchain of `extent`
+    pub fn scaled_extent(&self, extent: vk::Extent2D) -> vk::Extent2D {
+        let scale = |size: u32| ((size as f32 * self.scale).round() as u32).max(1);
+        vk::Extent2D {
+            width: scale(extent.width),
+            height: scale(extent.height),
+        }
+    }
+}
+
+/// Push constants of the sharpening upscale
+#[repr(C)]
+struct SharpenPushConstants {
+    input_texture: u32,
+    output_image: u32,
+    sharpness: f32,
+}
+
+impl VulkanApp {
+    /// Compute pipeline of the sharpening upscale, see `shaders/sharpen.comp`
+    pub fn create_sharpen_pipeline(
+        device: Arc<lv::Device>,
+        resource_layout: vk::DescriptorSetLayout,
+    ) -> Rc<lv::ComputePipeline> {
+        Rc::new(VulkanApp::create_compute_pipeline(
+            device,
+            std::path::Path::new("./shaders/sharpen.comp.spv"),
+            "sharpen",
+            vec![resource_layout],
+            std::mem::size_of::<SharpenPushConstants>() as u32,
+        ))
+    }
+
+    /// Storage image at the swapchain extent the upscale filter sharpens into
+    pub fn create_sharpen_image(
+        extent: vk::Extent2D,
+        format: vk::Format,
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+    ) -> lv::AllocatedImage {
+        lv::AllocatedImage::new(
+            utility::init::image_create_info(
+                format,
+                vk::ImageUsageFlags::STORAGE | vk::ImageUsageFlags::TRANSFER_SRC,
+                vk::Extent3D {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1,
+                },
+            ),
+            vk::ImageAspectFlags::COLOR,
+            device,
+            allocator,
+            Some("Sharpen image"),
+        )
+    }
+
+    /// Upscale `texture_index` to the swapchain extent into the sharpen image while sharpening it
+    pub fn sharpen(&self, texture_index: u32) {
+        let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
+        let _label = lv::DebugLabel::new(&self.logical_device, command_buffer, "sharpen");
+        let _scope = self.profiler.scope(command_buffer, "sharpen");
+        let sharpen_image = self
+            .gpu_resource_table
+            .get_storage_image(self.sharpen_image_index as usize)
+            .as_ref()
+            .unwrap();
+        utility::transition_image(
+            &self.logical_device.handle,
+            command_buffer,
+            sharpen_image.get_handle(),
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+            vk::QUEUE_FAMILY_IGNORED,
+            vk::QUEUE_FAMILY_IGNORED,
+        );
+        self.compute_barrier();
+        let push_constants = SharpenPushConstants {
+            input_texture: texture_index,
+            output_image: self.sharpen_image_index,
+            sharpness: self.render_scale_settings.sharpness,
+        };
+        self.dispatch_compute(
+            &self.sharpen_pipeline,
+            &push_constants,
+            [
+                self.swapchain.extent.width.div_ceil(16),
+                self.swapchain.extent.height.div_ceil(16),
+                1,
+            ],
+            true,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extent(width: u32, height: u32) -> vk::Extent2D {
+        vk::Extent2D { width, height }
+    }
+
+    #[test]
+    fn scaled_extent_rounds_and_never_reaches_zero() {
+        let mut settings = RenderScaleSettings::default();
+        assert_eq!(
+            settings.scaled_extent(extent(1920, 1080)),
+            extent(1920, 1080)
+        );
+        settings.scale = 0.5;
+        assert_eq!(settings.scaled_extent(extent(1919, 1081)), extent(960, 541));
+        assert_eq!(settings.scaled_extent(extent(1, 1)), extent(1, 1));
+        settings.scale = 2.0;
+        assert_eq!(settings.scaled_extent(extent(800, 600)), extent(1600, 1200));
+    }
+
+    #[test]
+    fn step_scale_clamps_and_reports_changes() {
+        let mut settings = RenderScaleSettings::default();
+        assert!(settings.step_scale(-1.0));
+        assert_eq!(settings.scale, 0.75);
+        assert!(settings.step_scale(-1.0));
+        assert_eq!(settings.scale, MIN_SCALE);
+        assert!(!settings.step_scale(-1.0));
+        assert_eq!(settings.scale, MIN_SCALE);
+        assert!(settings.step_scale(100.0));
+        assert_eq!(settings.scale, MAX_SCALE);
+        assert!(!settings.step_scale(1.0));
+    }
+
+    #[test]
+    fn filter_names_round_trip() {
+        for filter in UpscaleFilter::ALL {
+            assert_eq!(UpscaleFilter::from_name(filter.name()), Some(filter));
+        }
+        assert_eq!(UpscaleFilter::Sharpen.next(), UpscaleFilter::Nearest);
+    }
+}
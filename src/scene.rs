@@ -0,0 +1,431 @@
+use glam::{Affine3A, Mat4};
+
+/// Index of a mesh owned by the renderer
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MeshId(pub u32);
+
+/// Index of a material owned by the renderer. Materials decide which pipeline a draw uses
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MaterialId(pub u32);
+
+/// Slot of a node in its scene, and the generation of the slot it was created in, so ids of
+/// removed nodes are not mistaken for nodes that later reuse the slot
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+pub struct Node {
+    pub name: String,
+    /// Transform relative to the parent
+    pub local: Affine3A,
+    /// Transform relative to the scene root, updated by [`Scene::update_transforms`]
+    world: Affine3A,
+    /// `world` before the last [`Scene::update_transforms`], for motion vectors
+    previous_world: Affine3A,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    pub mesh: Option<MeshId>,
+    /// Material to draw `mesh` with, the default material if `None`
+    pub material: Option<MaterialId>,
+}
+
+/// Per-instance data as read by `shaders/mesh.vert`
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct InstanceData {
+    pub model: [[f32; 4]; 4],
+    /// Inverse transpose of `model`, for transforming normals
+    pub normal: [[f32; 4]; 4],
+    /// `model` of the previous frame, for motion vectors
+    pub previous_model: [[f32; 4]; 4],
+}
+
+/// Instances of one mesh drawn with one material, in a single instanced draw
+#[derive(Clone, Copy, Debug)]
+pub struct DrawBatch {
+    pub mesh: MeshId,
+    pub material: MaterialId,
+    /// Index of the first instance in [`DrawList::instances`]
+    pub first_instance: u32,
+    pub instance_count: u32,
+}
+
+/// Scene flattened for a single frame, sorted by material and then mesh to minimize state changes
+#[derive(Clone, Debug, Default)]
+pub struct DrawList {
+    pub batches: Vec<DrawBatch>,
+    pub instances: Vec<InstanceData>,
+}
+
+/// Storage for one node, kept around when the node is removed so its generation can be bumped
+#[derive(Default)]
+struct Slot {
+    node: Option<Node>,
+    generation: u32,
+}
+
+/// Hierarchy of nodes with transforms, some of which reference a mesh to draw
+#[derive(Default)]
+pub struct Scene {
+    nodes: Vec<Slot>,
+    free_ids: Vec<u32>,
+    roots: Vec<NodeId>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Scene::default()
+    }
+
+    /// Add a node under `parent`, placed in the world according to the parent's current world
+    /// transform so it does not jump or get a motion vector on the next update
+    pub fn add_node(&mut self, name: &str, local: Affine3A, parent: Option<NodeId>) -> NodeId {
+        let node = Node {
+            name: name.to_string(),
+            local,
+            world: local,
+            previous_world: local,
+            parent: None,
+            children: Vec::new(),
+            mesh: None,
+            material: None,
+        };
+        let index = match self.free_ids.pop() {
+            Some(index) => index,
+            None => {
+                self.nodes.push(Slot::default());
+                self.nodes.len() as u32 - 1
+            }
+        };
+        let slot = &mut self.nodes[index as usize];
+        slot.node = Some(node);
+        let id = NodeId {
+            index,
+            generation: slot.generation,
+        };
+        self.roots.push(id);
+        if let Some(parent) = parent {
+            self.set_parent(id, Some(parent));
+            let (parent_world, parent_previous_world) = {
+                let parent = self.get_node(parent).unwrap();
+                (parent.world, parent.previous_world)
+            };
+            let node = self.get_node_mut(id).unwrap();
+            node.world = parent_world * local;
+            node.previous_world = parent_previous_world * local;
+        }
+        id
+    }
+
+    /// Add a node drawing `mesh` with `material`
+    pub fn add_mesh_node(
+        &mut self,
+        name: &str,
+        local: Affine3A,
+        parent: Option<NodeId>,
+        mesh: MeshId,
+        material: Option<MaterialId>,
+    ) -> NodeId {
+        let id = self.add_node(name, local, parent);
+        let node = self.get_node_mut(id).unwrap();
+        node.mesh = Some(mesh);
+        node.material = material;
+        id
+    }
+
+    /// The node `id` refers to, `None` if it has been removed
+    pub fn get_node(&self, id: NodeId) -> Option<&Node> {
+        self.nodes
+            .get(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_ref())
+    }
+
+    pub fn get_node_mut(&mut self, id: NodeId) -> Option<&mut Node> {
+        self.nodes
+            .get_mut(id.index as usize)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.node.as_mut())
+    }
+
+    /// Direct children of `id`, empty if it has been removed
+    pub fn get_children(&self, id: NodeId) -> &[NodeId] {
+        self.get_node(id)
+            .map(|node| node.children.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Whether `ancestor` is `id` or one of its parents
+    fn is_ancestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut current = Some(id);
+        while let Some(node_id) = current {
+            if node_id == ancestor {
+                return true;
+            }
+            current = self.get_node(node_id).and_then(|node| node.parent);
+        }
+        false
+    }
+
+    /// Move `id` under `parent`, or to the root if `None`. The local transform is kept, so the
+    /// node moves along with its new parent.
+    ///
+    /// Panics if this would make a node its own ancestor.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) {
+        if let Some(parent) = parent {
+            assert!(
+                self.get_node(parent).is_some(),
+                "Parent node does not exist"
+            );
+            if self.is_ancestor(id, parent) {
+                panic!("Parenting {:?} to {:?} would create a cycle", id, parent);
+            }
+        }
+        let old_parent = self.get_node(id).expect("Node does not exist").parent;
+        match old_parent {
+            Some(old_parent) => self
+                .get_node_mut(old_parent)
+                .unwrap()
+                .children
+                .retain(|child| *child != id),
+            None => self.roots.retain(|root| *root != id),
+        }
+        match parent {
+            Some(parent) => self.get_node_mut(parent).unwrap().children.push(id),
+            None => self.roots.push(id),
+        }
+        self.get_node_mut(id).unwrap().parent = parent;
+    }
+
+    /// Remove `id` together with all of its descendants. Their ids stop referring to any node.
+    pub fn remove_node(&mut self, id: NodeId) {
+        self.set_parent(id, None);
+        self.roots.retain(|root| *root != id);
+        let mut stack = vec![id];
+        while let Some(node_id) = stack.pop() {
+            let slot = &mut self.nodes[node_id.index as usize];
+            if let Some(node) = slot.node.take() {
+                stack.extend(node.children);
+                slot.generation = slot.generation.wrapping_add(1);
+                self.free_ids.push(node_id.index);
+            }
+        }
+    }
+
+    /// Recompute every node's world transform from the local transforms, keeping the previous one
+    pub fn update_transforms(&mut self) {
+        let mut stack: Vec<(NodeId, Affine3A)> = self
+            .roots
+            .iter()
+            .map(|root| (*root, Affine3A::IDENTITY))
+            .collect();
+        while let Some((id, parent_world)) = stack.pop() {
+            let node = self.nodes[id.index as usize].node.as_mut().unwrap();
+            node.previous_world = node.world;
+            node.world = parent_world * node.local;
+            let world = node.world;
+            stack.extend(node.children.iter().map(|child| (*child, world)));
+        }
+    }
+
+    /// Flatten the scene into instanced draws. Call [`Scene::update_transforms`] first
+    pub fn build_draw_list(&self) -> DrawList {
+        let mut draws: Vec<(MaterialId, MeshId, Affine3A, Affine3A)> = self
+            .nodes
+            .iter()
+            .filter_map(|slot| slot.node.as_ref())
+            .filter_map(|node| {
+                node.mesh.map(|mesh| {
+                    let material = node.material.unwrap_or_default();
+                    (material, mesh, node.world, node.previous_world)
+                })
+            })
+            .collect();
+        draws.sort_by_key(|(material, mesh, _, _)| (*material, *mesh));
+
+        let mut draw_list = DrawList {
+            batches: Vec::new(),
+            instances: Vec::with_capacity(draws.len()),
+        };
+        for (material, mesh, world, previous_world) in draws {
+            let model = Mat4::from(world);
+            draw_list.instances.push(InstanceData {
+                model: model.to_cols_array_2d(),
+                normal: model.inverse().transpose().to_cols_array_2d(),
+                previous_model: Mat4::from(previous_world).to_cols_array_2d(),
+            });
+            match draw_list.batches.last_mut() {
+                Some(batch) if batch.material == material && batch.mesh == mesh => {
+                    batch.instance_count += 1;
+                }
+                _ => draw_list.batches.push(DrawBatch {
+                    mesh,
+                    material,
+                    first_instance: draw_list.instances.len() as u32 - 1,
+                    instance_count: 1,
+                }),
+            }
+        }
+        draw_list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use glam::Vec3;
+
+    fn translation(x: f32) -> Affine3A {
+        Affine3A::from_translation(Vec3::new(x, 0.0, 0.0))
+    }
+
+    fn world_x(scene: &Scene, id: NodeId) -> f32 {
+        scene.get_node(id).unwrap().world.translation.x
+    }
+
+    #[test]
+    fn world_transforms_compose_parents() {
+        let mut scene = Scene::new();
+        let root = scene.add_node("Root", translation(1.0), None);
+        let child = scene.add_node("Child", translation(2.0), Some(root));
+        let grandchild = scene.add_node("Grandchild", translation(4.0), Some(child));
+        scene.update_transforms();
+        assert_eq!(world_x(&scene, root), 1.0);
+        assert_eq!(world_x(&scene, child), 3.0);
+        assert_eq!(world_x(&scene, grandchild), 7.0);
+
+        scene.set_parent(child, None);
+        scene.update_transforms();
+        assert_eq!(world_x(&scene, child), 2.0);
+        assert_eq!(world_x(&scene, grandchild), 6.0);
+    }
+
+    #[test]
+    fn update_keeps_the_previous_world_transform() {
+        let mut scene = Scene::new();
+        let root = scene.add_node("Root", translation(1.0), None);
+        scene.update_transforms();
+        scene.get_node_mut(root).unwrap().local = translation(5.0);
+        scene.update_transforms();
+        let node = scene.get_node(root).unwrap();
+        assert_eq!(node.previous_world.translation.x, 1.0);
+        assert_eq!(node.world.translation.x, 5.0);
+    }
+
+    #[test]
+    fn added_nodes_start_at_their_parents_world_transform() {
+        let mut scene = Scene::new();
+        let root = scene.add_node("Root", translation(1.0), None);
+        scene.update_transforms();
+        scene.get_node_mut(root).unwrap().local = translation(3.0);
+        scene.update_transforms();
+        let child = scene.add_node("Child", translation(2.0), Some(root));
+        let node = scene.get_node(child).unwrap();
+        assert_eq!(node.world.translation.x, 5.0);
+        assert_eq!(node.previous_world.translation.x, 3.0);
+
+        // Updating right away keeps the child where it was placed
+        scene.update_transforms();
+        let node = scene.get_node(child).unwrap();
+        assert_eq!(node.previous_world.translation.x, 5.0);
+        assert_eq!(node.world.translation.x, 5.0);
+    }
+
+    #[test]
+    #[should_panic(expected = "would create a cycle")]
+    fn parenting_to_a_descendant_panics() {
+        let mut scene = Scene::new();
+        let root = scene.add_node("Root", Affine3A::IDENTITY, None);
+        let child = scene.add_node("Child", Affine3A::IDENTITY, Some(root));
+        scene.set_parent(root, Some(child));
+    }
+
+    #[test]
+    fn removed_ids_do_not_alias_reused_slots() {
+        let mut scene = Scene::new();
+        let root = scene.add_node("Root", Affine3A::IDENTITY, None);
+        let child = scene.add_node("Child", Affine3A::IDENTITY, Some(root));
+        scene.remove_node(root);
+        assert!(scene.get_node(root).is_none());
+        assert!(scene.get_node(child).is_none());
+
+        let reused = scene.add_node("Reused", Affine3A::IDENTITY, None);
+        assert!(reused.index == root.index || reused.index == child.index);
+        assert_eq!(reused.generation, 1);
+        assert!(scene.get_node(root).is_none());
+        assert!(scene.get_node(child).is_none());
+        assert_eq!(scene.get_node(reused).unwrap().name, "Reused");
+    }
+
+    #[test]
+    fn removing_a_child_detaches_it_from_its_parent() {
+        let mut scene = Scene::new();
+        let root = scene.add_node("Root", Affine3A::IDENTITY, None);
+        let first = scene.add_node("First", Affine3A::IDENTITY, Some(root));
+        let second = scene.add_node("Second", Affine3A::IDENTITY, Some(root));
+        scene.remove_node(first);
+        assert_eq!(scene.get_children(root), &[second]);
+        assert!(scene.get_children(first).is_empty());
+        assert!(scene.get_node(first).is_none());
+        scene.update_transforms();
+        assert_eq!(scene.roots, vec![root]);
+    }
+
+    #[test]
+    fn draw_list_batches_by_material_then_mesh() {
+        let mut scene = Scene::new();
+        let (triangle, quad) = (MeshId(0), MeshId(1));
+        let gold = Some(MaterialId(2));
+        scene.add_mesh_node("A", translation(1.0), None, quad, gold);
+        scene.add_mesh_node("B", translation(2.0), None, triangle, gold);
+        scene.add_mesh_node("C", translation(3.0), None, triangle, None);
+        scene.add_node("Empty", Affine3A::IDENTITY, None);
+        scene.add_mesh_node("D", translation(4.0), None, triangle, gold);
+        scene.update_transforms();
+
+        let draw_list = scene.build_draw_list();
+        let batches: Vec<_> = draw_list
+            .batches
+            .iter()
+            .map(|batch| {
+                (
+                    batch.material,
+                    batch.mesh,
+                    batch.first_instance,
+                    batch.instance_count,
+                )
+            })
+            .collect();
+        assert_eq!(
+            batches,
+            [
+                (MaterialId(0), triangle, 0, 1),
+                (MaterialId(2), triangle, 1, 2),
+                (MaterialId(2), quad, 3, 1),
+            ]
+        );
+        let instance_x: Vec<f32> = draw_list
+            .instances
+            .iter()
+            .map(|instance| instance.model[3][0])
+            .collect();
+        assert_eq!(instance_x, [3.0, 2.0, 4.0, 1.0]);
+    }
+
+    #[test]
+    fn instances_carry_normal_and_previous_matrices() {
+        let mut scene = Scene::new();
+        let scale = Affine3A::from_scale(Vec3::new(2.0, 1.0, 1.0));
+        let node = scene.add_mesh_node("Node", scale, None, MeshId(0), None);
+        scene.update_transforms();
+        scene.get_node_mut(node).unwrap().local = translation(1.0) * scale;
+        scene.update_transforms();
+
+        let instance = scene.build_draw_list().instances[0];
+        assert_eq!(instance.model[3][0], 1.0);
+        assert_eq!(instance.previous_model[3][0], 0.0);
+        assert_eq!(instance.normal[0][0], 0.5);
+    }
+}
@@ -0,0 +1,326 @@
+use crate::lv;
+use ash::vk;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// Image a screenshot is taken from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScreenshotSource {
+    /// The HDR draw target, before it is copied to the swapchain
+    DrawImage,
+    /// The swapchain image that gets presented
+    Swapchain,
+}
+
+impl ScreenshotSource {
+    pub const ALL: [ScreenshotSource; 2] =
+        [ScreenshotSource::DrawImage, ScreenshotSource::Swapchain];
+
+    /// Name used for the source in [`CAPTURE_SOURCE_ENV`]
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScreenshotSource::DrawImage => "draw",
+            ScreenshotSource::Swapchain => "swapchain",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ScreenshotSource> {
+        ScreenshotSource::ALL
+            .iter()
+            .copied()
+            .find(|source| source.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScreenshotFormat {
+    /// 8-bit sRGB
+    Png,
+    /// Lossless linear floating point
+    Exr,
+}
+
+impl ScreenshotFormat {
+    /// Pick the format from the extension of `path`, defaulting to PNG
+    pub fn from_path(path: &Path) -> ScreenshotFormat {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("exr") => ScreenshotFormat::Exr,
+            _ => ScreenshotFormat::Png,
+        }
+    }
+}
+
+/// Environment variable with the path to save a screenshot of the draw image to, after which the
+/// application exits. Used by the golden-image tests
+pub const CAPTURE_PATH_ENV: &str = "LV_CAPTURE";
+/// Environment variable with the frame to capture for [`CAPTURE_PATH_ENV`], defaults to 0
+pub const CAPTURE_FRAME_ENV: &str = "LV_CAPTURE_FRAME";
+/// Environment variable with the [`ScreenshotSource::name`] of the image to capture for
+/// [`CAPTURE_PATH_ENV`], defaults to the draw image
+pub const CAPTURE_SOURCE_ENV: &str = "LV_CAPTURE_SOURCE";
+
+/// Capture of a single frame requested through the environment
+#[derive(Clone, Debug)]
+pub struct AutoCapture {
+    pub frame: u64,
+    pub source: ScreenshotSource,
+    pub path: PathBuf,
+}
+
+impl AutoCapture {
+    pub fn from_env() -> Option<AutoCapture> {
+        let path = std::env::var_os(CAPTURE_PATH_ENV)?;
+        let frame = match std::env::var(CAPTURE_FRAME_ENV) {
+            Ok(frame) => frame.trim().parse::<u64>().unwrap_or_else(|_| {
+                log::warn!("Ignoring invalid {}={:?}", CAPTURE_FRAME_ENV, frame);
+                0
+            }),
+            Err(_) => 0,
+        };
+        let source = match std::env::var(CAPTURE_SOURCE_ENV) {
+            Ok(source) => ScreenshotSource::from_name(source.trim()).unwrap_or_else(|| {
+                log::warn!("Ignoring invalid {}={:?}", CAPTURE_SOURCE_ENV, source);
+                ScreenshotSource::DrawImage
+            }),
+            Err(_) => ScreenshotSource::DrawImage,
+        };
+        Some(AutoCapture {
+            frame,
+            source,
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ScreenshotRequest {
+    pub source: ScreenshotSource,
+    /// Where to save the screenshot, the extension decides between PNG and EXR
+    pub path: PathBuf,
+}
+
+/// Screenshot whose copy has been recorded but not necessarily executed yet
+pub struct PendingScreenshot {
+    buffer: lv::AllocatedBuffer,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    path: PathBuf,
+}
+
+impl PendingScreenshot {
+    /// Record a copy of `image` into a host-visible buffer.
+    ///
+    /// `image` must be in `TRANSFER_SRC_OPTIMAL` layout. Call [`PendingScreenshot::save`] once the
+    /// GPU has finished executing `command_buffer`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+        command_buffer: vk::CommandBuffer,
+        image: vk::Image,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        path: PathBuf,
+    ) -> Result<PendingScreenshot, String> {
+        let bytes_per_pixel = bytes_per_pixel(format)
+            .ok_or_else(|| format!("Cannot take screenshots of {:?} images", format))?;
+        let buffer = lv::AllocatedBuffer::new(
+            extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * bytes_per_pixel,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            gpu_allocator::MemoryLocation::GpuToCpu,
+            device.clone(),
+            allocator,
+            Some("Screenshot readback"),
+        );
+
+        let region = vk::BufferImageCopy2 {
+            s_type: vk::StructureType::BUFFER_IMAGE_COPY_2,
+            buffer_offset: 0,
+            buffer_row_length: 0,
+            buffer_image_height: 0,
+            image_subresource: vk::ImageSubresourceLayers {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                mip_level: 0,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image_offset: vk::Offset3D::default(),
+            image_extent: vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            ..Default::default()
+        };
+        let copy_info = vk::CopyImageToBufferInfo2 {
+            s_type: vk::StructureType::COPY_IMAGE_TO_BUFFER_INFO_2,
+            src_image: image,
+            src_image_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            dst_buffer: buffer.get_handle(),
+            region_count: 1,
+            p_regions: &region,
+            ..Default::default()
+        };
+        // Make the copy visible to the host once the frame has finished
+        let memory_barrier = vk::MemoryBarrier2 {
+            s_type: vk::StructureType::MEMORY_BARRIER_2,
+            src_stage_mask: vk::PipelineStageFlags2::COPY,
+            src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+            dst_stage_mask: vk::PipelineStageFlags2::HOST,
+            dst_access_mask: vk::AccessFlags2::HOST_READ,
+            ..Default::default()
+        };
+        let dep_info = vk::DependencyInfo {
+            s_type: vk::StructureType::DEPENDENCY_INFO,
+            memory_barrier_count: 1,
+            p_memory_barriers: &memory_barrier,
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .handle
+                .cmd_copy_image_to_buffer2(command_buffer, &copy_info);
+            device
+                .handle
+                .cmd_pipeline_barrier2(command_buffer, &dep_info);
+        }
+
+        Ok(PendingScreenshot {
+            buffer,
+            extent,
+            format,
+            path,
+        })
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Convert the copied pixels and write them to disk
+    pub fn save(self) -> Result<(), String> {
+        let data = self
+            .buffer
+            .mapped_slice()
+            .ok_or("Screenshot buffer is not host visible")?;
+        let pixels = decode_linear_rgba(self.format, data)
+            .ok_or_else(|| format!("Cannot decode {:?} pixels", self.format))?;
+        if let Some(directory) = self.path.parent() {
+            if !directory.as_os_str().is_empty() {
+                std::fs::create_dir_all(directory).map_err(|err| err.to_string())?;
+            }
+        }
+        match ScreenshotFormat::from_path(&self.path) {
+            ScreenshotFormat::Png => write_png(&self.path, self.extent, &pixels),
+            ScreenshotFormat::Exr => write_exr(&self.path, self.extent, &pixels),
+        }
+    }
+}
+
+fn bytes_per_pixel(format: vk::Format) -> Option<vk::DeviceSize> {
+    match format {
+        vk::Format::R16G16B16A16_SFLOAT => Some(8),
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB => Some(4),
+        _ => None,
+    }
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Decode raw pixels into linear RGBA.
+///
+/// 8-bit formats are assumed to hold display-ready sRGB encoded values, whether or not the format
+/// itself is an `_SRGB` one, so saving them as PNG gives back exactly what was presented.
+fn decode_linear_rgba(format: vk::Format, data: &[u8]) -> Option<Vec<[f32; 4]>> {
+    match format {
+        vk::Format::R16G16B16A16_SFLOAT => Some(
+            data.chunks_exact(8)
+                .map(|pixel| {
+                    let mut rgba = [0.0f32; 4];
+                    for (channel, bytes) in rgba.iter_mut().zip(pixel.chunks_exact(2)) {
+                        *channel = exr::prelude::f16::from_le_bytes([bytes[0], bytes[1]]).to_f32();
+                    }
+                    rgba
+                })
+                .collect(),
+        ),
+        vk::Format::R8G8B8A8_UNORM
+        | vk::Format::R8G8B8A8_SRGB
+        | vk::Format::B8G8R8A8_UNORM
+        | vk::Format::B8G8R8A8_SRGB => {
+            let bgra = matches!(
+                format,
+                vk::Format::B8G8R8A8_UNORM | vk::Format::B8G8R8A8_SRGB
+            );
+            Some(
+                data.chunks_exact(4)
+                    .map(|pixel| {
+                        let (r, b) = if bgra {
+                            (pixel[2], pixel[0])
+                        } else {
+                            (pixel[0], pixel[2])
+                        };
+                        [
+                            srgb_to_linear(r as f32 / 255.0),
+                            srgb_to_linear(pixel[1] as f32 / 255.0),
+                            srgb_to_linear(b as f32 / 255.0),
+                            pixel[3] as f32 / 255.0,
+                        ]
+                    })
+                    .collect(),
+            )
+        }
+        _ => None,
+    }
+}
+
+fn write_png(path: &Path, extent: vk::Extent2D, pixels: &[[f32; 4]]) -> Result<(), String> {
+    let data: Vec<u8> = pixels
+        .iter()
+        .flat_map(|pixel| {
+            [
+                linear_to_srgb(pixel[0]),
+                linear_to_srgb(pixel[1]),
+                linear_to_srgb(pixel[2]),
+                pixel[3],
+            ]
+        })
+        .map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+        .collect();
+
+    let file = std::fs::File::create(path).map_err(|err| err.to_string())?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), extent.width, extent.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+    let mut writer = encoder.write_header().map_err(|err| err.to_string())?;
+    writer
+        .write_image_data(&data)
+        .map_err(|err| err.to_string())
+}
+
+fn write_exr(path: &Path, extent: vk::Extent2D, pixels: &[[f32; 4]]) -> Result<(), String> {
+    let width = extent.width as usize;
+    exr::prelude::write_rgba_file(path, width, extent.height as usize, |x, y| {
+        let pixel = pixels[y * width + x];
+        (pixel[0], pixel[1], pixel[2], pixel[3])
+    })
+    .map_err(|err| err.to_string())
+}
@@ -0,0 +1,477 @@
+use crate::camera::Camera;
+use crate::light::LightKind;
+use crate::utility::{self, parse_env};
+use crate::{lv, VulkanApp};
+use ash::vk::{self, TaggedStructure};
+use glam::{Mat4, Vec3, Vec3Swizzles};
+use std::ffi::CString;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+/// Most cascades a shadow map can have, must match `shaders/shadow.inc.glsl`
+pub const MAX_CASCADES: usize = 4;
+
+/// Environment variable overriding the number of cascades
+const CASCADES_ENV: &str = "LV_SHADOW_CASCADES";
+/// Environment variable overriding the width and height of each cascade in texels
+const RESOLUTION_ENV: &str = "LV_SHADOW_RESOLUTION";
+
+/// How far behind a cascade's bounds the light's near plane is pulled, so casters outside the
+/// camera frustum still shadow what is inside it
+const CASTER_MARGIN: f32 = 20.0;
+
+/// Format of the shadow map, one array layer per cascade
+const SHADOW_MAP_FORMAT: vk::Format = vk::Format::D32_SFLOAT;
+
+/// Cascaded shadow map configuration for the shadow casting directional light
+#[derive(Clone, Copy, Debug)]
+pub struct ShadowSettings {
+    /// Number of cascades, between 1 and [`MAX_CASCADES`]
+    pub cascade_count: u32,
+    /// Width and height of each cascade in texels
+    pub resolution: u32,
+    /// Blend between uniform (0) and logarithmic (1) cascade splits
+    pub split_lambda: f32,
+    /// Shadows end this far from the camera, or at its far plane if that is closer
+    pub max_distance: f32,
+    /// Depth bias of the shadow pipeline in units of the smallest depth difference
+    pub depth_bias_constant: f32,
+    /// Depth bias of the shadow pipeline scaled by the polygon's depth slope
+    pub depth_bias_slope: f32,
+    /// How many texels receivers are moved along their normal before the shadow map lookup
+    pub normal_offset: f32,
+    /// PCF kernel radius in texels, 0 for a single bilinear comparison
+    pub filter_radius: u32,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            cascade_count: 4,
+            resolution: 2048,
+            split_lambda: 0.75,
+            max_distance: 40.0,
+            depth_bias_constant: 1.25,
+            depth_bias_slope: 1.75,
+            normal_offset: 1.0,
+            filter_radius: 1,
+        }
+    }
+}
+
+impl ShadowSettings {
+    /// Default settings with the cascade count and resolution overridden from the environment
+    pub fn from_env() -> ShadowSettings {
+        let mut settings = ShadowSettings::default();
+        if let Some(cascade_count) = parse_env::<u32>(CASCADES_ENV) {
+            settings.cascade_count = cascade_count.clamp(1, MAX_CASCADES as u32);
+        }
+        if let Some(resolution) = parse_env::<u32>(RESOLUTION_ENV) {
+            settings.resolution = resolution.max(1);
+        }
+        settings
+    }
+}
+
+/// Shadow cascades as laid out for shaders, see `shaders/shadow.inc.glsl`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ShadowData {
+    pub view_projection: [[[f32; 4]; 4]; MAX_CASCADES],
+    /// View space depth at which each cascade ends
+    pub split_depths: [f32; MAX_CASCADES],
+    /// World space size of a texel in each cascade
+    pub texel_sizes: [f32; MAX_CASCADES],
+    /// 0 disables shadows
+    pub cascade_count: u32,
+    /// Index of the shadow map in the resource table's textures
+    pub shadow_map: u32,
+    pub normal_offset: f32,
+    pub filter_radius: u32,
+}
+
+/// View space depth at which each of `count` cascades between `near` and `far` ends, blending
+/// logarithmic and uniform splits by `lambda`
+pub fn cascade_splits(near: f32, far: f32, count: u32, lambda: f32) -> Vec<f32> {
+    (1..=count)
+        .map(|cascade| {
+            let fraction = cascade as f32 / count as f32;
+            let logarithmic = near * (far / near).powf(fraction);
+            let uniform = near + (far - near) * fraction;
+            lambda * logarithmic + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// Fit an orthographic projection along `light_direction` around each cascade's slice of the
+/// camera frustum
+pub fn compute_cascades(
+    camera: &Camera,
+    light_direction: Vec3,
+    settings: &ShadowSettings,
+    shadow_map: u32,
+) -> ShadowData {
+    let (near, far) = camera.projection.get_depth_range();
+    let shadow_far = far.min(settings.max_distance);
+    let splits = cascade_splits(
+        near,
+        shadow_far,
+        settings.cascade_count,
+        settings.split_lambda,
+    );
+
+    // Rays through the frustum's corners from the near to the far plane, in world space
+    let inverse_view_projection = (camera.projection_matrix() * camera.view_matrix()).inverse();
+    let corner_rays = [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)].map(|(x, y)| {
+        (
+            inverse_view_projection.project_point3(Vec3::new(x, y, 0.0)),
+            inverse_view_projection.project_point3(Vec3::new(x, y, 1.0)),
+        )
+    });
+    let up = if light_direction.normalize().y.abs() > 0.99 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+
+    let mut data = ShadowData {
+        cascade_count: settings.cascade_count,
+        shadow_map,
+        normal_offset: settings.normal_offset,
+        filter_radius: settings.filter_radius,
+        ..Default::default()
+    };
+    let mut cascade_near = near;
+    for (cascade, split) in splits.into_iter().enumerate() {
+        let mut corners = Vec::with_capacity(8);
+        for (near_corner, far_corner) in corner_rays {
+            for depth in [cascade_near, split] {
+                corners.push(near_corner.lerp(far_corner, (depth - near) / (far - near)));
+            }
+        }
+        // A bounding sphere keeps the projection the same size as the camera turns
+        let center = corners.iter().sum::<Vec3>() / corners.len() as f32;
+        let radius = corners
+            .iter()
+            .map(|corner| corner.distance(center))
+            .fold(0.0f32, f32::max);
+        let radius = (radius * 16.0).ceil() / 16.0;
+
+        let view = Mat4::look_to_rh(center, light_direction, up);
+        let mut projection = Mat4::orthographic_rh(
+            -radius,
+            radius,
+            -radius,
+            radius,
+            -radius - CASTER_MARGIN,
+            radius,
+        );
+        // Snap to whole texels so shadow edges do not shimmer as the camera moves
+        let half_resolution = settings.resolution as f32 * 0.5;
+        let origin = (projection * view).project_point3(Vec3::ZERO).xy() * half_resolution;
+        let snap = (origin.round() - origin) / half_resolution;
+        projection.w_axis.x += snap.x;
+        projection.w_axis.y += snap.y;
+
+        data.view_projection[cascade] = (projection * view).to_cols_array_2d();
+        data.split_depths[cascade] = split;
+        data.texel_sizes[cascade] = 2.0 * radius / settings.resolution as f32;
+        cascade_near = split;
+    }
+    data
+}
+
+/// Push constants of the shadow pipeline
+#[repr(C)]
+struct ShadowPushConstants {
+    vertices: vk::DeviceAddress,
+    instances: vk::DeviceAddress,
+    shadows: vk::DeviceAddress,
+    cascade: u32,
+}
+
+impl VulkanApp {
+    /// Depth array with a layer per cascade, sampled with depth comparison for PCF
+    pub fn create_shadow_map(
+        settings: &ShadowSettings,
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+    ) -> lv::Texture {
+        let mut image_ci = utility::init::image_create_info(
+            SHADOW_MAP_FORMAT,
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | vk::ImageUsageFlags::SAMPLED,
+            vk::Extent3D {
+                width: settings.resolution,
+                height: settings.resolution,
+                depth: 1,
+            },
+        );
+        image_ci.array_layers = settings.cascade_count;
+        let image = lv::AllocatedImage::new_array(
+            image_ci,
+            vk::ImageAspectFlags::DEPTH,
+            device.clone(),
+            allocator,
+            Some("Shadow map"),
+        );
+        // Linear filtering of the comparison results gives each PCF tap bilinear weights.
+        // Lookups outside the map count as lit
+        let sampler = Rc::new(lv::Sampler::new(
+            &vk::SamplerCreateInfo {
+                s_type: vk::StructureType::SAMPLER_CREATE_INFO,
+                mag_filter: vk::Filter::LINEAR,
+                min_filter: vk::Filter::LINEAR,
+                mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+                address_mode_u: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+                address_mode_v: vk::SamplerAddressMode::CLAMP_TO_BORDER,
+                address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                compare_enable: vk::TRUE,
+                compare_op: vk::CompareOp::LESS_OR_EQUAL,
+                border_color: vk::BorderColor::FLOAT_OPAQUE_WHITE,
+                ..Default::default()
+            },
+            device,
+            Some("Shadow sampler"),
+        ));
+        lv::Texture::from_image(image, sampler)
+    }
+
+    /// Fit the shadow cascades of the first directional light to the camera
+    pub fn update_shadows(&mut self, frame_slot: usize) {
+        let sun = self
+            .lights
+            .iter()
+            .find(|light| light.kind == LightKind::Directional);
+        let shadow_data = match sun {
+            Some(sun) => compute_cascades(
+                &self.camera,
+                sun.direction,
+                &self.shadow_settings,
+                self.shadow_map_index,
+            ),
+            None => ShadowData::default(),
+        };
+        self.frames[frame_slot].shadow_buffer.write(&shadow_data);
+    }
+
+    /// Render the depth of the draw list into each cascade of the shadow map
+    pub fn draw_shadows(&self) {
+        let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
+        let _label = lv::DebugLabel::new(&self.logical_device, command_buffer, "shadows");
+        let _scope = self.profiler.scope(command_buffer, "shadows");
+        let shadow_map = self
+            .gpu_resource_table
+            .get_texture(self.shadow_map_index as usize)
+            .as_ref()
+            .unwrap()
+            .get_image();
+        utility::transition_image(
+            &self.logical_device.handle,
+            command_buffer,
+            shadow_map.get_handle(),
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            vk::QUEUE_FAMILY_IGNORED,
+            vk::QUEUE_FAMILY_IGNORED,
+        );
+        let extent = vk::Extent2D {
+            width: self.shadow_settings.resolution,
+            height: self.shadow_settings.resolution,
+        };
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        let scissor = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        };
+
+        let frame = self.get_current_frame();
+        for cascade in 0..self.shadow_settings.cascade_count {
+            let depth_attachment = utility::init::depth_attachment_info(
+                shadow_map.get_layer_view(cascade),
+                vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            );
+            let rendering_info = vk::RenderingInfo {
+                s_type: vk::RenderingInfo::STRUCTURE_TYPE,
+                render_area: scissor,
+                layer_count: 1,
+                p_depth_attachment: &depth_attachment,
+                ..Default::default()
+            };
+            unsafe {
+                self.logical_device
+                    .handle
+                    .cmd_begin_rendering(command_buffer, &rendering_info);
+                self.logical_device.handle.cmd_bind_pipeline(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    self.shadow_pipeline.get_handle(),
+                );
+                self.logical_device
+                    .handle
+                    .cmd_set_viewport(command_buffer, 0, &[viewport]);
+                self.logical_device
+                    .handle
+                    .cmd_set_scissor(command_buffer, 0, &[scissor]);
+            }
+            for batch in self.draw_list.batches.iter() {
+                let mesh = &self.meshes[batch.mesh.0 as usize];
+                let push_constants = ShadowPushConstants {
+                    vertices: mesh.get_vertex_address(),
+                    instances: frame.instance_buffer.get_device_address(),
+                    shadows: frame.shadow_buffer.get_device_address(),
+                    cascade,
+                };
+                unsafe {
+                    self.logical_device.handle.cmd_push_constants(
+                        command_buffer,
+                        self.shadow_pipeline.get_layout(),
+                        vk::ShaderStageFlags::VERTEX,
+                        0,
+                        std::slice::from_raw_parts(
+                            &push_constants as *const _ as *const u8,
+                            std::mem::size_of::<ShadowPushConstants>(),
+                        ),
+                    );
+                    self.logical_device.handle.cmd_bind_index_buffer(
+                        command_buffer,
+                        mesh.get_index_buffer(),
+                        0,
+                        vk::IndexType::UINT32,
+                    );
+                    self.logical_device.handle.cmd_draw_indexed(
+                        command_buffer,
+                        mesh.get_index_count(),
+                        batch.instance_count,
+                        0,
+                        0,
+                        batch.first_instance,
+                    );
+                }
+            }
+            unsafe {
+                self.logical_device.handle.cmd_end_rendering(command_buffer);
+            }
+        }
+        utility::transition_image(
+            &self.logical_device.handle,
+            command_buffer,
+            shadow_map.get_handle(),
+            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::QUEUE_FAMILY_IGNORED,
+            vk::QUEUE_FAMILY_IGNORED,
+        );
+    }
+
+    /// Depth-only pipeline rendering shadow casters into one cascade, see `shaders/shadow.vert`
+    pub fn create_shadow_pipeline(
+        device: Arc<lv::Device>,
+        settings: &ShadowSettings,
+    ) -> Rc<lv::Pipeline> {
+        let vertex_shader = lv::Shader::new(
+            std::path::Path::new("./shaders/shadow.vert.spv"),
+            device.clone(),
+            None,
+        );
+        let shader_entry_point = CString::new("main").unwrap();
+        let vert_shader_stage_info = vk::PipelineShaderStageCreateInfo {
+            s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
+            stage: vk::ShaderStageFlags::VERTEX,
+            module: vertex_shader.handle,
+            p_name: shader_entry_point.as_ptr(),
+            ..Default::default()
+        };
+        let push_constant = vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: std::mem::size_of::<ShadowPushConstants>() as u32,
+        };
+        let builder = lv::PipelineBuilder::new()
+            .set_name("shadow")
+            .attach_push_constant(push_constant)
+            .dynamic_states(vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR])
+            .attach_shaders_stages(vec![vert_shader_stage_info])
+            .set_input_topology(vk::PrimitiveTopology::TRIANGLE_LIST)
+            .set_polygon_mode(vk::PolygonMode::FILL)
+            .set_cull_mode(vk::CullModeFlags::NONE, vk::FrontFace::CLOCKWISE)
+            .set_depth_bias(settings.depth_bias_constant, settings.depth_bias_slope)
+            .set_multisampling_none()
+            .set_depth_format(SHADOW_MAP_FORMAT)
+            .enable_depthtest(true, vk::CompareOp::LESS);
+        Rc::new(lv::Pipeline::from_builder(builder, device))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::Projection;
+
+    #[test]
+    fn splits_increase_and_end_at_far() {
+        for lambda in [0.0, 0.5, 1.0] {
+            let splits = cascade_splits(0.1, 40.0, 4, lambda);
+            assert_eq!(splits.len(), 4);
+            assert!(splits.windows(2).all(|pair| pair[0] < pair[1]));
+            assert!((splits[3] - 40.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn lambda_blends_uniform_and_logarithmic_splits() {
+        let uniform = cascade_splits(1.0, 101.0, 2, 0.0);
+        assert!((uniform[0] - 51.0).abs() < 1e-4);
+        let logarithmic = cascade_splits(1.0, 100.0, 2, 1.0);
+        assert!((logarithmic[0] - 10.0).abs() < 1e-4);
+    }
+
+    fn test_camera() -> Camera {
+        Camera::new(
+            Vec3::new(0.0, 2.0, 5.0),
+            Projection::Perspective {
+                fov_y: 60f32.to_radians(),
+                near: 0.1,
+                far: 100.0,
+            },
+            16.0 / 9.0,
+        )
+    }
+
+    #[test]
+    fn cascades_end_at_max_distance() {
+        let settings = ShadowSettings::default();
+        let data = compute_cascades(&test_camera(), Vec3::new(-1.0, -2.0, -0.5), &settings, 7);
+        assert_eq!(data.cascade_count, settings.cascade_count);
+        assert_eq!(data.shadow_map, 7);
+        let count = settings.cascade_count as usize;
+        assert!((data.split_depths[count - 1] - settings.max_distance).abs() < 1e-3);
+        assert!(data.texel_sizes[..count]
+            .windows(2)
+            .all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn cascades_cover_their_slice_of_the_frustum() {
+        let camera = test_camera();
+        let settings = ShadowSettings::default();
+        let data = compute_cascades(&camera, Vec3::new(-1.0, -2.0, -0.5), &settings, 0);
+        let mut cascade_near = 0.1;
+        for cascade in 0..settings.cascade_count as usize {
+            let split = data.split_depths[cascade];
+            let depth = (cascade_near + split) * 0.5;
+            let point = camera.position + camera.forward() * depth;
+            let clip =
+                Mat4::from_cols_array_2d(&data.view_projection[cascade]).project_point3(point);
+            assert!(clip.x.abs() <= 1.0 && clip.y.abs() <= 1.0, "{:?}", clip);
+            assert!((0.0..=1.0).contains(&clip.z), "{:?}", clip);
+            cascade_near = split;
+        }
+    }
+}
@@ -0,0 +1,366 @@
+use crate::{lv, utility, VulkanApp};
+use ash::vk;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+/// Number of bins in the luminance histogram, must match `shaders/exposure.inc.glsl`
+pub const HISTOGRAM_BINS: u32 = 256;
+
+/// Environment variable selecting the operator by name, e.g. `agx`
+const OPERATOR_ENV: &str = "LV_TONEMAP";
+/// Environment variable enabling auto-exposure when set to `1`
+const AUTO_EXPOSURE_ENV: &str = "LV_AUTO_EXPOSURE";
+
+/// Curve compressing HDR scene values into the displayable range
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TonemapOperator {
+    /// Fit of the ACES filmic curve, saturated and contrasty
+    Aces,
+    /// AgX, desaturates bright colors towards white
+    Agx,
+    /// Simple `x / (1 + x)` per channel
+    Reinhard,
+}
+
+impl TonemapOperator {
+    pub const ALL: [TonemapOperator; 3] = [
+        TonemapOperator::Aces,
+        TonemapOperator::Agx,
+        TonemapOperator::Reinhard,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TonemapOperator::Aces => "aces",
+            TonemapOperator::Agx => "agx",
+            TonemapOperator::Reinhard => "reinhard",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<TonemapOperator> {
+        TonemapOperator::ALL
+            .iter()
+            .copied()
+            .find(|operator| operator.name() == name)
+    }
+
+    /// The operator after this one, wrapping around
+    pub fn next(&self) -> TonemapOperator {
+        let index = TonemapOperator::ALL
+            .iter()
+            .position(|operator| operator == self)
+            .unwrap();
+        TonemapOperator::ALL[(index + 1) % TonemapOperator::ALL.len()]
+    }
+
+    /// Value the tonemap shader selects the operator by, see `shaders/tonemap.comp`
+    pub fn get_index(&self) -> u32 {
+        match self {
+            TonemapOperator::Aces => 0,
+            TonemapOperator::Agx => 1,
+            TonemapOperator::Reinhard => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TonemapSettings {
+    pub operator: TonemapOperator,
+    /// Exposure compensation in stops, applied on top of auto-exposure
+    pub exposure: f32,
+    /// Expose for the average scene luminance from a histogram of the draw image
+    pub auto_exposure: bool,
+    /// Log2 luminance of the darkest histogram bin
+    pub min_log_luminance: f32,
+    /// Log2 luminance range the histogram covers
+    pub log_luminance_range: f32,
+    /// How fast auto-exposure adapts, higher is faster
+    pub adaptation_rate: f32,
+}
+
+impl Default for TonemapSettings {
+    fn default() -> Self {
+        TonemapSettings {
+            operator: TonemapOperator::Aces,
+            exposure: 0.0,
+            auto_exposure: false,
+            min_log_luminance: -10.0,
+            log_luminance_range: 14.0,
+            adaptation_rate: 1.5,
+        }
+    }
+}
+
+impl TonemapSettings {
+    /// Default settings with the operator and auto-exposure overridden from the environment
+    pub fn from_env() -> TonemapSettings {
+        let mut settings = TonemapSettings::default();
+        if let Ok(name) = std::env::var(OPERATOR_ENV) {
+            match TonemapOperator::from_name(name.trim()) {
+                Some(operator) => settings.operator = operator,
+                None => log::warn!("Ignoring unknown {}={:?}", OPERATOR_ENV, name),
+            }
+        }
+        if let Ok(value) = std::env::var(AUTO_EXPOSURE_ENV) {
+            settings.auto_exposure = value.trim() == "1";
+        }
+        settings
+    }
+
+    /// Exposure compensation as a linear factor
+    pub fn get_exposure_scale(&self) -> f32 {
+        self.exposure.exp2()
+    }
+
+    /// Fraction of the way to the current average luminance auto-exposure adapts over
+    /// `delta_time` seconds
+    pub fn get_adaptation(&self, delta_time: f32) -> f32 {
+        1.0 - (-delta_time * self.adaptation_rate).exp()
+    }
+}
+
+/// Push constants of the luminance histogram pass
+#[repr(C)]
+struct HistogramPushConstants {
+    histogram: vk::DeviceAddress,
+    image: u32,
+    min_log_luminance: f32,
+    inverse_log_luminance_range: f32,
+}
+
+/// Push constants of the pass averaging the histogram into the adapted luminance
+#[repr(C)]
+struct ExposurePushConstants {
+    histogram: vk::DeviceAddress,
+    exposure: vk::DeviceAddress,
+    pixel_count: u32,
+    min_log_luminance: f32,
+    log_luminance_range: f32,
+    adaptation: f32,
+}
+
+/// Push constants of the tonemapping pass
+#[repr(C)]
+struct TonemapPushConstants {
+    exposure: vk::DeviceAddress,
+    hdr_image: u32,
+    ldr_image: u32,
+    operator: u32,
+    exposure_scale: f32,
+    auto_exposure: u32,
+    output_encoding: u32,
+    paper_white: f32,
+}
+
+impl VulkanApp {
+    /// Compute pipelines of the luminance histogram, the exposure it averages to and the
+    /// tonemapping that applies it, see `shaders/tonemap.comp`
+    pub fn create_tonemap_pipelines(
+        device: Arc<lv::Device>,
+        resource_layout: vk::DescriptorSetLayout,
+    ) -> (
+        Rc<lv::ComputePipeline>,
+        Rc<lv::ComputePipeline>,
+        Rc<lv::ComputePipeline>,
+    ) {
+        let histogram_pipeline = Rc::new(VulkanApp::create_compute_pipeline(
+            device.clone(),
+            std::path::Path::new("./shaders/luminance_histogram.comp.spv"),
+            "luminance histogram",
+            vec![resource_layout],
+            std::mem::size_of::<HistogramPushConstants>() as u32,
+        ));
+        let exposure_pipeline = Rc::new(VulkanApp::create_compute_pipeline(
+            device.clone(),
+            std::path::Path::new("./shaders/luminance_average.comp.spv"),
+            "exposure",
+            Vec::new(),
+            std::mem::size_of::<ExposurePushConstants>() as u32,
+        ));
+        let tonemap_pipeline = Rc::new(VulkanApp::create_compute_pipeline(
+            device,
+            std::path::Path::new("./shaders/tonemap.comp.spv"),
+            "tonemap",
+            vec![resource_layout],
+            std::mem::size_of::<TonemapPushConstants>() as u32,
+        ));
+        (histogram_pipeline, exposure_pipeline, tonemap_pipeline)
+    }
+
+    /// Cleared luminance histogram and the adapted luminance, starting out at 1
+    pub fn create_exposure_buffers(
+        immediate_submit: &lv::ImmediateSubmit,
+        device: Arc<lv::Device>,
+        allocator: Arc<Mutex<gpu_allocator::vulkan::Allocator>>,
+    ) -> (lv::AllocatedBuffer, lv::AllocatedBuffer) {
+        let usage = vk::BufferUsageFlags::STORAGE_BUFFER
+            | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS
+            | vk::BufferUsageFlags::TRANSFER_DST;
+        let histogram_buffer = lv::AllocatedBuffer::new(
+            (HISTOGRAM_BINS as usize * std::mem::size_of::<u32>()) as vk::DeviceSize,
+            usage,
+            gpu_allocator::MemoryLocation::GpuOnly,
+            device.clone(),
+            allocator.clone(),
+            Some("Luminance histogram"),
+        );
+        let exposure_buffer = lv::AllocatedBuffer::new(
+            std::mem::size_of::<f32>() as vk::DeviceSize,
+            usage,
+            gpu_allocator::MemoryLocation::GpuOnly,
+            device.clone(),
+            allocator,
+            Some("Exposure"),
+        );
+        immediate_submit.submit(|command_buffer| unsafe {
+            device.handle.cmd_fill_buffer(
+                command_buffer,
+                histogram_buffer.get_handle(),
+                0,
+                vk::WHOLE_SIZE,
+                0,
+            );
+            device.handle.cmd_fill_buffer(
+                command_buffer,
+                exposure_buffer.get_handle(),
+                0,
+                vk::WHOLE_SIZE,
+                1.0f32.to_bits(),
+            );
+        });
+        (histogram_buffer, exposure_buffer)
+    }
+
+    /// Adapt the exposure towards the average luminance of the draw image
+    pub fn compute_exposure(&self) {
+        let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
+        let _label = lv::DebugLabel::new(&self.logical_device, command_buffer, "auto exposure");
+        let _scope = self.profiler.scope(command_buffer, "auto exposure");
+        let settings = &self.tonemap_settings;
+        // Also orders this frame's histogram after the previous frame's reset of it
+        self.compute_barrier();
+        let histogram_push_constants = HistogramPushConstants {
+            histogram: self.histogram_buffer.get_device_address(),
+            image: self.targets.draw_image_index,
+            min_log_luminance: settings.min_log_luminance,
+            inverse_log_luminance_range: 1.0 / settings.log_luminance_range,
+        };
+        self.dispatch_compute(
+            &self.histogram_pipeline,
+            &histogram_push_constants,
+            [
+                self.draw_extent.width.div_ceil(16),
+                self.draw_extent.height.div_ceil(16),
+                1,
+            ],
+            true,
+        );
+        self.compute_barrier();
+        let exposure_push_constants = ExposurePushConstants {
+            histogram: self.histogram_buffer.get_device_address(),
+            exposure: self.exposure_buffer.get_device_address(),
+            pixel_count: self.draw_extent.width * self.draw_extent.height,
+            min_log_luminance: settings.min_log_luminance,
+            log_luminance_range: settings.log_luminance_range,
+            adaptation: settings.get_adaptation(self.time_step),
+        };
+        self.dispatch_compute(
+            &self.exposure_pipeline,
+            &exposure_push_constants,
+            [1, 1, 1],
+            false,
+        );
+        self.compute_barrier();
+    }
+
+    /// Tonemap the HDR draw image into the LDR image that gets presented. The draw image must be
+    /// in `GENERAL`, the LDR image is left in `GENERAL`
+    pub fn tonemap(&self) {
+        if self.tonemap_settings.auto_exposure {
+            self.compute_exposure();
+        }
+        let command_buffer = self.get_current_frame().main_command_buffer.get_handle();
+        let _label = lv::DebugLabel::new(&self.logical_device, command_buffer, "tonemap");
+        let _scope = self.profiler.scope(command_buffer, "tonemap");
+        let ldr_image = self
+            .gpu_resource_table
+            .get_texture(self.targets.ldr_texture_index as usize)
+            .as_ref()
+            .unwrap()
+            .get_image();
+        utility::transition_image(
+            &self.logical_device.handle,
+            command_buffer,
+            ldr_image.get_handle(),
+            vk::ImageLayout::UNDEFINED,
+            vk::ImageLayout::GENERAL,
+            vk::QUEUE_FAMILY_IGNORED,
+            vk::QUEUE_FAMILY_IGNORED,
+        );
+        let settings = &self.tonemap_settings;
+        let push_constants = TonemapPushConstants {
+            exposure: self.exposure_buffer.get_device_address(),
+            hdr_image: self.targets.draw_image_index,
+            ldr_image: self.targets.ldr_view_index,
+            operator: settings.operator.get_index(),
+            exposure_scale: settings.get_exposure_scale(),
+            auto_exposure: settings.auto_exposure as u32,
+            output_encoding: self.output_encoding.get_index(),
+            paper_white: self.display_settings.paper_white,
+        };
+        self.dispatch_compute(
+            &self.tonemap_pipeline,
+            &push_constants,
+            [
+                self.draw_extent.width.div_ceil(16),
+                self.draw_extent.height.div_ceil(16),
+                1,
+            ],
+            true,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operator_names_round_trip() {
+        for operator in TonemapOperator::ALL {
+            assert_eq!(TonemapOperator::from_name(operator.name()), Some(operator));
+        }
+        assert_eq!(TonemapOperator::from_name("filmic"), None);
+    }
+
+    #[test]
+    fn next_visits_every_operator() {
+        let mut operator = TonemapOperator::Aces;
+        let mut indices = Vec::new();
+        for _ in TonemapOperator::ALL {
+            indices.push(operator.get_index());
+            operator = operator.next();
+        }
+        assert_eq!(operator, TonemapOperator::Aces);
+        indices.sort();
+        assert_eq!(indices, [0, 1, 2]);
+    }
+
+    #[test]
+    fn exposure_is_in_stops() {
+        let settings = TonemapSettings {
+            exposure: -2.0,
+            ..Default::default()
+        };
+        assert_eq!(settings.get_exposure_scale(), 0.25);
+    }
+
+    #[test]
+    fn adaptation_approaches_one() {
+        let settings = TonemapSettings::default();
+        assert_eq!(settings.get_adaptation(0.0), 0.0);
+        let short = settings.get_adaptation(1.0 / 60.0);
+        let long = settings.get_adaptation(10.0);
+        assert!(0.0 < short && short < long && long < 1.0);
+    }
+}
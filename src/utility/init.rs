@@ -34,6 +34,27 @@ pub fn attachment_info(
     color_attachment
 }
 
+/// Depth attachment cleared to the far plane at 1.0
+pub fn depth_attachment_info(
+    image_view: vk::ImageView,
+    layout: vk::ImageLayout,
+) -> vk::RenderingAttachmentInfo {
+    vk::RenderingAttachmentInfo {
+        s_type: vk::StructureType::RENDERING_ATTACHMENT_INFO,
+        image_view,
+        image_layout: layout,
+        load_op: vk::AttachmentLoadOp::CLEAR,
+        store_op: vk::AttachmentStoreOp::STORE,
+        clear_value: vk::ClearValue {
+            depth_stencil: vk::ClearDepthStencilValue {
+                depth: 1.0,
+                stencil: 0,
+            },
+        },
+        ..Default::default()
+    }
+}
+
 pub fn image_create_info(
     format: vk::Format,
     usage_flags: vk::ImageUsageFlags,
@@ -5,6 +5,30 @@ use ash::vk;
 pub mod init;
 pub mod tools;
 
+/// Aspect of an image going from `old_layout` to `new_layout`, depth if either is a depth layout
+fn image_aspect(old_layout: vk::ImageLayout, new_layout: vk::ImageLayout) -> vk::ImageAspectFlags {
+    let is_depth = |layout| {
+        matches!(
+            layout,
+            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL
+                | vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL
+                | vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+                | vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+        )
+    };
+    if is_depth(old_layout) || is_depth(new_layout) {
+        vk::ImageAspectFlags::DEPTH
+    } else {
+        vk::ImageAspectFlags::COLOR
+    }
+}
+
+/// Record a layout transition for `image`.
+///
+/// When `src_queue_family_index` and `dst_queue_family_index` differ the barrier also transfers
+/// queue family ownership of the image. An ownership transfer has to be recorded twice with
+/// identical arguments: once on a queue of the source family (release) and once on a queue of the
+/// destination family (acquire), see [`transfer_image_ownership`].
 pub fn transition_image(
     device: &ash::Device,
     command_buffer: vk::CommandBuffer,
@@ -14,6 +38,13 @@ pub fn transition_image(
     src_queue_family_index: u32,
     dst_queue_family_index: u32,
 ) {
+    // A "transfer" to the same family is just a regular barrier
+    let (src_queue_family_index, dst_queue_family_index) =
+        if src_queue_family_index == dst_queue_family_index {
+            (vk::QUEUE_FAMILY_IGNORED, vk::QUEUE_FAMILY_IGNORED)
+        } else {
+            (src_queue_family_index, dst_queue_family_index)
+        };
     let image_barrier = vk::ImageMemoryBarrier2 {
         s_type: vk::StructureType::IMAGE_MEMORY_BARRIER_2,
 
@@ -30,7 +61,7 @@ pub fn transition_image(
         new_layout,
         image,
 
-        subresource_range: init::image_subresource_range(vk::ImageAspectFlags::COLOR),
+        subresource_range: init::image_subresource_range(image_aspect(old_layout, new_layout)),
 
         ..Default::default()
     };
@@ -45,6 +76,44 @@ pub fn transition_image(
     }
 }
 
+/// Move ownership of `image` from the queue family of `src_queue` to that of `dst_queue`.
+///
+/// The release barrier is recorded into `release_command_buffer`, which must be submitted to
+/// `src_queue`, and the acquire barrier into `acquire_command_buffer`, which must be submitted to
+/// `dst_queue` after the release submission (e.g. by waiting on a semaphore it signals).
+#[allow(clippy::too_many_arguments)]
+pub fn transfer_image_ownership(
+    device: &ash::Device,
+    release_command_buffer: vk::CommandBuffer,
+    acquire_command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    old_layout: vk::ImageLayout,
+    new_layout: vk::ImageLayout,
+    src_queue: &lv::Queue,
+    dst_queue: &lv::Queue,
+) {
+    transition_image(
+        device,
+        release_command_buffer,
+        image,
+        old_layout,
+        new_layout,
+        src_queue.index,
+        dst_queue.index,
+    );
+    if src_queue.index != dst_queue.index {
+        transition_image(
+            device,
+            acquire_command_buffer,
+            image,
+            old_layout,
+            new_layout,
+            src_queue.index,
+            dst_queue.index,
+        );
+    }
+}
+
 pub fn copy_image_to_image(
     command_buffer: vk::CommandBuffer,
     device: &lv::Device,
@@ -52,6 +121,7 @@ pub fn copy_image_to_image(
     destination: vk::Image,
     src_size: vk::Extent2D,
     dst_size: vk::Extent2D,
+    filter: vk::Filter,
 ) {
     let blit_region = vk::ImageBlit2 {
         s_type: vk::StructureType::IMAGE_BLIT_2,
@@ -99,7 +169,7 @@ pub fn copy_image_to_image(
         dst_image_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
         src_image: source,
         src_image_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
-        filter: vk::Filter::LINEAR,
+        filter,
         region_count: 1,
         p_regions: &blit_region,
         ..Default::default()
@@ -109,3 +179,56 @@ pub fn copy_image_to_image(
             .cmd_blit_image2(command_buffer, &blit_info)
     };
 }
+
+/// Record a global memory barrier making `src_access_mask` writes in `src_stage_mask` available
+/// to `dst_access_mask` accesses in `dst_stage_mask`
+pub fn memory_barrier(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    src_stage_mask: vk::PipelineStageFlags2,
+    src_access_mask: vk::AccessFlags2,
+    dst_stage_mask: vk::PipelineStageFlags2,
+    dst_access_mask: vk::AccessFlags2,
+) {
+    let memory_barrier = vk::MemoryBarrier2 {
+        s_type: vk::StructureType::MEMORY_BARRIER_2,
+        src_stage_mask,
+        src_access_mask,
+        dst_stage_mask,
+        dst_access_mask,
+        ..Default::default()
+    };
+    let dependency_info = vk::DependencyInfo {
+        s_type: vk::StructureType::DEPENDENCY_INFO,
+        memory_barrier_count: 1,
+        p_memory_barriers: &memory_barrier,
+        ..Default::default()
+    };
+    unsafe {
+        device.cmd_pipeline_barrier2(command_buffer, &dependency_info);
+    }
+}
+
+/// Whether images of `format` are sRGB encoded and decoded by the hardware on access
+pub fn is_srgb_format(format: vk::Format) -> bool {
+    matches!(
+        format,
+        vk::Format::R8_SRGB
+            | vk::Format::R8G8_SRGB
+            | vk::Format::R8G8B8_SRGB
+            | vk::Format::B8G8R8_SRGB
+            | vk::Format::R8G8B8A8_SRGB
+            | vk::Format::B8G8R8A8_SRGB
+            | vk::Format::A8B8G8R8_SRGB_PACK32
+    )
+}
+
+/// Parse environment variable `name`, warning about and ignoring values that do not parse
+pub fn parse_env<T: std::str::FromStr>(name: &str) -> Option<T> {
+    let value = std::env::var(name).ok()?;
+    let parsed = value.trim().parse::<T>().ok();
+    if parsed.is_none() {
+        log::warn!("Ignoring invalid {}={:?}", name, value);
+    }
+    parsed
+}
@@ -0,0 +1,282 @@
+//! Golden-image regression tests.
+//!
+//! Each test runs the application with only some passes enabled, captures the draw or swapchain
+//! image and compares it against a reference image in `tests/golden/`. Captures render to a
+//! `VK_EXT_headless_surface`, so no display is needed, but they do need a Vulkan driver with that
+//! extension (e.g. lavapipe) and so are ignored by default:
+//!
+//! ```sh
+//! cargo test --test golden -- --ignored
+//! ```
+//!
+//! The application fails the run if the validation layer reports any error while rendering, so
+//! install the Khronos validation layer to have the tests check for them too.
+//!
+//! Set `LV_BLESS=1` to write the rendered images as the new references instead of comparing.
+//! References have to be blessed again in any change that alters what these passes render, on
+//! lavapipe so they match what CI renders:
+//!
+//! ```sh
+//! LV_BLESS=1 cargo test --test golden -- --ignored
+//! ```
+//!
+//! Then check the updated images in `tests/golden/` look right and commit them with the change.
+//! Scenes are kept minimal, the geometry pass draws a single unlit triangle without lights and
+//! antialiasing, so only changes to the passes under test need a new blessing.
+//! On a mismatch the rendered image and a diff image are written next to each other in the
+//! target directory and their paths are included in the failure message.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Device the scenes are rendered on, overridable with `LV_GOLDEN_GPU`
+const DEFAULT_GPU: &str = "llvmpipe";
+/// Frame to capture, late enough that nothing depends on the first frame being special
+const CAPTURE_FRAME: u64 = 3;
+/// CIE76 color difference below which two pixels are considered identical, roughly a just
+/// noticeable difference
+const PIXEL_TOLERANCE: f32 = 2.3;
+/// Fraction of pixels allowed to exceed [`PIXEL_TOLERANCE`], to absorb rasterization differences
+/// along edges
+const MAX_DIFFERING_FRACTION: f64 = 0.001;
+
+struct Image {
+    width: u32,
+    height: u32,
+    /// 8-bit sRGB RGBA
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    fn load(path: &Path) -> Image {
+        let file = std::fs::File::open(path)
+            .unwrap_or_else(|err| panic!("Failed to open {}: {}", path.display(), err));
+        let mut decoder = png::Decoder::new(std::io::BufReader::new(file));
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buffer).unwrap();
+        buffer.truncate(info.buffer_size());
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => buffer,
+            png::ColorType::Rgb => buffer
+                .chunks_exact(3)
+                .flat_map(|pixel| [pixel[0], pixel[1], pixel[2], 255])
+                .collect(),
+            color_type => panic!(
+                "Unsupported color type {:?} in {}",
+                color_type,
+                path.display()
+            ),
+        };
+        Image {
+            width: info.width,
+            height: info.height,
+            pixels,
+        }
+    }
+
+    fn save(&self, path: &Path) {
+        let file = std::fs::File::create(path).unwrap();
+        let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&self.pixels).unwrap();
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert an sRGB pixel to CIE L*a*b* with a D65 white point
+fn srgb_to_lab(pixel: &[u8]) -> [f32; 3] {
+    let (r, g, b) = (
+        srgb_to_linear(pixel[0]),
+        srgb_to_linear(pixel[1]),
+        srgb_to_linear(pixel[2]),
+    );
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f32| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// CIE76 color difference between two sRGB pixels, ignoring alpha
+fn delta_e(a: &[u8], b: &[u8]) -> f32 {
+    let (a, b) = (srgb_to_lab(a), srgb_to_lab(b));
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2) + (a[2] - b[2]).powi(2)).sqrt()
+}
+
+/// Compare `actual` against `reference`, returning a diff image if they differ too much
+fn compare(reference: &Image, actual: &Image) -> Result<(), (String, Image)> {
+    if reference.width != actual.width || reference.height != actual.height {
+        return Err((
+            format!(
+                "size mismatch: reference is {}x{}, rendered image is {}x{}",
+                reference.width, reference.height, actual.width, actual.height
+            ),
+            Image {
+                width: actual.width,
+                height: actual.height,
+                pixels: actual.pixels.clone(),
+            },
+        ));
+    }
+
+    let mut differing = 0usize;
+    let mut max_delta = 0.0f32;
+    let mut diff_pixels = Vec::with_capacity(reference.pixels.len());
+    for (expected, pixel) in reference
+        .pixels
+        .chunks_exact(4)
+        .zip(actual.pixels.chunks_exact(4))
+    {
+        // Alpha has no perceptual scale, so map it onto the same 0-100 range as lightness
+        let alpha_delta = (expected[3] as f32 - pixel[3] as f32).abs() * 100.0 / 255.0;
+        let delta = delta_e(expected, pixel).max(alpha_delta);
+        max_delta = max_delta.max(delta);
+        if delta > PIXEL_TOLERANCE {
+            differing += 1;
+            // Differences in red, scaled so 10 or more is fully saturated
+            let intensity = (delta / 10.0).min(1.0);
+            diff_pixels.extend_from_slice(&[(128.0 + 127.0 * intensity) as u8, 0, 0, 255]);
+        } else {
+            // Dimmed grayscale of the reference for context
+            let luma = (expected[0] as u32 * 3 + expected[1] as u32 * 6 + expected[2] as u32) / 40;
+            diff_pixels.extend_from_slice(&[luma as u8, luma as u8, luma as u8, 255]);
+        }
+    }
+
+    let pixel_count = (reference.width * reference.height) as f64;
+    if differing as f64 > pixel_count * MAX_DIFFERING_FRACTION {
+        return Err((
+            format!(
+                "{} of {} pixels ({:.3}%) differ by more than {} (max difference {:.2})",
+                differing,
+                pixel_count,
+                differing as f64 * 100.0 / pixel_count,
+                PIXEL_TOLERANCE,
+                max_delta
+            ),
+            Image {
+                width: reference.width,
+                height: reference.height,
+                pixels: diff_pixels,
+            },
+        ));
+    }
+    Ok(())
+}
+
+fn output_directory() -> PathBuf {
+    let directory = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    std::fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+/// Environment every scene is rendered with, so changing the defaults does not change them
+const FIXED_SETTINGS: [(&str, &str); 4] = [
+    ("LV_SCENE", "triangle"),
+    ("LV_AA", "none"),
+    ("LV_MSAA", "1"),
+    ("LV_RENDER_SCALE", "1"),
+];
+
+/// Render `passes` with the application and return the path of the captured `source` image,
+/// either `draw` or `swapchain`
+fn render(name: &str, passes: &str, source: &str) -> PathBuf {
+    let output = output_directory().join(format!("{}.png", name));
+    let _ = std::fs::remove_file(&output);
+    let gpu = std::env::var("LV_GOLDEN_GPU").unwrap_or_else(|_| DEFAULT_GPU.to_string());
+    let status = Command::new(env!("CARGO_BIN_EXE_learn_vulkan"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("LV_GPU", gpu)
+        .envs(FIXED_SETTINGS)
+        .env("LV_PASSES", passes)
+        .env("LV_CAPTURE", &output)
+        .env("LV_CAPTURE_FRAME", CAPTURE_FRAME.to_string())
+        .env("LV_CAPTURE_SOURCE", source)
+        .status()
+        .expect("Failed to launch the application");
+    assert!(
+        status.success(),
+        "Rendering {} failed with {}",
+        name,
+        status
+    );
+    assert!(
+        output.exists(),
+        "Rendering {} did not produce {}",
+        name,
+        output.display()
+    );
+    output
+}
+
+fn check_golden(name: &str, passes: &str, source: &str) {
+    let rendered = render(name, passes, source);
+    let reference = Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join("golden")
+        .join(format!("{}.png", name));
+
+    if std::env::var_os("LV_BLESS").is_some() {
+        std::fs::create_dir_all(reference.parent().unwrap()).unwrap();
+        std::fs::copy(&rendered, &reference).unwrap();
+        println!("Updated {}", reference.display());
+        return;
+    }
+    assert!(
+        reference.exists(),
+        "Missing reference image {}, run with LV_BLESS=1 to create it",
+        reference.display()
+    );
+
+    if let Err((message, diff)) = compare(&Image::load(&reference), &Image::load(&rendered)) {
+        let diff_path = output_directory().join(format!("{}.diff.png", name));
+        diff.save(&diff_path);
+        panic!(
+            "{} does not match its reference: {}\n  reference: {}\n  rendered:  {}\n  diff:      {}",
+            name,
+            message,
+            reference.display(),
+            rendered.display(),
+            diff_path.display()
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs a Vulkan driver, see the module documentation"]
+fn gradient() {
+    check_golden("gradient", "gradient", "draw");
+}
+
+/// The geometry pass drawing a vertex-colored triangle over a cleared draw image
+#[test]
+#[ignore = "needs a Vulkan driver, see the module documentation"]
+fn triangle() {
+    check_golden("triangle", "geometry", "draw");
+}
+
+/// The gradient after tonemapping, output encoding and the overlay, as it is presented
+#[test]
+#[ignore = "needs a Vulkan driver, see the module documentation"]
+fn presented() {
+    check_golden("presented", "gradient", "swapchain");
+}